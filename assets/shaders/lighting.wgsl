@@ -0,0 +1,93 @@
+// Light over a level, multiplied with what is drawn under it (see lighting.rs)
+// Every pixel gets the ambient light plus each point light that reaches it
+// without a solid cell in between
+
+#import bevy_sprite::mesh2d_types
+#import bevy_sprite::mesh2d_view_bindings
+
+struct Light {
+    color: vec4<f32>,
+    position: vec2<f32>,
+    radius: f32,
+    intensity: f32,
+};
+
+// MAX_LIGHTS in lighting.rs
+struct Lighting {
+    ambient: vec4<f32>,
+    level_size: vec2<f32>,
+    cell_size: f32,
+    light_count: u32,
+    lights: array<Light, 16>,
+};
+
+@group(1) @binding(0)
+var<uniform> lighting: Lighting;
+@group(1) @binding(1)
+var occluder_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var occluder_sampler: sampler;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+// Longest a shadow ray gets, in half cells
+let MAX_STEPS: i32 = 64;
+
+fn cell_of(position: vec2<f32>) -> vec2<f32> {
+    return floor(position / lighting.cell_size);
+}
+
+// Positions are in pixels from the bottom left of the level, the texture
+// starts from the top row
+fn blocks_light(position: vec2<f32>) -> bool {
+    let uv = vec2<f32>(
+        position.x / lighting.level_size.x,
+        1.0 - position.y / lighting.level_size.y
+    );
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+        return false;
+    }
+    return textureSampleLevel(occluder_texture, occluder_sampler, uv, 0.0).r > 0.5;
+}
+
+// Walls light up on their own face, and lights inside a wall still shine out,
+// so the cells at both ends never block
+fn in_shadow(position: vec2<f32>, light: vec2<f32>) -> bool {
+    let to_light = light - position;
+    let step_length = lighting.cell_size * 0.5;
+    let steps = min(i32(length(to_light) / step_length), MAX_STEPS);
+    let start_cell = cell_of(position);
+    let light_cell = cell_of(light);
+
+    for (var i = 1; i < steps; i = i + 1) {
+        let on_ray = position + to_light * (f32(i) / f32(steps));
+        let cell = cell_of(on_ray);
+        if (all(cell == start_cell) || all(cell == light_cell)) {
+            continue;
+        }
+        if (blocks_light(on_ray)) {
+            return true;
+        }
+    }
+    return false;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    // The quad's uv starts from the top left
+    let position = vec2<f32>(in.uv.x, 1.0 - in.uv.y) * lighting.level_size;
+
+    var light = lighting.ambient.rgb;
+    for (var i = 0u; i < lighting.light_count; i = i + 1u) {
+        let point = lighting.lights[i];
+        let point_distance = length(point.position - position);
+        if (point_distance >= point.radius || in_shadow(position, point.position)) {
+            continue;
+        }
+        let falloff = 1.0 - point_distance / point.radius;
+        light = light + point.color.rgb * point.intensity * falloff * falloff;
+    }
+    return vec4<f32>(min(light, vec3<f32>(1.0)), 1.0);
+}
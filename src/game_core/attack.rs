@@ -1 +1,95 @@
+use crate::game_core::entities::entity_components::{
+    DamageResistance, Enemy, EnemyStats, Health, Player,
+};
+use crate::game_core::world::{BreakableTile, TileBrokenEvent};
+use bevy::prelude::*;
 
+// Anything that hurts something goes through this event (melee, hazards...),
+// apply_damage_system then takes care of health, death and the events below
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+}
+
+// Events sent by the combat systems so other parts of the game
+// (particles, sound, ui...) can react to hits without having to
+// look at the collision events themselves
+
+pub struct EnemyHitEvent {
+    pub enemy: Entity,
+    pub position: Vec2,
+    pub damage: f32,
+}
+
+pub struct EnemyDeathEvent {
+    pub enemy: Entity,
+    pub position: Vec2,
+    pub name: String,
+    pub archetype: String,
+    pub xp: u32,
+}
+
+// Damage resistance is the percentage of damage taken away, capped so that
+// levelling up never makes the player immune
+pub static MAX_DAMAGE_RESISTANCE: f32 = 80.0;
+
+pub fn resisted_damage(amount: f32, resistance: f32) -> f32 {
+    amount * (1.0 - resistance.clamp(0.0, MAX_DAMAGE_RESISTANCE) / 100.0)
+}
+
+pub fn apply_damage_system(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut enemies: Query<(&Enemy, &mut EnemyStats, &GlobalTransform)>,
+    mut players: Query<(&mut Health, &DamageResistance), With<Player>>,
+    mut breakables: Query<(&mut BreakableTile, &Parent, &GlobalTransform)>,
+    mut hit_events: EventWriter<EnemyHitEvent>,
+    mut death_events: EventWriter<EnemyDeathEvent>,
+    mut tile_broken_events: EventWriter<TileBrokenEvent>,
+) {
+    for damage in damage_events.iter() {
+        if let Ok((enemy, mut stats, transform)) = enemies.get_mut(damage.target) {
+            // Already killed earlier this frame, waiting to be despawned
+            if stats.health <= 0.0 {
+                continue;
+            }
+
+            let position = transform.translation().truncate();
+            hit_events.send(EnemyHitEvent {
+                enemy: damage.target,
+                position,
+                damage: damage.amount,
+            });
+
+            if enemy.attack(damage.amount, &mut stats) {
+                death_events.send(EnemyDeathEvent {
+                    enemy: damage.target,
+                    position,
+                    name: enemy.name.clone(),
+                    archetype: enemy.archetype.clone(),
+                    xp: stats.xp,
+                });
+                commands.entity(damage.target).despawn_recursive();
+            }
+        } else if let Ok((mut health, resistance)) = players.get_mut(damage.target) {
+            let amount = resisted_damage(damage.amount, resistance.0);
+            health.0 = (health.0 - amount).max(0.0);
+        } else if let Ok((mut tile, level, transform)) = breakables.get_mut(damage.target) {
+            if tile.health <= 0.0 {
+                continue;
+            }
+
+            tile.health -= damage.amount;
+            if tile.health <= 0.0 {
+                // The tile sensors are children of their level
+                tile_broken_events.send(TileBrokenEvent {
+                    level: level.get(),
+                    coords: tile.coords,
+                    value: tile.value,
+                    position: transform.translation().truncate(),
+                });
+                commands.entity(damage.target).despawn_recursive();
+            }
+        }
+    }
+}
@@ -0,0 +1,377 @@
+// Sound effects and music
+// Everything else in the game asks for sounds by name with a SoundEvent,
+// the actual files are listed in assets/audio/manifest.ron
+
+use crate::game_core::attack::{EnemyDeathEvent, EnemyHitEvent};
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::entities::entity_components::{Health, Player};
+use crate::game_core::entities::levelling::LevelUpEvent;
+use crate::game_core::particles::FOOTSTEP_MIN_SPEED;
+use crate::game_core::quest::QuestCompletedEvent;
+use crate::game_core::settings::Settings;
+use bevy::audio::AudioSink;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub static AUDIO_MANIFEST_PATH: &str = "assets/audio/manifest.ron";
+
+// Name of the LDtk level field holding the music track for that level
+pub static LEVEL_MUSIC_FIELD: &str = "Music";
+
+pub static FOOTSTEP_INTERVAL_SECS: f32 = 0.35;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Sound {
+    Hit,
+    EnemyDeath,
+    PlayerDeath,
+    Pickup,
+    Footstep,
+    LevelUp,
+    QuestComplete,
+}
+
+pub struct SoundEvent(pub Sound);
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AudioManifest {
+    // A sound can have several files, one is picked at random each time it plays
+    pub sounds: HashMap<Sound, Vec<String>>,
+    pub music: HashMap<String, String>,
+    // Sound effects play at a random speed of 1 +- this, which also shifts the pitch
+    pub pitch_variation: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct AudioHandles {
+    pub sounds: HashMap<Sound, Vec<Handle<AudioSource>>>,
+    pub music: HashMap<String, Handle<AudioSource>>,
+    pub pitch_variation: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct CurrentMusic {
+    pub track: Option<String>,
+    pub sink: Option<Handle<AudioSink>>,
+}
+
+pub fn setup_audio_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let manifest: AudioManifest = load_ron_or_default(AUDIO_MANIFEST_PATH);
+
+    let mut handles = AudioHandles {
+        pitch_variation: manifest.pitch_variation,
+        ..default()
+    };
+    for (sound, paths) in manifest.sounds.iter() {
+        handles.sounds.insert(
+            *sound,
+            paths
+                .iter()
+                .map(|path| asset_server.load(path.as_str()))
+                .collect(),
+        );
+    }
+    for (track, path) in manifest.music.iter() {
+        handles
+            .music
+            .insert(track.clone(), asset_server.load(path.as_str()));
+    }
+
+    commands.insert_resource(handles);
+    commands.init_resource::<CurrentMusic>();
+}
+
+pub fn play_sound_system(
+    mut sound_events: EventReader<SoundEvent>,
+    audio: Res<Audio>,
+    handles: Res<AudioHandles>,
+    settings: Res<Settings>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for SoundEvent(sound) in sound_events.iter() {
+        // Not every sound has a file yet, those are just silent
+        let Some(source) = handles
+            .sounds
+            .get(sound)
+            .and_then(|variants| variants.choose(&mut rng))
+        else {
+            continue;
+        };
+
+        let variation = handles.pitch_variation;
+        let speed = if variation > 0.0 {
+            1.0 + rng.gen_range(-variation..=variation)
+        } else {
+            1.0
+        };
+
+        audio.play_with_settings(
+            source.clone(),
+            PlaybackSettings {
+                repeat: false,
+                volume: settings.audio.sfx(),
+                speed,
+            },
+        );
+    }
+}
+
+pub fn level_music_system(
+    mut level_events: EventReader<LevelEvent>,
+    level_query: Query<&Handle<LdtkLevel>>,
+    levels: Res<Assets<LdtkLevel>>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    handles: Res<AudioHandles>,
+    settings: Res<Settings>,
+    mut current_music: ResMut<CurrentMusic>,
+) {
+    for level_event in level_events.iter() {
+        let LevelEvent::Spawned(level_iid) = level_event else {
+            continue;
+        };
+
+        let Some(level) = level_query
+            .iter()
+            .filter_map(|handle| levels.get(handle))
+            .find(|level| &level.level.iid == level_iid)
+        else {
+            continue;
+        };
+
+        let track = level
+            .level
+            .field_instances
+            .iter()
+            .find(|field| field.identifier == LEVEL_MUSIC_FIELD)
+            .and_then(|field| match &field.value {
+                FieldValue::String(Some(track)) if !track.is_empty() => Some(track.clone()),
+                _ => None,
+            });
+
+        // Keep the music going when moving between levels with the same track
+        if track == current_music.track {
+            continue;
+        }
+
+        if let Some(sink) = current_music
+            .sink
+            .take()
+            .and_then(|sink| audio_sinks.get(&sink))
+        {
+            sink.stop();
+        }
+
+        current_music.track = track.clone();
+        if let Some(source) = track.and_then(|track| handles.music.get(&track)) {
+            let sink = audio.play_with_settings(
+                source.clone(),
+                PlaybackSettings {
+                    repeat: true,
+                    volume: settings.audio.music(),
+                    speed: 1.0,
+                },
+            );
+            current_music.sink = Some(audio_sinks.get_handle(sink));
+        }
+    }
+}
+
+pub fn music_volume_system(
+    settings: Res<Settings>,
+    current_music: Res<CurrentMusic>,
+    audio_sinks: Res<Assets<AudioSink>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    if let Some(sink) = current_music
+        .sink
+        .as_ref()
+        .and_then(|sink| audio_sinks.get(sink))
+    {
+        sink.set_volume(settings.audio.music());
+    }
+}
+
+pub fn combat_sounds_system(
+    mut hit_events: EventReader<EnemyHitEvent>,
+    mut death_events: EventReader<EnemyDeathEvent>,
+    players: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut player_dead: Local<bool>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for _ in hit_events.iter() {
+        sound_events.send(SoundEvent(Sound::Hit));
+    }
+    for _ in death_events.iter() {
+        sound_events.send(SoundEvent(Sound::EnemyDeath));
+    }
+
+    // Only once when health runs out, not on every hit taken after that
+    for health in players.iter() {
+        let dead = health.0 <= 0.0;
+        if dead && !*player_dead {
+            sound_events.send(SoundEvent(Sound::PlayerDeath));
+        }
+        *player_dead = dead;
+    }
+}
+
+pub fn level_up_sound_system(
+    mut level_up_events: EventReader<LevelUpEvent>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for _ in level_up_events.iter() {
+        sound_events.send(SoundEvent(Sound::LevelUp));
+    }
+}
+
+pub fn quest_complete_sound_system(
+    mut completed_events: EventReader<QuestCompletedEvent>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for _ in completed_events.iter() {
+        sound_events.send(SoundEvent(Sound::QuestComplete));
+    }
+}
+
+pub fn footstep_sound_system(
+    time: Res<Time>,
+    player: Query<&Velocity, With<Player>>,
+    mut since_last_step: Local<f32>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    if let Ok(velocity) = player.get_single() {
+        if velocity.linvel.length() <= FOOTSTEP_MIN_SPEED {
+            *since_last_step = FOOTSTEP_INTERVAL_SECS;
+            return;
+        }
+
+        *since_last_step += time.delta_seconds();
+        if *since_last_step >= FOOTSTEP_INTERVAL_SECS {
+            *since_last_step = 0.0;
+            sound_events.send(SoundEvent(Sound::Footstep));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::settings::AudioSettings;
+    use crate::game_core::test_harness::TestApp;
+
+    // Steps the app, collecting the sounds asked for on the way
+    fn sounds_while_stepping(test_app: &mut TestApp, frames: u32) -> Vec<Sound> {
+        let mut reader = test_app
+            .app
+            .world
+            .resource::<Events<SoundEvent>>()
+            .get_reader_current();
+        let mut sounds = Vec::new();
+        for _ in 0..frames {
+            test_app.step(1);
+            let events = test_app.app.world.resource::<Events<SoundEvent>>();
+            sounds.extend(reader.iter(events).map(|SoundEvent(sound)| *sound));
+        }
+        sounds
+    }
+
+    #[test]
+    fn gameplay_events_ask_for_their_sounds() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        let world = &mut test_app.app.world;
+        world.send_event(EnemyHitEvent {
+            enemy: Entity::from_raw(u32::MAX),
+            position: Vec2::ZERO,
+            damage: 1.0,
+        });
+        world.send_event(EnemyDeathEvent {
+            enemy: Entity::from_raw(u32::MAX),
+            position: Vec2::ZERO,
+            name: "test".to_string(),
+            archetype: "slime".to_string(),
+            xp: 0,
+        });
+        world.send_event(LevelUpEvent { player, level: 1 });
+        world.send_event(QuestCompletedEvent {
+            quest: "test".to_string(),
+        });
+
+        let mut sounds = sounds_while_stepping(&mut test_app, 1);
+        sounds.sort_by_key(|sound| format!("{:?}", sound));
+        assert_eq!(
+            sounds,
+            vec![
+                Sound::EnemyDeath,
+                Sound::Hit,
+                Sound::LevelUp,
+                Sound::QuestComplete
+            ]
+        );
+    }
+
+    #[test]
+    fn the_player_only_dies_once() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+
+        test_app.get_mut::<Health>(player).unwrap().0 = 0.0;
+        assert_eq!(
+            sounds_while_stepping(&mut test_app, 1),
+            vec![Sound::PlayerDeath]
+        );
+
+        // Hits taken while already dead stay quiet
+        test_app.get_mut::<Health>(player).unwrap().0 = 0.0;
+        assert!(sounds_while_stepping(&mut test_app, 1).is_empty());
+    }
+
+    #[test]
+    fn footsteps_follow_the_walking_pace() {
+        let mut test_app = TestApp::sandbox();
+        assert!(sounds_while_stepping(&mut test_app, 30).is_empty());
+
+        test_app.press_key(KeyCode::D);
+        let sounds = sounds_while_stepping(&mut test_app, 60);
+        test_app.release_key(KeyCode::D);
+
+        // The first step sounds as soon as the player is fast enough, then one
+        // every FOOTSTEP_INTERVAL_SECS
+        let expected = (1.0 / FOOTSTEP_INTERVAL_SECS) as usize;
+        assert!(sounds.iter().all(|sound| *sound == Sound::Footstep));
+        assert!(
+            (expected..=expected + 1).contains(&sounds.len()),
+            "{} footsteps",
+            sounds.len()
+        );
+    }
+
+    #[test]
+    fn volumes_are_scaled_by_the_master_volume() {
+        let settings = AudioSettings {
+            master_volume: 0.5,
+            music_volume: 0.6,
+            sfx_volume: 0.8,
+        };
+        assert!((settings.music() - 0.3).abs() < 1e-6);
+        assert!((settings.sfx() - 0.4).abs() < 1e-6);
+
+        let muted = AudioSettings {
+            master_volume: 0.0,
+            ..settings
+        };
+        assert_eq!(muted.music(), 0.0);
+        assert_eq!(muted.sfx(), 0.0);
+    }
+}
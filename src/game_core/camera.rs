@@ -1,11 +1,30 @@
 use crate::game_core::entities::entity_components::Player;
 use bevy::prelude::*;
+use rand::Rng;
+
+// How fast a shake dies down, in pixels per second
+pub static SHAKE_DECAY: f32 = 30.0;
 
 // pub fn setup_camera() {
 //
 // }
 
+// Shakes the camera by up to `strength` pixels, fading out over time
+#[derive(Resource, Default)]
+pub struct CameraShake {
+    pub strength: f32,
+}
+
+impl CameraShake {
+    // A weaker shake doesn't cut a stronger one short
+    pub fn shake(&mut self, strength: f32) {
+        self.strength = self.strength.max(strength);
+    }
+}
+
 pub fn follow_player_system(
+    time: Res<Time>,
+    mut shake: ResMut<CameraShake>,
     player_query: Query<(&Transform), With<Player>>,
     mut camera_query: Query<
         (
@@ -15,10 +34,17 @@ pub fn follow_player_system(
         Without<Player>,
     >,
 ) {
+    let mut offset = Vec2::ZERO;
+    if shake.strength > 0.0 {
+        let mut rng = rand::thread_rng();
+        offset = Vec2::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0)) * shake.strength;
+        shake.strength = (shake.strength - SHAKE_DECAY * time.delta_seconds()).max(0.0);
+    }
+
     if let Ok((camera, mut camera_transform)) = camera_query.get_single_mut() {
         if let Ok(player_transform) = player_query.get_single() {
-            camera_transform.translation.x = player_transform.translation.x;
-            camera_transform.translation.y = player_transform.translation.y;
+            camera_transform.translation.x = player_transform.translation.x + offset.x;
+            camera_transform.translation.y = player_transform.translation.y + offset.y;
         }
     }
 }
@@ -0,0 +1,143 @@
+// Rapier collision groups for everything in the game
+// INTERACTIONS is the one place that says who collides with (or senses) whom
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Layer {
+    Player,
+    Enemy,
+    // Walls, fences and anything else solid
+    World,
+    // Blocks walking, but not things flying over it
+    Water,
+    PlayerAttack,
+    EnemyAttack,
+    Pickup,
+    // Sensor areas, like slow zones or level triggers
+    Trigger,
+    // Tiles that can be destroyed by attacking them
+    Breakable,
+    // The player's ranged attacks, enemy ones are EnemyAttack
+    PlayerProjectile,
+}
+
+pub static ALL_LAYERS: [Layer; 10] = [
+    Layer::Player,
+    Layer::Enemy,
+    Layer::World,
+    Layer::Water,
+    Layer::PlayerAttack,
+    Layer::EnemyAttack,
+    Layer::Pickup,
+    Layer::Trigger,
+    Layer::Breakable,
+    Layer::PlayerProjectile,
+];
+
+// Every pair of layers that interact, in both directions
+// Anything not listed here passes straight through each other
+pub static INTERACTIONS: [(Layer, Layer); 16] = [
+    (Layer::Player, Layer::World),
+    (Layer::Player, Layer::Water),
+    (Layer::Player, Layer::Enemy),
+    (Layer::Player, Layer::EnemyAttack),
+    (Layer::Player, Layer::Pickup),
+    (Layer::Player, Layer::Trigger),
+    (Layer::Enemy, Layer::World),
+    (Layer::Enemy, Layer::Water),
+    (Layer::Enemy, Layer::Enemy),
+    (Layer::Enemy, Layer::PlayerAttack),
+    (Layer::Enemy, Layer::Trigger),
+    (Layer::EnemyAttack, Layer::World),
+    (Layer::PlayerAttack, Layer::Breakable),
+    (Layer::PlayerProjectile, Layer::Enemy),
+    (Layer::PlayerProjectile, Layer::World),
+    (Layer::PlayerProjectile, Layer::Breakable),
+];
+
+impl Layer {
+    pub fn group(&self) -> Group {
+        match self {
+            Layer::Player => Group::GROUP_1,
+            Layer::Enemy => Group::GROUP_2,
+            Layer::World => Group::GROUP_3,
+            Layer::Water => Group::GROUP_4,
+            Layer::PlayerAttack => Group::GROUP_5,
+            Layer::EnemyAttack => Group::GROUP_6,
+            Layer::Pickup => Group::GROUP_7,
+            Layer::Trigger => Group::GROUP_8,
+            Layer::Breakable => Group::GROUP_9,
+            Layer::PlayerProjectile => Group::GROUP_10,
+        }
+    }
+
+    pub fn interacts_with(&self, other: Layer) -> bool {
+        INTERACTIONS
+            .iter()
+            .any(|&(a, b)| (a == *self && b == other) || (a == other && b == *self))
+    }
+
+    pub fn filters(&self) -> Group {
+        ALL_LAYERS
+            .iter()
+            .filter(|other| self.interacts_with(**other))
+            .fold(Group::NONE, |filters, other| filters | other.group())
+    }
+
+    pub fn collision_groups(&self) -> CollisionGroups {
+        CollisionGroups::new(self.group(), self.filters())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rapier only lets two colliders interact if each one's filters
+    // contain the other's group
+    fn rapier_interacts(a: Layer, b: Layer) -> bool {
+        let a = a.collision_groups();
+        let b = b.collision_groups();
+        a.memberships.intersects(b.filters) && b.memberships.intersects(a.filters)
+    }
+
+    #[test]
+    fn groups_match_the_interaction_matrix() {
+        for a in ALL_LAYERS {
+            for b in ALL_LAYERS {
+                assert_eq!(
+                    rapier_interacts(a, b),
+                    a.interacts_with(b),
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn player_attack_only_hits_enemies_and_breakables() {
+        for layer in ALL_LAYERS {
+            assert_eq!(
+                Layer::PlayerAttack.interacts_with(layer),
+                layer == Layer::Enemy || layer == Layer::Breakable,
+                "{:?}",
+                layer
+            );
+        }
+    }
+
+    #[test]
+    fn every_layer_has_its_own_group() {
+        for a in ALL_LAYERS {
+            for b in ALL_LAYERS {
+                if a != b {
+                    assert!(!a.group().intersects(b.group()));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Helpers for the ron data files under assets/data
+
+use bevy::asset::FileAssetIo;
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+// Data paths start from the folder holding the assets folder, found the same
+// way the AssetServer finds it (BEVY_ASSET_ROOT, then CARGO_MANIFEST_DIR, then
+// next to the executable), so the game can be started from any directory
+pub fn resolve_path(path: &str) -> PathBuf {
+    FileAssetIo::get_base_path().join(path)
+}
+
+// Missing or broken files fall back to the defaults, with a warning so
+// designers can see what went wrong
+pub fn load_ron_or_default<T: DeserializeOwned + Default>(path: &str) -> T {
+    match fs::read_to_string(resolve_path(path)) {
+        Ok(contents) => ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Could not parse {}: {}", path, error);
+            T::default()
+        }),
+        Err(error) => {
+            warn!("Could not read {}: {}", path, error);
+            T::default()
+        }
+    }
+}
+
+// Written the same way it is read back, failures only get a warning since
+// losing a save is better than crashing the game over it
+pub fn save_ron<T: Serialize>(path: &str, value: &T) {
+    let contents = match ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        Ok(contents) => contents,
+        Err(error) => {
+            warn!("Could not serialize {}: {}", path, error);
+            return;
+        }
+    };
+
+    if let Err(error) = fs::write(resolve_path(path), contents) {
+        warn!("Could not write {}: {}", path, error);
+    }
+}
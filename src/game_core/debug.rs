@@ -0,0 +1,184 @@
+// Debug overlays, only compiled with `cargo run --features debug`
+//
+// F1 - Rapier collider rendering
+// F2 - World inspector
+// F3 - FPS and entity count
+// F4 - Grid overlay (IntGrid GridCoords and the merged tile colliders)
+
+use crate::game_core::ui::UiFont;
+use crate::game_core::world::MergedTileCollider;
+use bevy::diagnostic::{Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+use bevy_rapier2d::render::DebugRenderContext;
+
+pub static TOGGLE_COLLIDERS_KEY: KeyCode = KeyCode::F1;
+pub static TOGGLE_INSPECTOR_KEY: KeyCode = KeyCode::F2;
+pub static TOGGLE_STATS_KEY: KeyCode = KeyCode::F3;
+pub static TOGGLE_GRID_KEY: KeyCode = KeyCode::F4;
+
+static GRID_TEXT_COLOR: Color = Color::rgba(1.0, 1.0, 0.0, 0.8);
+static MERGED_RECT_COLOR: Color = Color::rgba(1.0, 0.0, 1.0, 0.25);
+
+#[derive(Resource)]
+pub struct DebugOverlays {
+    pub colliders: bool,
+    pub inspector: bool,
+    pub stats: bool,
+    pub grid: bool,
+}
+
+impl Default for DebugOverlays {
+    fn default() -> Self {
+        // Colliders start visible, same as before this was toggleable
+        DebugOverlays {
+            colliders: true,
+            inspector: false,
+            stats: false,
+            grid: false,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct DebugStatsText;
+
+#[derive(Component)]
+pub struct GridOverlay;
+
+pub fn toggle_debug_overlays_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlays: ResMut<DebugOverlays>,
+    mut debug_render: ResMut<DebugRenderContext>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_COLLIDERS_KEY) {
+        overlays.colliders = !overlays.colliders;
+    }
+    if keyboard_input.just_pressed(TOGGLE_INSPECTOR_KEY) {
+        overlays.inspector = !overlays.inspector;
+    }
+    if keyboard_input.just_pressed(TOGGLE_STATS_KEY) {
+        overlays.stats = !overlays.stats;
+    }
+    if keyboard_input.just_pressed(TOGGLE_GRID_KEY) {
+        overlays.grid = !overlays.grid;
+    }
+
+    debug_render.enabled = overlays.colliders;
+}
+
+// Same window as bevy_inspector_egui's WorldInspectorPlugin, but only drawn while toggled on
+pub fn world_inspector_system(world: &mut World) {
+    if !world.resource::<DebugOverlays>().inspector {
+        return;
+    }
+
+    let egui_context = world.resource_mut::<EguiContext>().ctx_mut().clone();
+    egui::Window::new("World Inspector")
+        .default_size((320.0, 160.0))
+        .show(&egui_context, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                bevy_inspector_egui::bevy_inspector::ui_for_world(world, ui);
+                ui.allocate_space(ui.available_size());
+            });
+        });
+}
+
+pub fn setup_debug_stats(mut commands: Commands, font: Res<UiFont>) {
+    commands.spawn((
+        TextBundle::from_section("", font.style(14.0)).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(4.0),
+                top: Val::Px(4.0),
+                ..default()
+            },
+            ..default()
+        }),
+        DebugStatsText,
+    ));
+}
+
+pub fn debug_stats_system(
+    overlays: Res<DebugOverlays>,
+    diagnostics: Res<Diagnostics>,
+    mut text: Query<(&mut Text, &mut Visibility), With<DebugStatsText>>,
+) {
+    if let Ok((mut text, mut visibility)) = text.get_single_mut() {
+        visibility.is_visible = overlays.stats;
+        if !overlays.stats {
+            return;
+        }
+
+        let fps = diagnostics
+            .get(FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.smoothed())
+            .unwrap_or(0.0);
+        let entities = diagnostics
+            .get(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+            .and_then(|count| count.value())
+            .unwrap_or(0.0);
+
+        text.sections[0].value = format!("FPS: {:.0}\nEntities: {:.0}", fps, entities);
+    }
+}
+
+pub fn grid_overlay_system(
+    mut commands: Commands,
+    overlays: Res<DebugOverlays>,
+    font: Res<UiFont>,
+    cells: Query<(&GridCoords, &GlobalTransform), With<IntGridCell>>,
+    merged_colliders: Query<(&MergedTileCollider, &GlobalTransform)>,
+    existing_overlay: Query<Entity, With<GridOverlay>>,
+) {
+    if !overlays.is_changed() {
+        return;
+    }
+
+    for entity in existing_overlay.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if !overlays.grid {
+        return;
+    }
+
+    for (grid_coords, transform) in cells.iter() {
+        let mut text_style = font.style(5.0);
+        text_style.color = GRID_TEXT_COLOR;
+
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    format!("{},{}", grid_coords.x, grid_coords.y),
+                    text_style,
+                )
+                .with_alignment(TextAlignment::CENTER),
+                transform: Transform::from_translation(
+                    transform.translation().truncate().extend(900.0),
+                ),
+                ..default()
+            },
+            GridOverlay,
+        ));
+    }
+
+    for (merged, transform) in merged_colliders.iter() {
+        for rect in merged.rects.iter() {
+            let center = transform.translation().truncate() + rect.center(merged.grid_size);
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: MERGED_RECT_COLOR,
+                        custom_size: Some(rect.size(merged.grid_size)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(center.extend(899.0)),
+                    ..default()
+                },
+                GridOverlay,
+            ));
+        }
+    }
+}
@@ -0,0 +1,361 @@
+// Conversations with NPCs, written as ron files under assets/dialogue
+// A dialogue is a graph of nodes. Entering a node can redirect to another one
+// depending on flags and the inventory, apply actions (set flags, give keys...),
+// and then either offers choices or moves on to its `next` node.
+// The conversation ends when there is nowhere left to go
+
+use crate::game_core::data::{load_ron_or_default, resolve_path};
+use crate::game_core::entities::entity_components::{Inventory, Player};
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::save::SaveData;
+use crate::game_core::settings::{Action, Settings};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+pub static DIALOGUE_DIR: &str = "assets/dialogue";
+
+static INPUT_LOCK: &str = "dialogue";
+
+// Speed of the typewriter effect
+pub static CHARS_PER_SEC: f32 = 40.0;
+
+// Guards against branches that send each other round in circles
+static MAX_REDIRECTS: usize = 16;
+
+static CHOICE_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum Condition {
+    Flag(String),
+    NotFlag(String),
+    HasKey(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum DialogueAction {
+    SetFlag(String),
+    ClearFlag(String),
+    GiveKey(String),
+    TakeKey(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Branch {
+    pub conditions: Vec<Condition>,
+    pub node: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Choice {
+    pub text: String,
+    // The choice is hidden unless all of these hold
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<DialogueAction>,
+    // Ends the conversation when None
+    pub next: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DialogueNode {
+    // The NPC's name when None
+    pub speaker: Option<String>,
+    pub text: String,
+    // The first branch whose conditions hold is entered instead of this node
+    pub branches: Vec<Branch>,
+    pub actions: Vec<DialogueAction>,
+    pub choices: Vec<Choice>,
+    // Where to go without choices, ends the conversation when None
+    pub next: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Dialogue {
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+impl Default for Dialogue {
+    fn default() -> Self {
+        Dialogue {
+            start: "start".to_string(),
+            nodes: HashMap::from([(
+                "start".to_string(),
+                DialogueNode {
+                    text: "...".to_string(),
+                    ..default()
+                },
+            )]),
+        }
+    }
+}
+
+impl Dialogue {
+    pub fn load(id: &str) -> Dialogue {
+        load_ron_or_default(&format!("{}/{}.ron", DIALOGUE_DIR, id))
+    }
+}
+
+// Every dialogue under DIALOGUE_DIR by id, read once at startup instead of
+// every time someone starts talking
+#[derive(Resource, Default)]
+pub struct Dialogues(pub HashMap<String, Dialogue>);
+
+impl Dialogues {
+    pub fn load() -> Dialogues {
+        let entries = match fs::read_dir(resolve_path(DIALOGUE_DIR)) {
+            Ok(entries) => entries,
+            Err(error) => {
+                warn!("Could not read {}: {}", DIALOGUE_DIR, error);
+                return Dialogues::default();
+            }
+        };
+
+        let dialogues = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .map_or(false, |extension| extension == "ron")
+            })
+            .filter_map(|path| {
+                let id = path.file_stem()?.to_str()?.to_string();
+                let dialogue = Dialogue::load(&id);
+                Some((id, dialogue))
+            })
+            .collect();
+        Dialogues(dialogues)
+    }
+
+    // Unknown ids get the placeholder dialogue, like a file that fails to load
+    pub fn get(&self, id: &str) -> Dialogue {
+        self.0.get(id).cloned().unwrap_or_else(|| {
+            warn!("There is no dialogue called {}", id);
+            Dialogue::default()
+        })
+    }
+}
+
+pub fn conditions_hold(
+    conditions: &[Condition],
+    save_data: &SaveData,
+    inventory: &Inventory,
+) -> bool {
+    conditions.iter().all(|condition| match condition {
+        Condition::Flag(flag) => save_data.flags.contains(flag),
+        Condition::NotFlag(flag) => !save_data.flags.contains(flag),
+        Condition::HasKey(key_id) => inventory.has_key(key_id),
+    })
+}
+
+pub fn apply_actions(
+    actions: &[DialogueAction],
+    save_data: &mut SaveData,
+    inventory: &mut Inventory,
+) {
+    // Touching the save data would write it to disk again for nothing
+    if actions.is_empty() {
+        return;
+    }
+
+    for action in actions {
+        match action {
+            DialogueAction::SetFlag(flag) => {
+                save_data.flags.insert(flag.clone());
+            }
+            DialogueAction::ClearFlag(flag) => {
+                save_data.flags.remove(flag);
+            }
+            DialogueAction::GiveKey(key_id) => inventory.add_key(key_id),
+            DialogueAction::TakeKey(key_id) => {
+                inventory.take_key(key_id);
+            }
+        }
+    }
+    save_data.keys = inventory.keys.clone();
+}
+
+pub struct ActiveConversation {
+    pub npc: Entity,
+    pub npc_name: String,
+    pub dialogue: Dialogue,
+    pub node: String,
+    // Indices of the current node's choices whose conditions hold
+    pub choices: Vec<usize>,
+    // Characters of the text shown so far by the typewriter
+    pub revealed: f32,
+}
+
+impl ActiveConversation {
+    pub fn current(&self) -> &DialogueNode {
+        &self.dialogue.nodes[&self.node]
+    }
+
+    pub fn speaker(&self) -> &str {
+        self.current()
+            .speaker
+            .as_deref()
+            .unwrap_or(self.npc_name.as_str())
+    }
+
+    pub fn fully_shown(&self) -> bool {
+        self.revealed as usize >= self.current().text.chars().count()
+    }
+
+    pub fn visible_text(&self) -> String {
+        self.current()
+            .text
+            .chars()
+            .take(self.revealed as usize)
+            .collect()
+    }
+
+    pub fn choice(&self, index: usize) -> Option<&Choice> {
+        self.choices
+            .get(index)
+            .map(|&choice| &self.current().choices[choice])
+    }
+
+    // Follows branches and applies the node's actions, returns false when the
+    // node doesn't exist, which ends the conversation
+    fn enter(&mut self, node: &str, save_data: &mut SaveData, inventory: &mut Inventory) -> bool {
+        let mut node = node.to_string();
+        for _ in 0..MAX_REDIRECTS {
+            let Some(current) = self.dialogue.nodes.get(&node) else {
+                warn!("Dialogue node {} doesn't exist", node);
+                return false;
+            };
+            match current
+                .branches
+                .iter()
+                .find(|branch| conditions_hold(&branch.conditions, save_data, inventory))
+            {
+                Some(branch) => node = branch.node.clone(),
+                None => break,
+            }
+        }
+        let Some(current) = self.dialogue.nodes.get(&node) else {
+            return false;
+        };
+
+        apply_actions(&current.actions, save_data, inventory);
+        self.choices = current
+            .choices
+            .iter()
+            .enumerate()
+            .filter(|(_, choice)| conditions_hold(&choice.conditions, save_data, inventory))
+            .map(|(index, _)| index)
+            .collect();
+        self.node = node;
+        self.revealed = 0.0;
+        true
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Conversation(pub Option<ActiveConversation>);
+
+impl Conversation {
+    pub fn is_active(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn start(
+        &mut self,
+        npc: Entity,
+        npc_name: &str,
+        dialogue: Dialogue,
+        save_data: &mut SaveData,
+        inventory: &mut Inventory,
+        input_locks: &mut InputLocks,
+    ) {
+        let start = dialogue.start.clone();
+        let mut conversation = ActiveConversation {
+            npc,
+            npc_name: npc_name.to_string(),
+            dialogue,
+            node: String::new(),
+            choices: Vec::new(),
+            revealed: 0.0,
+        };
+        if conversation.enter(&start, save_data, inventory) {
+            self.0 = Some(conversation);
+            input_locks.lock(INPUT_LOCK);
+        }
+    }
+}
+
+// Interact skips the typewriter and then moves on, the number keys pick choices
+pub fn dialogue_input_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    mut conversation: ResMut<Conversation>,
+    mut input_locks: ResMut<InputLocks>,
+    mut save_data: ResMut<SaveData>,
+    mut players: Query<&mut Inventory, With<Player>>,
+) {
+    // Checked before borrowing mutably, which would flag the conversation as changed
+    if !conversation.is_active() {
+        return;
+    }
+    let Ok(mut inventory) = players.get_single_mut() else {
+        return;
+    };
+    let Some(active) = conversation.0.as_mut() else {
+        return;
+    };
+
+    active.revealed += time.delta_seconds() * CHARS_PER_SEC;
+
+    let interact = settings
+        .key_bindings
+        .just_pressed(Action::Interact, &keyboard_input);
+    if !active.fully_shown() {
+        if interact {
+            active.revealed = active.current().text.chars().count() as f32;
+        }
+        return;
+    }
+
+    let next = if active.choices.is_empty() {
+        if !interact {
+            return;
+        }
+        active.current().next.clone()
+    } else {
+        let Some(choice) = CHOICE_KEYS
+            .iter()
+            .position(|key| keyboard_input.just_pressed(*key))
+            .and_then(|index| active.choice(index))
+            .cloned()
+        else {
+            return;
+        };
+        apply_actions(&choice.actions, &mut save_data, &mut inventory);
+        choice.next
+    };
+
+    let continues = match next {
+        Some(next) => active.enter(&next, &mut save_data, &mut inventory),
+        None => false,
+    };
+    if !continues {
+        conversation.0 = None;
+        input_locks.unlock(INPUT_LOCK);
+    }
+}
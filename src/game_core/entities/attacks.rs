@@ -0,0 +1,99 @@
+// Player melee moves, loaded from assets/data/attacks.ron
+// Each attack kind has a chain of steps: pressing the same attack again within
+// the chain window after a step's recovery plays the next step of the chain
+
+use crate::game_core::data::load_ron_or_default;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub static ATTACKS_PATH: &str = "assets/data/attacks.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum AttackKind {
+    Light,
+    Heavy,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum HitboxShape {
+    // Width and height
+    Box(f32, f32),
+    // Radius
+    Circle(f32),
+}
+
+impl HitboxShape {
+    pub fn collider(&self) -> Collider {
+        match *self {
+            HitboxShape::Box(width, height) => Collider::cuboid(width / 2.0, height / 2.0),
+            HitboxShape::Circle(radius) => Collider::ball(radius),
+        }
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        match *self {
+            HitboxShape::Box(width, height) => {
+                Mesh::from(shape::Quad::new(Vec2::new(width, height)))
+            }
+            HitboxShape::Circle(radius) => Mesh::from(shape::Circle::new(radius)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AttackStep {
+    pub hitbox: HitboxShape,
+    // Multiplies the player's AttackPower
+    pub damage_multiplier: f32,
+    // Seconds the hitbox stays out
+    pub active: f32,
+    // Seconds after the hitbox is gone before the next attack can start
+    pub recovery: f32,
+    // The step doesn't start without this much stamina
+    #[serde(default)]
+    pub stamina_cost: f32,
+}
+
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AttackDefinitions {
+    // Seconds after a step's recovery during which the next press continues the chain
+    pub chain_window: f32,
+    pub combos: HashMap<AttackKind, Vec<AttackStep>>,
+}
+
+impl Default for AttackDefinitions {
+    fn default() -> Self {
+        // The single swing the player had before combos existed
+        AttackDefinitions {
+            chain_window: 0.0,
+            combos: HashMap::from([(
+                AttackKind::Light,
+                vec![AttackStep {
+                    hitbox: HitboxShape::Box(40.0, 40.0),
+                    damage_multiplier: 1.0,
+                    active: 0.1,
+                    recovery: 0.0,
+                    stamina_cost: 0.0,
+                }],
+            )]),
+        }
+    }
+}
+
+impl AttackDefinitions {
+    pub fn load() -> AttackDefinitions {
+        load_ron_or_default(ATTACKS_PATH)
+    }
+
+    // Empty for kinds without any steps defined
+    pub fn chain(&self, kind: AttackKind) -> &[AttackStep] {
+        self.combos.get(&kind).map_or(&[], |steps| steps.as_slice())
+    }
+
+    pub fn step(&self, kind: AttackKind, step: usize) -> Option<&AttackStep> {
+        self.chain(kind).get(step)
+    }
+}
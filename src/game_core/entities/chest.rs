@@ -0,0 +1,146 @@
+// Chests placed in LDtk, opened with the interact key
+// Each has a "Chest_id" for quests to refer to, and is kept in the save data
+// by entity iid so it stays open
+
+use crate::game_core::audio::{Sound, SoundEvent};
+use crate::game_core::entities::entity_components::ColliderBundle;
+use crate::game_core::entities::interaction::InteractionTarget;
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::save::SaveData;
+use crate::game_core::settings::{Action, Settings};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+pub static CHEST_SPRITE_PATH: &str = "tile_sets/mystic_woods_free_2.1/sprites/objects/chest_01.png";
+
+// The sprite sheet is the lid opening over 4 frames
+static CHEST_FRAME_SIZE: f32 = 16.0;
+static CHEST_FRAMES: usize = 4;
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Chest {
+    pub iid: String,
+    pub chest_id: String,
+    pub open: bool,
+}
+
+impl From<EntityInstance> for Chest {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut chest = Chest {
+            iid: entity_instance.iid.clone(),
+            ..default()
+        };
+        for field in entity_instance.field_instances {
+            if field.identifier == "Chest_id" {
+                if let FieldValue::String(Some(chest_id)) = field.value {
+                    chest.chest_id = chest_id;
+                }
+            }
+        }
+        chest
+    }
+}
+
+// The sprite sheet is set up in on_chest_spawned
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct ChestBundle {
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+
+    #[from_entity_instance]
+    pub chest: Chest,
+
+    #[from_entity_instance]
+    #[bundle]
+    pub collider_bundle: ColliderBundle,
+
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+}
+
+pub struct ChestOpenedEvent {
+    pub chest: Entity,
+    pub chest_id: String,
+}
+
+fn chest_frame(open: bool) -> usize {
+    if open {
+        CHEST_FRAMES - 1
+    } else {
+        0
+    }
+}
+
+// Chests opened in an earlier session start open
+pub fn on_chest_spawned(
+    mut chests: Query<
+        (
+            &mut Chest,
+            &mut Handle<TextureAtlas>,
+            &mut TextureAtlasSprite,
+        ),
+        Added<Chest>,
+    >,
+    save_data: Res<SaveData>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut atlas: Local<Option<Handle<TextureAtlas>>>,
+) {
+    for (mut chest, mut atlas_handle, mut sprite) in chests.iter_mut() {
+        let handle = atlas.get_or_insert_with(|| {
+            texture_atlases.add(TextureAtlas::from_grid(
+                asset_server.load(CHEST_SPRITE_PATH),
+                Vec2::splat(CHEST_FRAME_SIZE),
+                CHEST_FRAMES,
+                1,
+                None,
+                None,
+            ))
+        });
+        *atlas_handle = handle.clone();
+
+        chest.open = save_data.opened_chests.contains(&chest.iid);
+        sprite.index = chest_frame(chest.open);
+    }
+}
+
+// Opens the targeted chest
+pub fn chest_interaction_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
+    target: Res<InteractionTarget>,
+    mut chests: Query<(&mut Chest, Option<&mut TextureAtlasSprite>)>,
+    mut save_data: ResMut<SaveData>,
+    mut opened_events: EventWriter<ChestOpenedEvent>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    if input_locks.is_locked()
+        || !settings
+            .key_bindings
+            .just_pressed(Action::Interact, &keyboard_input)
+    {
+        return;
+    }
+
+    let Some(entity) = target.0 else {
+        return;
+    };
+    let Ok((mut chest, sprite)) = chests.get_mut(entity) else {
+        return;
+    };
+    if chest.open {
+        return;
+    }
+
+    chest.open = true;
+    if let Some(mut sprite) = sprite {
+        sprite.index = chest_frame(true);
+    }
+    save_data.opened_chests.insert(chest.iid.clone());
+    opened_events.send(ChestOpenedEvent {
+        chest: entity,
+        chest_id: chest.chest_id.clone(),
+    });
+    sound_events.send(SoundEvent(Sound::Pickup));
+}
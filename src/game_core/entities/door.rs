@@ -0,0 +1,349 @@
+// Doors that can be locked, and the keys that unlock them
+// Both are kept in the save data by entity iid, so opened doors stay open
+// and picked up keys don't come back
+
+use crate::game_core::audio::{Sound, SoundEvent};
+use crate::game_core::entities::entity_components::{ColliderBundle, Inventory, Player};
+use crate::game_core::entities::interaction::InteractionTarget;
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::save::SaveData;
+use crate::game_core::settings::{Action, Settings};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+pub static DOOR_OPEN_SPRITE_PATH: &str =
+    "tile_sets/mystic_woods_free_2.1/sprites/tilesets/walls/wooden_door_b.png";
+
+// There is no key sprite in the tilesets, so keys are drawn as a small square
+static KEY_COLOR: Color = Color::rgb(1.0, 0.85, 0.2);
+static KEY_SIZE: f32 = 6.0;
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Door {
+    pub iid: String,
+    pub locked: bool,
+    // Key needed to unlock the door, locked doors without one never open
+    pub key_id: Option<String>,
+    pub open: bool,
+}
+
+impl From<EntityInstance> for Door {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut door = Door {
+            iid: entity_instance.iid.clone(),
+            ..default()
+        };
+        for field in entity_instance.field_instances {
+            match field.identifier.as_ref() {
+                "Locked" => {
+                    if let FieldValue::Bool(locked) = field.value {
+                        door.locked = locked;
+                    }
+                }
+                "Key_id" => {
+                    if let FieldValue::String(key_id) = field.value {
+                        door.key_id = key_id;
+                    }
+                }
+                _ => {}
+            }
+        }
+        door
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct DoorBundle {
+    #[sprite_bundle("tile_sets/mystic_woods_free_2.1/sprites/tilesets/walls/wooden_door.png")]
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+
+    #[from_entity_instance]
+    pub door: Door,
+
+    #[from_entity_instance]
+    #[bundle]
+    pub collider_bundle: ColliderBundle,
+
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+}
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct KeyPickup {
+    pub iid: String,
+    pub key_id: String,
+}
+
+impl From<EntityInstance> for KeyPickup {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut key = KeyPickup {
+            iid: entity_instance.iid.clone(),
+            ..default()
+        };
+        for field in entity_instance.field_instances {
+            if field.identifier == "Key_id" {
+                if let FieldValue::String(Some(key_id)) = field.value {
+                    key.key_id = key_id;
+                }
+            }
+        }
+        key
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct KeyBundle {
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+
+    #[from_entity_instance]
+    pub key: KeyPickup,
+
+    #[from_entity_instance]
+    #[bundle]
+    pub collider_bundle: ColliderBundle,
+
+    pub sensor: Sensor,
+
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+}
+
+fn open_door(commands: &mut Commands, entity: Entity, door: &mut Door, asset_server: &AssetServer) {
+    door.open = true;
+    door.locked = false;
+
+    let open_sprite: Handle<Image> = asset_server.load(DOOR_OPEN_SPRITE_PATH);
+    commands
+        .entity(entity)
+        .remove::<Collider>()
+        .insert(open_sprite);
+}
+
+// Doors opened in an earlier session start open
+pub fn on_door_spawned(
+    mut commands: Commands,
+    mut doors: Query<(Entity, &mut Door), Added<Door>>,
+    save_data: Res<SaveData>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, mut door) in doors.iter_mut() {
+        if save_data.opened_doors.contains(&door.iid) {
+            open_door(&mut commands, entity, &mut door, &asset_server);
+        }
+    }
+}
+
+pub fn on_key_spawned(
+    mut commands: Commands,
+    mut keys: Query<(Entity, &KeyPickup, &mut Sprite), Added<KeyPickup>>,
+    save_data: Res<SaveData>,
+) {
+    for (entity, key, mut sprite) in keys.iter_mut() {
+        if save_data.collected_keys.contains(&key.iid) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        sprite.color = KEY_COLOR;
+        sprite.custom_size = Some(Vec2::splat(KEY_SIZE));
+    }
+}
+
+// The player's keys come from the save data
+pub fn restore_inventory_system(
+    mut inventories: Query<&mut Inventory, Added<Inventory>>,
+    save_data: Res<SaveData>,
+) {
+    for mut inventory in inventories.iter_mut() {
+        inventory.keys = save_data.keys.clone();
+    }
+}
+
+pub fn key_pickup_system(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut players: Query<(Entity, &mut Inventory), With<Player>>,
+    keys: Query<(Entity, &KeyPickup)>,
+    mut save_data: ResMut<SaveData>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    let Ok((player, mut inventory)) = players.get_single_mut() else {
+        return;
+    };
+
+    for (key_entity, key) in keys.iter() {
+        if rapier_context.intersection_pair(player, key_entity) != Some(true) {
+            continue;
+        }
+
+        inventory.add_key(&key.key_id);
+        save_data.keys = inventory.keys.clone();
+        save_data.collected_keys.insert(key.iid.clone());
+        sound_events.send(SoundEvent(Sound::Pickup));
+        commands.entity(key_entity).despawn_recursive();
+    }
+}
+
+// Opens the targeted door, using up a key if it is locked
+pub fn door_interaction_system(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
+    asset_server: Res<AssetServer>,
+    target: Res<InteractionTarget>,
+    mut players: Query<&mut Inventory, With<Player>>,
+    mut doors: Query<&mut Door>,
+    mut save_data: ResMut<SaveData>,
+) {
+    if input_locks.is_locked()
+        || !settings
+            .key_bindings
+            .just_pressed(Action::Interact, &keyboard_input)
+    {
+        return;
+    }
+
+    let Some((entity, mut door)) = target
+        .0
+        .and_then(|entity| Some((entity, doors.get_mut(entity).ok()?)))
+    else {
+        return;
+    };
+    if door.open {
+        return;
+    }
+    let Ok(mut inventory) = players.get_single_mut() else {
+        return;
+    };
+
+    if door.locked {
+        let Some(key_id) = door.key_id.clone() else {
+            info!("This door can't be unlocked");
+            return;
+        };
+        if !inventory.take_key(&key_id) {
+            info!("This door needs the {} key", key_id);
+            return;
+        }
+        save_data.keys = inventory.keys.clone();
+    }
+
+    open_door(&mut commands, entity, &mut door, &asset_server);
+    save_data.opened_doors.insert(door.iid.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::test_harness::TestApp;
+
+    // A door right next to the player, locked with the "gate" key
+    fn door_next_to_player(test_app: &mut TestApp) -> Entity {
+        let player = test_app.player();
+        let player_position = test_app.get::<Transform>(player).unwrap().translation;
+
+        let door = test_app
+            .app
+            .world
+            .spawn((
+                Door {
+                    iid: "test-door".to_string(),
+                    locked: true,
+                    key_id: Some("gate".to_string()),
+                    open: false,
+                },
+                Collider::cuboid(16.0, 8.0),
+                TransformBundle::from(Transform::from_translation(
+                    player_position + Vec3::new(0.0, 24.0, 0.0),
+                )),
+            ))
+            .id();
+        test_app.step(1);
+        door
+    }
+
+    #[test]
+    fn locked_door_needs_its_key() {
+        let mut test_app = TestApp::sandbox();
+        let door = door_next_to_player(&mut test_app);
+
+        test_app.press_key(KeyCode::E);
+        test_app.step(1);
+
+        assert!(test_app.get::<Door>(door).unwrap().locked);
+        assert!(test_app.get::<Collider>(door).is_some());
+    }
+
+    #[test]
+    fn key_unlocks_door_and_is_used_up() {
+        let mut test_app = TestApp::sandbox();
+        let door = door_next_to_player(&mut test_app);
+        let player = test_app.player();
+        test_app
+            .get_mut::<Inventory>(player)
+            .unwrap()
+            .add_key("gate");
+
+        test_app.press_key(KeyCode::E);
+        test_app.step(1);
+
+        assert!(test_app.get::<Door>(door).unwrap().open);
+        assert!(test_app.get::<Collider>(door).is_none());
+        assert!(!test_app.get::<Inventory>(player).unwrap().has_key("gate"));
+
+        let save_data = test_app.app.world.resource::<SaveData>();
+        assert!(save_data.opened_doors.contains("test-door"));
+        assert!(save_data.keys.is_empty());
+    }
+
+    // Walks the player onto the sandbox's "gate" key
+    fn pick_up_key(test_app: &mut TestApp) -> Entity {
+        let world = &mut test_app.app.world;
+        let (key, key_transform) = world
+            .query_filtered::<(Entity, &Transform), With<KeyPickup>>()
+            .single(world);
+        let key_position = key_transform.translation;
+
+        let player = test_app.player();
+        test_app.get_mut::<Transform>(player).unwrap().translation = key_position;
+        test_app.step(3);
+        key
+    }
+
+    #[test]
+    fn walking_over_a_key_picks_it_up() {
+        let mut test_app = TestApp::sandbox();
+        let key = pick_up_key(&mut test_app);
+        let player = test_app.player();
+
+        assert!(!test_app.exists(key));
+        assert!(test_app.get::<Inventory>(player).unwrap().has_key("gate"));
+
+        let save_data = test_app.app.world.resource::<SaveData>();
+        assert_eq!(save_data.keys.get("gate"), Some(&1));
+        assert_eq!(save_data.collected_keys.len(), 1);
+    }
+
+    #[test]
+    fn collected_keys_dont_come_back() {
+        let mut test_app = TestApp::sandbox();
+        pick_up_key(&mut test_app);
+        let save_data = test_app.app.world.resource::<SaveData>().clone();
+
+        // The next session, with the same save
+        let mut test_app = TestApp::new();
+        test_app.app.insert_resource(save_data);
+        test_app.load_sandbox();
+        test_app.step(1);
+
+        let world = &mut test_app.app.world;
+        let keys = world.query::<&KeyPickup>().iter(world).count();
+        assert_eq!(keys, 0);
+        let player = test_app.player();
+        assert!(test_app.get::<Inventory>(player).unwrap().has_key("gate"));
+    }
+}
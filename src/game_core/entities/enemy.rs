@@ -1,13 +1,202 @@
 use super::entity_components::Enemy;
-use crate::game_core::entities::entity_components::{EnemyBundle, EnemyStats, Player};
+use crate::game_core::attack::DamageEvent;
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::entities::enemy_archetypes::{
+    AiProfile, EnemyArchetypeAtlases, EnemyArchetypes,
+};
+use crate::game_core::entities::entity_components::{EnemyAi, EnemyStats, Player};
+use crate::game_core::projectile::spawn_projectile;
+use crate::game_core::visibility::LineOfSight;
+use crate::game_core::y_sort::YSort;
 use bevy::prelude::*;
-use bevy_ecs_ldtk::prelude::*;
 use bevy_rapier2d::prelude::*;
+use rand::Rng;
 
-pub fn on_enemy_spawned(mut enemies: Query<(&Enemy, &mut EnemyStats), Added<Enemy>>) {
-    for (enemy, mut current_stats) in enemies.iter_mut() {
-        current_stats.health = enemy.stats.health;
-        current_stats.attack_chance = enemy.stats.attack_chance;
-        current_stats.attack_power = enemy.stats.attack_power;
+// Gives new enemies the sprite, collider, stats and AI of their archetype
+pub fn on_enemy_spawned(
+    mut commands: Commands,
+    mut enemies: Query<(Entity, &Enemy, &mut EnemyStats), Added<Enemy>>,
+    archetypes: Res<EnemyArchetypes>,
+    atlases: Option<Res<EnemyArchetypeAtlases>>,
+) {
+    for (entity, enemy, mut current_stats) in enemies.iter_mut() {
+        let archetype = archetypes.get(&enemy.archetype);
+
+        current_stats.health = enemy.overrides.health.unwrap_or(archetype.health);
+        current_stats.attack_chance = enemy
+            .overrides
+            .attack_chance
+            .unwrap_or(archetype.attack_chance);
+        current_stats.attack_power = enemy
+            .overrides
+            .attack_power
+            .unwrap_or(archetype.attack_power);
+        current_stats.xp = enemy.overrides.xp.unwrap_or(archetype.xp);
+
+        commands.entity(entity).insert((
+            archetype.collider_bundle(),
+            EnemyAi {
+                profile: archetype.ai,
+                attack_timer: 0.0,
+            },
+            YSort::new(archetype.sort_anchor()),
+        ));
+
+        // Not there in the headless tests, which don't load any images
+        if let Some(atlas) = atlases
+            .as_ref()
+            .and_then(|atlases| atlases.0.get(&enemy.archetype))
+        {
+            commands.entity(entity).insert((
+                atlas.clone(),
+                TextureAtlasSprite::new(archetype.sprite.index),
+            ));
+        }
+    }
+}
+
+pub fn enemy_ai_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    players: Query<(Entity, &GlobalTransform), With<Player>>,
+    mut enemies: Query<(
+        Entity,
+        &mut EnemyAi,
+        &EnemyStats,
+        &GlobalTransform,
+        &mut Velocity,
+    )>,
+    mut damage_events: EventWriter<DamageEvent>,
+    line_of_sight: LineOfSight,
+) {
+    let Ok((player, player_transform)) = players.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation().truncate();
+    let mut rng = rand::thread_rng();
+
+    for (entity, mut ai, stats, transform, mut velocity) in enemies.iter_mut() {
+        let (aggro_radius, attack_range, speed, attack_cooldown, projectile) = match ai.profile {
+            AiProfile::Passive => continue,
+            AiProfile::Melee {
+                aggro_radius,
+                attack_range,
+                speed,
+                attack_cooldown,
+            } => (aggro_radius, attack_range, speed, attack_cooldown, None),
+            AiProfile::Ranged {
+                aggro_radius,
+                attack_range,
+                speed,
+                attack_cooldown,
+                projectile,
+            } => (
+                aggro_radius,
+                attack_range,
+                speed,
+                attack_cooldown,
+                Some(projectile),
+            ),
+        };
+
+        ai.attack_timer = (ai.attack_timer - time.delta_seconds()).max(0.0);
+
+        let position = transform.translation().truncate();
+        let to_player = player_position - position;
+        let distance = to_player.length();
+        // Enemies don't notice the player through walls
+        let sees_player =
+            distance <= aggro_radius && line_of_sight.can_see(position, player_position);
+
+        if !sees_player {
+            velocity.linvel = Vec2::ZERO;
+        } else if distance <= attack_range {
+            velocity.linvel = Vec2::ZERO;
+
+            if ai.attack_timer <= 0.0 {
+                ai.attack_timer = attack_cooldown;
+                if rng.gen_range(0.0..100.0) < stats.attack_chance {
+                    match projectile {
+                        // Whether it lands is up to the projectile now
+                        Some(projectile) => {
+                            spawn_projectile(
+                                &mut commands,
+                                projectile,
+                                entity,
+                                stats.attack_power,
+                                Layer::EnemyAttack,
+                                position,
+                                to_player,
+                            );
+                        }
+                        None => damage_events.send(DamageEvent {
+                            target: player,
+                            amount: stats.attack_power,
+                        }),
+                    }
+                }
+            }
+        } else {
+            velocity.linvel = to_player / distance * speed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::entities::entity_components::Health;
+    use crate::game_core::entities::player::HEALTH;
+    use crate::game_core::test_harness::TestApp;
+
+    fn player_position(test_app: &mut TestApp) -> Vec2 {
+        let player = test_app.player();
+        test_app
+            .get::<Transform>(player)
+            .unwrap()
+            .translation
+            .truncate()
+    }
+
+    #[test]
+    fn slimes_stay_put() {
+        let mut test_app = TestApp::sandbox();
+        let slime = test_app.enemy_named("Terry Pratchet").unwrap();
+        let start = test_app.get::<Transform>(slime).unwrap().translation;
+
+        test_app.step(60);
+
+        assert_eq!(test_app.get::<Transform>(slime).unwrap().translation, start);
+        assert!(matches!(
+            test_app.get::<EnemyAi>(slime).unwrap().profile,
+            AiProfile::Passive
+        ));
+    }
+
+    #[test]
+    fn ldtk_stats_override_the_archetype() {
+        let mut test_app = TestApp::sandbox();
+        let slime = test_app.enemy_named("Terry Pratchet").unwrap();
+
+        assert_eq!(test_app.get::<EnemyStats>(slime).unwrap().health, 34.5);
+    }
+
+    #[test]
+    fn skeleton_chases_and_hits_the_player() {
+        let mut test_app = TestApp::sandbox();
+        let start = player_position(&mut test_app) + Vec2::new(60.0, 0.0);
+        let skeleton = test_app.spawn_enemy("skeleton", start);
+
+        test_app.step(30);
+        let closer = test_app
+            .get::<Transform>(skeleton)
+            .unwrap()
+            .translation
+            .truncate();
+        assert!(closer.distance(player_position(&mut test_app)) < 60.0);
+
+        test_app.step(240);
+        let player = test_app.player();
+        assert!(test_app.get::<Health>(player).unwrap().0 < HEALTH);
     }
 }
@@ -0,0 +1,178 @@
+// Enemy types, loaded from assets/data/enemy_archetypes.ron
+// The "Archetype" field of an LDtk Enemy picks one of these, and the stat
+// fields of the LDtk entity override the archetype's defaults when set
+
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::entities::entity_components::ColliderBundle;
+use crate::game_core::projectile::ProjectileSpec;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub static ENEMY_ARCHETYPES_PATH: &str = "assets/data/enemy_archetypes.ron";
+
+// Used for enemies without an Archetype field, or with one that isn't defined
+pub static DEFAULT_ARCHETYPE: &str = "slime";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ArchetypeSprite {
+    // Relative to assets/
+    pub path: String,
+    pub tile_size: (f32, f32),
+    pub columns: usize,
+    pub rows: usize,
+    // Tile of the sheet shown while standing still
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum AiProfile {
+    // Stays where it was placed
+    Passive,
+    // Walks towards the player once it is within `aggro_radius`, and hits it
+    // every `attack_cooldown` seconds while within `attack_range`
+    Melee {
+        aggro_radius: f32,
+        attack_range: f32,
+        speed: f32,
+        attack_cooldown: f32,
+    },
+    // Same as Melee, but stops at `attack_range` and shoots projectiles instead
+    Ranged {
+        aggro_radius: f32,
+        attack_range: f32,
+        speed: f32,
+        attack_cooldown: f32,
+        projectile: ProjectileSpec,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnemyArchetype {
+    pub sprite: ArchetypeSprite,
+    pub collider_size: (f32, f32),
+    pub health: f32,
+    pub attack_power: f32,
+    // Chance of an attack landing, between 0 and 100
+    pub attack_chance: f32,
+    pub ai: AiProfile,
+    // Experience the player gets for killing it
+    #[serde(default)]
+    pub xp: u32,
+    // From the enemy's position to its feet, for y-sorting
+    #[serde(default)]
+    pub sort_anchor: Option<f32>,
+}
+
+impl Default for EnemyArchetype {
+    fn default() -> Self {
+        // The slime every enemy was before archetypes existed
+        EnemyArchetype {
+            sprite: ArchetypeSprite {
+                path: "tile_sets/mystic_woods_free_2.1/sprites/characters/slime_sprite.png"
+                    .to_string(),
+                tile_size: (16.0, 12.0),
+                columns: 1,
+                rows: 1,
+                index: 0,
+            },
+            collider_size: (16.0, 12.0),
+            health: 10.0,
+            attack_power: 10.0,
+            attack_chance: 0.0,
+            ai: AiProfile::Passive,
+            xp: 0,
+            sort_anchor: None,
+        }
+    }
+}
+
+impl EnemyArchetype {
+    // The bottom of the collider when not set
+    pub fn sort_anchor(&self) -> f32 {
+        self.sort_anchor.unwrap_or(-self.collider_size.1 / 2.0)
+    }
+
+    pub fn collider_bundle(&self) -> ColliderBundle {
+        // Only enemies that walk around need to be pushed by physics
+        let rigid_body = match self.ai {
+            AiProfile::Passive => RigidBody::Fixed,
+            AiProfile::Melee { .. } | AiProfile::Ranged { .. } => RigidBody::Dynamic,
+        };
+
+        ColliderBundle {
+            collider: Collider::cuboid(self.collider_size.0 / 2.0, self.collider_size.1 / 2.0),
+            rigid_body,
+            velocity: Velocity::zero(),
+            rotation_constraints: LockedAxes::ROTATION_LOCKED,
+            gravity_scale: GravityScale(0.0),
+            collision_groups: Layer::Enemy.collision_groups(),
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct EnemyArchetypes {
+    pub archetypes: HashMap<String, EnemyArchetype>,
+}
+
+impl Default for EnemyArchetypes {
+    fn default() -> Self {
+        EnemyArchetypes {
+            archetypes: HashMap::from([(DEFAULT_ARCHETYPE.to_string(), EnemyArchetype::default())]),
+        }
+    }
+}
+
+impl EnemyArchetypes {
+    pub fn load() -> EnemyArchetypes {
+        load_ron_or_default(ENEMY_ARCHETYPES_PATH)
+    }
+
+    pub fn get(&self, name: &str) -> EnemyArchetype {
+        match self.archetypes.get(name) {
+            Some(archetype) => archetype.clone(),
+            None => {
+                warn!(
+                    "Unknown enemy archetype {}, using {}",
+                    name, DEFAULT_ARCHETYPE
+                );
+                self.archetypes
+                    .get(DEFAULT_ARCHETYPE)
+                    .cloned()
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+// Sprite sheet of every archetype, built once at startup
+#[derive(Resource, Default)]
+pub struct EnemyArchetypeAtlases(pub HashMap<String, Handle<TextureAtlas>>);
+
+pub fn setup_enemy_archetypes(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    archetypes: Res<EnemyArchetypes>,
+) {
+    let mut atlases = EnemyArchetypeAtlases::default();
+
+    for (name, archetype) in archetypes.archetypes.iter() {
+        let sprite = &archetype.sprite;
+        let atlas = TextureAtlas::from_grid(
+            asset_server.load(sprite.path.as_str()),
+            Vec2::new(sprite.tile_size.0, sprite.tile_size.1),
+            sprite.columns,
+            sprite.rows,
+            None,
+            None,
+        );
+        atlases.0.insert(name.clone(), texture_atlases.add(atlas));
+    }
+
+    commands.insert_resource(atlases);
+}
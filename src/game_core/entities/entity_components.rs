@@ -1,22 +1,29 @@
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::entities::attacks::AttackKind;
+use crate::game_core::entities::enemy_archetypes::{AiProfile, DEFAULT_ARCHETYPE};
 use crate::game_core::entities::player::{
-    ATTACK_DURATION_SECS, ATTACK_POWER, DAMAGE_RESISTANCE, HEALTH, PLAYER_MOVEMENT,
+    ATTACK_POWER, DAMAGE_RESISTANCE, HEALTH, PLAYER_MOVEMENT,
 };
+use crate::game_core::world::SpeedModifier;
 use bevy::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_rapier2d::prelude::*;
-use std::cell::RefMut;
-use std::os::macos::raw::stat;
-use std::time::Duration;
+use serde::Deserialize;
+use std::collections::HashMap;
 
 // COMMON COMPONENTS
 #[derive(Component, Clone, Default)]
-pub struct Health(f32);
+pub struct Health(pub f32);
 
 #[derive(Component, Clone, Default)]
-pub struct AttackPower(f32);
+pub struct AttackPower(pub f32);
 
 #[derive(Component, Clone, Default)]
-pub struct DamageResistance(f32);
+pub struct DamageResistance(pub f32);
+
+// Health can't go above this, levelling up raises it
+#[derive(Component, Clone, Default)]
+pub struct MaxHealth(pub f32);
 
 #[derive(Clone, Debug, Default, Bundle)]
 pub struct ColliderBundle {
@@ -25,6 +32,7 @@ pub struct ColliderBundle {
     pub velocity: Velocity,
     pub rotation_constraints: LockedAxes,
     pub gravity_scale: GravityScale,
+    pub collision_groups: CollisionGroups,
 }
 
 impl From<EntityInstance> for ColliderBundle {
@@ -36,13 +44,31 @@ impl From<EntityInstance> for ColliderBundle {
                 velocity: Velocity::zero(),
                 rotation_constraints: LockedAxes::ROTATION_LOCKED,
                 gravity_scale: GravityScale(0.0),
+                collision_groups: Layer::Player.collision_groups(),
+            },
+            "Door" => ColliderBundle {
+                collider: Collider::cuboid(32.0 / 2.0, 16.0 / 2.0),
+                rigid_body: RigidBody::Fixed,
+                velocity: Velocity::zero(),
+                rotation_constraints: LockedAxes::ROTATION_LOCKED,
+                gravity_scale: GravityScale(0.0),
+                collision_groups: Layer::World.collision_groups(),
+            },
+            "Key" => ColliderBundle {
+                collider: Collider::cuboid(8.0 / 2.0, 8.0 / 2.0),
+                rigid_body: RigidBody::Fixed,
+                velocity: Velocity::zero(),
+                rotation_constraints: LockedAxes::ROTATION_LOCKED,
+                gravity_scale: GravityScale(0.0),
+                collision_groups: Layer::Pickup.collision_groups(),
             },
-            "Enemy" => ColliderBundle {
-                collider: Collider::cuboid(16.0 / 2.0, 12.0 / 2.0),
+            "Chest" => ColliderBundle {
+                collider: Collider::cuboid(14.0 / 2.0, 10.0 / 2.0),
                 rigid_body: RigidBody::Fixed,
                 velocity: Velocity::zero(),
                 rotation_constraints: LockedAxes::ROTATION_LOCKED,
                 gravity_scale: GravityScale(0.0),
+                collision_groups: Layer::World.collision_groups(),
             },
             _ => ColliderBundle::default(),
         }
@@ -53,11 +79,133 @@ impl From<EntityInstance> for ColliderBundle {
 #[derive(Component, Clone, Default)]
 pub struct Player;
 
-#[derive(Component, Clone, Default)]
-pub struct AttackTimer(pub Timer);
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AttackPhase {
+    #[default]
+    Ready,
+    // The hitbox is out
+    Active,
+    // Waiting for the next attack to be allowed, presses are buffered meanwhile
+    Recovery,
+}
+
+// Where the player is in their current attack chain
+#[derive(Component, Clone, Debug, Default)]
+pub struct ComboState {
+    // Chain being played, None once the chain window has run out
+    pub kind: Option<AttackKind>,
+    // Step of the chain played last
+    pub step: usize,
+    pub phase: AttackPhase,
+    // Seconds left in the phase, or in the chain window when Ready
+    pub timer: f32,
+    // Attack pressed during the last step, played as soon as recovery ends
+    pub buffered: Option<AttackKind>,
+}
+
+// Items the player is carrying
+#[derive(Component, Clone, Debug, Default)]
+pub struct Inventory {
+    // Number of keys of each key id
+    pub keys: HashMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn add_key(&mut self, key_id: &str) {
+        *self.keys.entry(key_id.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn has_key(&self, key_id: &str) -> bool {
+        self.keys.get(key_id).copied().unwrap_or(0) > 0
+    }
+
+    // Uses up one key, returns false if the player doesn't have any
+    pub fn take_key(&mut self, key_id: &str) -> bool {
+        match self.keys.get_mut(key_id) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                if *count == 0 {
+                    self.keys.remove(key_id);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// The player's level, and the XP gathered towards the next one
+#[derive(Component, Clone, Debug)]
+pub struct Experience {
+    pub level: u32,
+    pub xp: u32,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Experience { level: 1, xp: 0 }
+    }
+}
+
+// Spent by attacks and dashes, which can't be done without enough of it
+#[derive(Component, Clone, Debug)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    // Per second
+    pub regen_rate: f32,
+    // Seconds after spending any before it starts coming back
+    pub regen_delay: f32,
+    pub since_used: f32,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Stamina {
+            current: 100.0,
+            max: 100.0,
+            regen_rate: 25.0,
+            regen_delay: 0.8,
+            since_used: 0.0,
+        }
+    }
+}
 
+impl Stamina {
+    // Returns false, spending nothing, if there isn't enough left
+    pub fn try_spend(&mut self, cost: f32) -> bool {
+        if self.current < cost {
+            return false;
+        }
+        self.current -= cost;
+        if cost > 0.0 {
+            self.since_used = 0.0;
+        }
+        true
+    }
+}
+
+// How much the player's gear weighs, which sets their stamina (see stamina.rs)
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum EquipmentLoad {
+    Light,
+    #[default]
+    Medium,
+    Heavy,
+}
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Dash {
+    pub direction: Vec2,
+    // Seconds left of the current dash, 0 when not dashing
+    pub remaining: f32,
+}
+
+// Marks the sensor spawned as a child of the player while attacking
 #[derive(Component, Clone, Default)]
-pub struct IsAttacking(pub bool);
+pub struct AttackHitbox {
+    pub damage: f32,
+}
 
 #[derive(Component, Clone, Default)]
 pub struct PlayerMovementInfo {
@@ -69,23 +217,30 @@ pub struct PlayerMovementInfo {
 #[derive(Bundle, Clone, Default)]
 pub struct PlayerStatsBundle {
     pub health: Health,
+    pub max_health: MaxHealth,
     pub attack_power: AttackPower,
     pub damage_resistance: DamageResistance,
     pub player_movement_info: PlayerMovementInfo,
-    pub attack_duration: AttackTimer,
+    pub combo: ComboState,
+    pub stamina: Stamina,
+    pub equipment_load: EquipmentLoad,
+    pub dash: Dash,
+    pub experience: Experience,
 }
 
 impl From<EntityInstance> for PlayerStatsBundle {
     fn from(entity_instance: EntityInstance) -> Self {
         PlayerStatsBundle {
             health: Health(HEALTH),
+            max_health: MaxHealth(HEALTH),
             attack_power: AttackPower(ATTACK_POWER),
             damage_resistance: DamageResistance(DAMAGE_RESISTANCE),
             player_movement_info: PLAYER_MOVEMENT.clone(),
-            attack_duration: AttackTimer(Timer::new(
-                Duration::from_millis((ATTACK_DURATION_SECS * 1000.0) as u64),
-                TimerMode::Repeating,
-            )),
+            combo: ComboState::default(),
+            stamina: Stamina::default(),
+            equipment_load: EquipmentLoad::default(),
+            dash: Dash::default(),
+            experience: Experience::default(),
         }
     }
 }
@@ -108,7 +263,9 @@ pub struct PlayerBundle {
     #[from_entity_instance]
     entity_instance: EntityInstance,
 
-    is_attacking: IsAttacking,
+    speed_modifier: SpeedModifier,
+
+    inventory: Inventory,
 
     #[worldly]
     worldly: Worldly,
@@ -118,28 +275,35 @@ pub struct PlayerBundle {
 #[derive(Component, Clone, Default)]
 pub struct Enemy {
     pub name: String,
-    pub stats: EnemyStats,
+    // Which of the EnemyArchetypes this is
+    pub archetype: String,
+    // Stats set on the LDtk entity, the archetype's defaults are used for the rest
+    pub overrides: EnemyStatOverrides,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EnemyStatOverrides {
+    pub health: Option<f32>,
+    pub attack_power: Option<f32>,
+    pub attack_chance: Option<f32>,
+    pub xp: Option<u32>,
 }
 
 impl Enemy {
-    // Return elements are (attacking back, died)
-    pub fn attack(&self, incoming_damage: f32, mut enemy_stats: &mut Mut<EnemyStats>)
-    /*-> (bool, bool)*/
-    {
+    // Returns true if the enemy died from this attack, apply_damage_system
+    // reports it with the hit and death events
+    pub fn attack(&self, incoming_damage: f32, enemy_stats: &mut Mut<EnemyStats>) -> bool {
         enemy_stats.health -= incoming_damage;
-        let mut dead = false;
-        if enemy_stats.health <= 0.0 {
-            dead = true;
-            enemy_stats.health = self.stats.health;
-            println!("DEAD!!!");
-        }
-        println!("Health is now: {}", enemy_stats.health);
+        enemy_stats.health <= 0.0
     }
 }
 
 impl From<EntityInstance> for Enemy {
     fn from(entity_instance: EntityInstance) -> Self {
-        let mut enemy = Enemy::default();
+        let mut enemy = Enemy {
+            archetype: DEFAULT_ARCHETYPE.to_string(),
+            ..default()
+        };
         for field in entity_instance.field_instances {
             match field.identifier.as_ref() {
                 "Name" => {
@@ -147,19 +311,29 @@ impl From<EntityInstance> for Enemy {
                         enemy.name = name;
                     }
                 }
+                "Archetype" => {
+                    if let FieldValue::String(Some(archetype)) = field.value {
+                        enemy.archetype = archetype;
+                    }
+                }
                 "Health" => {
-                    if let FieldValue::Float(Some(health)) = field.value {
-                        enemy.stats.health = health;
+                    if let FieldValue::Float(health) = field.value {
+                        enemy.overrides.health = health;
                     }
                 }
                 "Attack_Power" => {
-                    if let FieldValue::Float(Some(power)) = field.value {
-                        enemy.stats.attack_power = power;
+                    if let FieldValue::Float(power) = field.value {
+                        enemy.overrides.attack_power = power;
                     }
                 }
                 "Attack_Chance" => {
-                    if let FieldValue::Float(Some(chance)) = field.value {
-                        enemy.stats.attack_chance = chance;
+                    if let FieldValue::Float(chance) = field.value {
+                        enemy.overrides.attack_chance = chance;
+                    }
+                }
+                "XP" => {
+                    if let FieldValue::Int(xp) = field.value {
+                        enemy.overrides.xp = xp.map(|xp| xp.max(0) as u32);
                     }
                 }
                 _ => {}
@@ -179,19 +353,26 @@ pub struct EnemyStats {
     pub health: f32,
     pub attack_power: f32,
     pub attack_chance: f32,
+    // Given to the player on death
+    pub xp: u32,
+}
+
+// What the enemy does on its own, set from its archetype when it spawns
+#[derive(Component, Clone, Debug)]
+pub struct EnemyAi {
+    pub profile: AiProfile,
+    // Seconds until the next attack
+    pub attack_timer: f32,
 }
 
+// The sprite sheet, collider and AI come from the archetype, see on_enemy_spawned
 #[derive(Clone, Default, Bundle, LdtkEntity)]
 pub struct EnemyBundle {
-    #[sprite_bundle("tile_sets/mystic_woods_free_2.1/sprites/characters/slime_sprite.png")]
     #[bundle]
-    pub sprite_bundle: SpriteBundle,
+    pub sprite_sheet_bundle: SpriteSheetBundle,
 
     #[from_entity_instance]
     pub enemy: Enemy,
 
     pub enemy_current_stats: EnemyStats,
-
-    #[from_entity_instance]
-    pub collider_bundle: ColliderBundle,
 }
@@ -0,0 +1,103 @@
+// What pressing interact acts on: the closest NPC, closed door or closed
+// chest in reach. Only the target reacts to a press, so standing between a
+// door and an NPC doesn't open the door and start a conversation at once
+
+use crate::game_core::entities::chest::Chest;
+use crate::game_core::entities::door::Door;
+use crate::game_core::entities::entity_components::Player;
+use crate::game_core::entities::npc::{Npc, TALK_DISTANCE};
+use bevy::prelude::*;
+
+// How close the player has to be to a door or chest to use it
+pub static INTERACT_DISTANCE: f32 = 28.0;
+
+#[derive(Resource, Default)]
+pub struct InteractionTarget(pub Option<Entity>);
+
+pub fn interaction_target_system(
+    players: Query<&GlobalTransform, With<Player>>,
+    npcs: Query<(Entity, &GlobalTransform), With<Npc>>,
+    doors: Query<(Entity, &Door, &GlobalTransform)>,
+    chests: Query<(Entity, &Chest, &GlobalTransform)>,
+    mut target: ResMut<InteractionTarget>,
+) {
+    let closest = players.get_single().ok().and_then(|player_transform| {
+        let player_position = player_transform.translation().truncate();
+        let npcs = npcs
+            .iter()
+            .map(|(entity, transform)| (entity, transform, TALK_DISTANCE));
+        let doors = doors
+            .iter()
+            .filter(|(_, door, _)| !door.open)
+            .map(|(entity, _, transform)| (entity, transform, INTERACT_DISTANCE));
+        let chests = chests
+            .iter()
+            .filter(|(_, chest, _)| !chest.open)
+            .map(|(entity, _, transform)| (entity, transform, INTERACT_DISTANCE));
+
+        npcs.chain(doors)
+            .chain(chests)
+            .map(|(entity, transform, reach)| {
+                let distance = transform.translation().truncate().distance(player_position);
+                (entity, distance, reach)
+            })
+            .filter(|&(_, distance, reach)| distance <= reach)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entity, _, _)| entity)
+    });
+
+    if target.0 != closest {
+        target.0 = closest;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::dialogue::Conversation;
+    use crate::game_core::test_harness::TestApp;
+    use bevy_rapier2d::prelude::*;
+
+    #[test]
+    fn only_the_closest_one_reacts() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        let position = test_app.get::<Transform>(player).unwrap().translation;
+
+        let npc = test_app
+            .app
+            .world
+            .spawn((
+                Npc {
+                    name: "Hermit".to_string(),
+                    dialogue: "hermit".to_string(),
+                },
+                TransformBundle::from(Transform::from_translation(
+                    position + Vec3::new(16.0, 0.0, 0.0),
+                )),
+            ))
+            .id();
+        let door = test_app
+            .app
+            .world
+            .spawn((
+                Door::default(),
+                Collider::cuboid(16.0, 8.0),
+                TransformBundle::from(Transform::from_translation(
+                    position + Vec3::new(0.0, 24.0, 0.0),
+                )),
+            ))
+            .id();
+        test_app.step(1);
+        assert_eq!(
+            test_app.app.world.resource::<InteractionTarget>().0,
+            Some(npc)
+        );
+
+        test_app.press_key(KeyCode::E);
+        test_app.step(1);
+
+        assert!(test_app.app.world.resource::<Conversation>().is_active());
+        assert!(!test_app.get::<Door>(door).unwrap().open);
+    }
+}
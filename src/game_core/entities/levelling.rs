@@ -0,0 +1,319 @@
+// Player experience and levels, tuned from assets/data/levelling.ron
+// Killing enemies gives XP, and every level gained grows the player's stats
+
+use crate::game_core::attack::EnemyDeathEvent;
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::entities::entity_components::{
+    AttackPower, DamageResistance, Experience, Health, MaxHealth, Player,
+};
+use crate::game_core::save::SaveData;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+pub static LEVELLING_PATH: &str = "assets/data/levelling.ron";
+
+// Added to the player's stats on every level up
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct StatGrowth {
+    pub health: f32,
+    pub attack_power: f32,
+    pub damage_resistance: f32,
+}
+
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LevellingConfig {
+    // Entry n is the XP needed to go from level n + 1 to n + 2, so the player
+    // stops at level xp_curve.len() + 1
+    pub xp_curve: Vec<u32>,
+    pub growth: StatGrowth,
+}
+
+impl Default for LevellingConfig {
+    fn default() -> Self {
+        LevellingConfig {
+            xp_curve: vec![20, 40, 70, 110, 160],
+            growth: StatGrowth {
+                health: 2.0,
+                attack_power: 2.0,
+                damage_resistance: 1.0,
+            },
+        }
+    }
+}
+
+impl LevellingConfig {
+    pub fn load() -> LevellingConfig {
+        load_ron_or_default(LEVELLING_PATH)
+    }
+
+    // None once the player is at the highest level
+    pub fn xp_to_next(&self, level: u32) -> Option<u32> {
+        self.xp_curve.get(level.saturating_sub(1) as usize).copied()
+    }
+}
+
+// XP that doesn't come from a kill, like quest rewards
+pub struct XpRewardEvent {
+    pub xp: u32,
+}
+
+// Sent for every level gained, for the UI and audio
+pub struct LevelUpEvent {
+    pub player: Entity,
+    pub level: u32,
+}
+
+// Also gives back the stats of levels restored from the save data
+fn grow_stats(
+    growth: StatGrowth,
+    levels: u32,
+    max_health: &mut MaxHealth,
+    health: &mut Health,
+    power: &mut AttackPower,
+    resistance: &mut DamageResistance,
+) {
+    let levels = levels as f32;
+    max_health.0 += growth.health * levels;
+    health.0 = (health.0 + growth.health * levels).min(max_health.0);
+    power.0 += growth.attack_power * levels;
+    resistance.0 += growth.damage_resistance * levels;
+}
+
+pub fn experience_system(
+    mut death_events: EventReader<EnemyDeathEvent>,
+    mut reward_events: EventReader<XpRewardEvent>,
+    config: Res<LevellingConfig>,
+    mut save_data: ResMut<SaveData>,
+    mut players: Query<
+        (
+            Entity,
+            &mut Experience,
+            &mut Health,
+            &mut MaxHealth,
+            &mut AttackPower,
+            &mut DamageResistance,
+        ),
+        With<Player>,
+    >,
+    mut level_up_events: EventWriter<LevelUpEvent>,
+) {
+    let Ok((player, mut experience, mut health, mut max_health, mut power, mut resistance)) =
+        players.get_single_mut()
+    else {
+        return;
+    };
+
+    let gains = death_events
+        .iter()
+        .map(|death| death.xp)
+        .chain(reward_events.iter().map(|reward| reward.xp));
+    let mut gained = false;
+    for xp in gains {
+        gained = true;
+        experience.xp += xp;
+
+        // A big kill can be worth several levels at once
+        while let Some(needed) = config.xp_to_next(experience.level) {
+            if experience.xp < needed {
+                break;
+            }
+            experience.xp -= needed;
+            experience.level += 1;
+
+            grow_stats(
+                config.growth,
+                1,
+                &mut max_health,
+                &mut health,
+                &mut power,
+                &mut resistance,
+            );
+
+            level_up_events.send(LevelUpEvent {
+                player,
+                level: experience.level,
+            });
+        }
+    }
+
+    // Only when something changed, touching the save data writes it to disk
+    if gained {
+        save_data.level = experience.level;
+        save_data.xp = experience.xp;
+    }
+}
+
+// Gives a newly spawned player the level from the save data, along with the
+// stats it grew on the way there
+pub fn restore_level_system(
+    save_data: Res<SaveData>,
+    config: Res<LevellingConfig>,
+    mut players: Query<
+        (
+            &mut Experience,
+            &mut Health,
+            &mut MaxHealth,
+            &mut AttackPower,
+            &mut DamageResistance,
+        ),
+        Added<Player>,
+    >,
+) {
+    if save_data.level == 0 {
+        return;
+    }
+
+    for (mut experience, mut health, mut max_health, mut power, mut resistance) in
+        players.iter_mut()
+    {
+        let levels = save_data.level.saturating_sub(experience.level);
+        experience.level = save_data.level;
+        experience.xp = save_data.xp;
+        grow_stats(
+            config.growth,
+            levels,
+            &mut max_health,
+            &mut health,
+            &mut power,
+            &mut resistance,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::attack::DamageEvent;
+    use crate::game_core::entities::entity_components::EnemyStats;
+    use crate::game_core::entities::player::{ATTACK_POWER, HEALTH};
+    use crate::game_core::test_harness::TestApp;
+
+    fn kill(test_app: &mut TestApp, xp: u32) {
+        test_app.app.world.send_event(EnemyDeathEvent {
+            enemy: Entity::from_raw(u32::MAX),
+            position: Vec2::ZERO,
+            name: "test".to_string(),
+            archetype: "test".to_string(),
+            xp,
+        });
+        test_app.step(1);
+    }
+
+    fn damage_taken(test_app: &mut TestApp, amount: f32) -> f32 {
+        let player = test_app.player();
+        let before = test_app.get::<Health>(player).unwrap().0;
+        test_app.app.world.send_event(DamageEvent {
+            target: player,
+            amount,
+        });
+        test_app.step(1);
+        before - test_app.get::<Health>(player).unwrap().0
+    }
+
+    #[test]
+    fn xp_carries_over_into_the_next_level() {
+        let mut test_app = TestApp::sandbox();
+        let config = test_app.app.world.resource::<LevellingConfig>().clone();
+        let first = config.xp_to_next(1).unwrap();
+
+        kill(&mut test_app, first - 1);
+        let player = test_app.player();
+        assert_eq!(test_app.get::<Experience>(player).unwrap().level, 1);
+
+        kill(&mut test_app, 3);
+        let experience = test_app.get::<Experience>(player).unwrap();
+        assert_eq!(experience.level, 2);
+        assert_eq!(experience.xp, 2);
+        assert_eq!(
+            test_app.get::<MaxHealth>(player).unwrap().0,
+            HEALTH + config.growth.health
+        );
+        assert_eq!(
+            test_app.get::<AttackPower>(player).unwrap().0,
+            ATTACK_POWER + config.growth.attack_power
+        );
+        // Already at full health, so it only goes up to the new max
+        assert_eq!(
+            test_app.get::<Health>(player).unwrap().0,
+            HEALTH + config.growth.health
+        );
+    }
+
+    #[test]
+    fn levelled_players_take_less_damage() {
+        let mut fresh = TestApp::sandbox();
+        let mut levelled = TestApp::sandbox();
+        let config = levelled.app.world.resource::<LevellingConfig>().clone();
+        kill(&mut levelled, config.xp_to_next(1).unwrap());
+
+        let fresh_damage = damage_taken(&mut fresh, 5.0);
+        let levelled_damage = damage_taken(&mut levelled, 5.0);
+
+        assert!(fresh_damage > 0.0);
+        assert!(levelled_damage < fresh_damage);
+    }
+
+    #[test]
+    fn level_and_xp_go_into_the_save_data() {
+        let mut test_app = TestApp::sandbox();
+        let config = test_app.app.world.resource::<LevellingConfig>().clone();
+
+        kill(&mut test_app, config.xp_to_next(1).unwrap() + 1);
+
+        let save_data = test_app.app.world.resource::<SaveData>();
+        assert_eq!(save_data.level, 2);
+        assert_eq!(save_data.xp, 1);
+    }
+
+    #[test]
+    fn saved_level_comes_back_with_its_stats() {
+        let mut test_app = TestApp::new();
+        test_app.app.insert_resource(SaveData {
+            level: 3,
+            xp: 5,
+            ..default()
+        });
+        test_app.load_sandbox();
+        let config = test_app.app.world.resource::<LevellingConfig>().clone();
+
+        let player = test_app.player();
+        let experience = test_app.get::<Experience>(player).unwrap();
+        assert_eq!(experience.level, 3);
+        assert_eq!(experience.xp, 5);
+        let max_health = test_app.get::<MaxHealth>(player).unwrap().0;
+        assert_eq!(max_health, HEALTH + 2.0 * config.growth.health);
+        assert_eq!(test_app.get::<Health>(player).unwrap().0, max_health);
+    }
+
+    #[test]
+    fn stops_at_the_level_cap() {
+        let mut test_app = TestApp::sandbox();
+        let config = test_app.app.world.resource::<LevellingConfig>().clone();
+
+        kill(&mut test_app, u32::MAX / 2);
+
+        let player = test_app.player();
+        let experience = test_app.get::<Experience>(player).unwrap();
+        assert_eq!(experience.level, config.xp_curve.len() as u32 + 1);
+    }
+
+    #[test]
+    fn killing_an_enemy_gives_its_xp() {
+        let mut test_app = TestApp::sandbox();
+        let slime = test_app.enemy_named("Terry Pratchet").unwrap();
+        let xp = test_app.get::<EnemyStats>(slime).unwrap().xp;
+        assert!(xp > 0);
+
+        test_app.app.world.send_event(DamageEvent {
+            target: slime,
+            amount: 1000.0,
+        });
+        test_app.step(2);
+
+        let player = test_app.player();
+        let experience = test_app.get::<Experience>(player).unwrap();
+        assert_eq!(experience.level, 1);
+        assert_eq!(experience.xp, xp);
+    }
+}
@@ -1,3 +1,13 @@
+pub mod attacks;
+pub mod chest;
+pub mod door;
 pub mod enemy;
+pub mod enemy_archetypes;
 pub mod entity_components;
+pub mod interaction;
+pub mod levelling;
+pub mod npc;
 pub mod player;
+pub mod spawner;
+pub mod stamina;
+pub mod trigger;
@@ -0,0 +1,186 @@
+// Characters the player can talk to, placed in LDtk as Npc entities
+// Their "Dialogue" field names a file under assets/dialogue, see dialogue.rs
+
+use crate::game_core::dialogue::{Conversation, Dialogues};
+use crate::game_core::entities::entity_components::{Inventory, Player};
+use crate::game_core::entities::interaction::InteractionTarget;
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::save::SaveData;
+use crate::game_core::settings::{Action, Settings};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+// How close the player has to be to talk to an NPC
+pub static TALK_DISTANCE: f32 = 32.0;
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Npc {
+    pub name: String,
+    // File name under assets/dialogue, without the extension
+    pub dialogue: String,
+}
+
+impl From<EntityInstance> for Npc {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut npc = Npc::default();
+        for field in entity_instance.field_instances {
+            match (field.identifier.as_ref(), field.value) {
+                ("Name", FieldValue::String(Some(name))) => npc.name = name,
+                ("Dialogue", FieldValue::String(Some(dialogue))) => npc.dialogue = dialogue,
+                _ => {}
+            }
+        }
+        npc
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct NpcBundle {
+    // There are no NPC sprites in the tilesets yet, so they look like the player
+    #[sprite_bundle("tile_sets/mystic_woods_free_2.1/sprites/characters/player_sprite.png")]
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+
+    #[from_entity_instance]
+    pub npc: Npc,
+
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+}
+
+pub fn npc_interaction_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    target: Res<InteractionTarget>,
+    dialogues: Res<Dialogues>,
+    npcs: Query<&Npc>,
+    mut conversation: ResMut<Conversation>,
+    mut input_locks: ResMut<InputLocks>,
+    mut save_data: ResMut<SaveData>,
+    mut players: Query<&mut Inventory, With<Player>>,
+) {
+    // A conversation that ended this frame shouldn't start again from the same press
+    if conversation.is_changed()
+        || conversation.is_active()
+        || input_locks.is_locked()
+        || !settings
+            .key_bindings
+            .just_pressed(Action::Interact, &keyboard_input)
+    {
+        return;
+    }
+
+    let Some((entity, npc)) = target
+        .0
+        .and_then(|entity| Some((entity, npcs.get(entity).ok()?)))
+    else {
+        return;
+    };
+    let Ok(mut inventory) = players.get_single_mut() else {
+        return;
+    };
+
+    conversation.start(
+        entity,
+        &npc.name,
+        dialogues.get(&npc.dialogue),
+        &mut save_data,
+        &mut inventory,
+        &mut input_locks,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::test_harness::TestApp;
+
+    fn sandbox_with_hermit() -> TestApp {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        let position = test_app.get::<Transform>(player).unwrap().translation;
+        test_app.app.world.spawn((
+            Npc {
+                name: "Hermit".to_string(),
+                dialogue: "hermit".to_string(),
+            },
+            TransformBundle::from(Transform::from_translation(
+                position + Vec3::new(20.0, 0.0, 0.0),
+            )),
+        ));
+        test_app.step(1);
+        test_app
+    }
+
+    fn node(test_app: &TestApp) -> Option<String> {
+        let conversation = test_app.app.world.resource::<Conversation>();
+        conversation.0.as_ref().map(|active| active.node.clone())
+    }
+
+    fn interact(test_app: &mut TestApp, times: u32) {
+        for _ in 0..times {
+            test_app.press_key(KeyCode::E);
+            test_app.step(1);
+            test_app.release_key(KeyCode::E);
+            test_app.step(1);
+        }
+    }
+
+    // Skips the typewriter, then picks the choice
+    fn pick(test_app: &mut TestApp, key: KeyCode) {
+        interact(test_app, 1);
+        test_app.press_key(key);
+        test_app.step(1);
+        test_app.release_key(key);
+        test_app.step(1);
+    }
+
+    #[test]
+    fn talking_stops_the_player_until_the_end() {
+        let mut test_app = sandbox_with_hermit();
+
+        // Start, show the whole greeting, move on
+        interact(&mut test_app, 3);
+        assert_eq!(node(&test_app).as_deref(), Some("offer"));
+        assert!(test_app.app.world.resource::<InputLocks>().is_locked());
+
+        pick(&mut test_app, KeyCode::Key2);
+        assert_eq!(node(&test_app).as_deref(), Some("bye"));
+        interact(&mut test_app, 2);
+
+        assert_eq!(node(&test_app), None);
+        assert!(!test_app.app.world.resource::<InputLocks>().is_locked());
+    }
+
+    #[test]
+    fn choice_sets_a_flag_and_gives_a_key() {
+        let mut test_app = sandbox_with_hermit();
+
+        interact(&mut test_app, 3);
+        pick(&mut test_app, KeyCode::Key1);
+
+        let player = test_app.player();
+        assert!(test_app.get::<Inventory>(player).unwrap().has_key("gate"));
+        let save_data = test_app.app.world.resource::<SaveData>();
+        assert!(save_data.flags.contains("hermit_gave_key"));
+        assert!(save_data.keys.contains_key("gate"));
+    }
+
+    #[test]
+    fn flags_pick_the_branch() {
+        let mut test_app = sandbox_with_hermit();
+        test_app
+            .app
+            .world
+            .resource_mut::<SaveData>()
+            .flags
+            .insert("hermit_gave_key".to_string());
+
+        interact(&mut test_app, 1);
+
+        assert_eq!(node(&test_app).as_deref(), Some("again"));
+        // Giving the key back needs the key
+        let conversation = test_app.app.world.resource::<Conversation>();
+        assert_eq!(conversation.0.as_ref().unwrap().choices, vec![1]);
+    }
+}
@@ -1,14 +1,21 @@
+use crate::game_core::attack::DamageEvent;
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::entities::attacks::{AttackDefinitions, AttackKind};
 use crate::game_core::entities::entity_components::{
-    AttackPower, AttackTimer, DamageResistance, Enemy, EnemyStats, Health, IsAttacking, Player,
-    PlayerMovementInfo,
+    AttackHitbox, AttackPhase, AttackPower, ComboState, DamageResistance, Dash, Enemy, EnemyStats,
+    Health, Player, PlayerMovementInfo, Stamina,
 };
+use crate::game_core::entities::stamina::StaminaConfig;
+use crate::game_core::projectile::{spawn_projectile, ProjectileSpec};
+use crate::game_core::settings::{Action, Settings};
+use crate::game_core::world::{BreakableTile, SpeedModifier};
 use bevy::prelude::*;
 use bevy::sprite::MaterialMesh2dBundle;
 use bevy_ecs_ldtk::prelude::*;
-use bevy_inspector_egui::egui::Event::Key;
 use bevy_rapier2d::prelude::*;
 use bevy_rapier2d::rapier::geometry::CollisionEventFlags;
 use std::cell::RefMut;
+use std::collections::HashSet;
 
 use super::entity_components;
 
@@ -20,40 +27,85 @@ pub static PLAYER_MOVEMENT: PlayerMovementInfo = PlayerMovementInfo {
     deceleration: 700.0,
     max_speed: 200.0,
 };
-pub static ATTACK_DURATION_SECS: f32 = 0.1;
+pub static RANGED_ATTACK_POWER: f32 = 5.0;
+pub static RANGED_ATTACK_COOLDOWN_SECS: f32 = 0.5;
+pub static RANGED_ATTACK: ProjectileSpec = ProjectileSpec {
+    speed: 300.0,
+    lifetime: 1.0,
+    radius: 3.0,
+    color: (0.6, 0.9, 1.0),
+};
+
+// While anything holds a lock (menus, cutscenes...) the player ignores movement and attack input
+#[derive(Resource, Default)]
+pub struct InputLocks(HashSet<&'static str>);
+
+impl InputLocks {
+    pub fn lock(&mut self, reason: &'static str) {
+        self.0.insert(reason);
+    }
+
+    pub fn unlock(&mut self, reason: &'static str) {
+        self.0.remove(reason);
+    }
+
+    pub fn is_locked(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
 
 pub fn player_movement_system(
     time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
-    mut player: Query<(&mut Velocity, &PlayerMovementInfo, &Transform), With<Player>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
+    mut player: Query<
+        (
+            &mut Velocity,
+            &PlayerMovementInfo,
+            &Transform,
+            Option<&SpeedModifier>,
+            &Dash,
+        ),
+        With<Player>,
+    >,
 ) {
+    let bindings = &settings.key_bindings;
+    let pressed =
+        |action: Action| !input_locks.is_locked() && bindings.pressed(action, &keyboard_input);
+
     // Get player information
-    if let Ok((mut player_velocity, movement, transform)) = player.get_single_mut() {
+    if let Ok((mut player_velocity, movement, transform, speed_modifier, dash)) =
+        player.get_single_mut()
+    {
+        // dash_system is in control of the velocity until the dash is over
+        if dash.remaining > 0.0 {
+            return;
+        }
+
+        // Slow tiles etc lower the top speed
+        let max_speed = movement.max_speed * speed_modifier.map_or(1.0, |modifier| modifier.0);
+
         // If any movement keys pressed
-        if keyboard_input.any_pressed([
-            KeyCode::Up,
-            KeyCode::W,
-            KeyCode::Left,
-            KeyCode::A,
-            KeyCode::Down,
-            KeyCode::S,
-            KeyCode::Right,
-            KeyCode::D,
-        ]) {
+        if pressed(Action::MoveUp)
+            || pressed(Action::MoveDown)
+            || pressed(Action::MoveLeft)
+            || pressed(Action::MoveRight)
+        {
             // Acceleration is the rate at which the speed increases
             let mut acceleration = Vec2::ZERO;
 
             // set the acceleration based on inputs
-            if keyboard_input.pressed(KeyCode::Up) || keyboard_input.pressed(KeyCode::W) {
+            if pressed(Action::MoveUp) {
                 acceleration += Vec2::new(0.0, 1.0) * movement.acceleration;
             }
-            if keyboard_input.pressed(KeyCode::Right) || keyboard_input.pressed(KeyCode::D) {
+            if pressed(Action::MoveRight) {
                 acceleration += Vec2::new(1.0, 0.0) * movement.acceleration;
             }
-            if keyboard_input.pressed(KeyCode::Down) || keyboard_input.pressed(KeyCode::S) {
+            if pressed(Action::MoveDown) {
                 acceleration += Vec2::new(0.0, -1.0) * movement.acceleration;
             }
-            if keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::A) {
+            if pressed(Action::MoveLeft) {
                 acceleration += Vec2::new(-1.0, 0.0) * movement.acceleration;
             }
 
@@ -83,8 +135,8 @@ pub fn player_movement_system(
             player_velocity.linvel += acceleration * movement.acceleration * time.delta_seconds();
 
             // Cap speed if beyond max
-            if player_velocity.linvel.length() > movement.max_speed {
-                player_velocity.linvel = player_velocity.linvel.normalize() * movement.max_speed;
+            if player_velocity.linvel.length() > max_speed {
+                player_velocity.linvel = player_velocity.linvel.normalize() * max_speed;
             }
             // If no input AND if after this loop the velocity will be greater than zero
         } else if player_velocity.linvel.length() - (movement.deceleration * time.delta_seconds())
@@ -102,81 +154,212 @@ pub fn player_movement_system(
     }
 }
 
+// Plays the player's attack chains, see attacks.rs for how they are defined
 pub fn attack_handler_system(
     mut commands: Commands,
     mut player: Query<
         (
-            &mut IsAttacking,
-            &Transform,
-            &mut AttackTimer,
-            Option<&Children>,
             Entity,
+            &mut ComboState,
+            &mut Stamina,
+            &AttackPower,
+            Option<&Children>,
         ),
         With<Player>,
     >,
+    hitboxes: Query<Entity, With<AttackHitbox>>,
     time: Res<Time>,
+    attacks: Res<AttackDefinitions>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mouse: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
 ) {
-    if let Ok((mut attacking, transform, mut attack_timer, children, entity)) =
-        player.get_single_mut()
+    let Ok((entity, mut combo, mut stamina, attack_power, children)) = player.get_single_mut()
+    else {
+        return;
+    };
+
+    let pressed = if input_locks.is_locked() {
+        None
+    } else if mouse.just_pressed(settings.key_bindings.attack) {
+        Some(AttackKind::Light)
+    } else if settings
+        .key_bindings
+        .just_pressed(Action::HeavyAttack, &keyboard_input)
     {
-        if mouse.just_pressed(MouseButton::Left) && !attacking.0 {
-            attacking.0 = true;
-            if attacking.0 {
-                let collider = commands
-                    .spawn((
-                        MaterialMesh2dBundle {
-                            mesh: meshes
-                                .add(Mesh::from(shape::Quad::new(Vec2::new(40.0, 40.0))))
-                                .into(),
-                            material: materials.add(ColorMaterial::from(Color::BLUE)),
-                            ..default()
-                        },
-                        Collider::cuboid(20.0, 20.0),
-                        ActiveEvents::COLLISION_EVENTS,
-                        Sensor,
-                    ))
-                    .id();
-
-                commands.entity(entity).add_child(collider);
+        Some(AttackKind::Heavy)
+    } else {
+        None
+    };
+
+    combo.timer = (combo.timer - time.delta_seconds()).max(0.0);
+
+    // Presses while the current step plays wait for it to finish, the latest one wins
+    if pressed.is_some() && combo.phase != AttackPhase::Ready {
+        combo.buffered = pressed;
+    }
+
+    match combo.phase {
+        AttackPhase::Active if combo.timer <= 0.0 => {
+            // The player can have other children (particles etc), so only
+            // remove the ones that are attack hitboxes
+            if let Some(children) = children {
+                for &child in children.iter() {
+                    if hitboxes.contains(child) {
+                        commands.entity(child).despawn_recursive();
+                    }
+                }
             }
+            let recovery = combo
+                .kind
+                .and_then(|kind| attacks.step(kind, combo.step))
+                .map_or(0.0, |step| step.recovery);
+            combo.phase = AttackPhase::Recovery;
+            combo.timer = recovery;
         }
-
-        if attacking.0 {
-            attack_timer.0.tick(time.delta());
+        AttackPhase::Recovery if combo.timer <= 0.0 => {
+            combo.phase = AttackPhase::Ready;
+            combo.timer = attacks.chain_window;
+        }
+        AttackPhase::Ready if combo.timer <= 0.0 => {
+            combo.kind = None;
         }
+        _ => {}
+    }
 
-        if attack_timer.0.finished() {
-            let mut count = 0;
-            for i in children.unwrap().iter() {
-                count += 1;
-            }
-            if let Some(&child) = children
-                .expect("NO CHILD WHEN KILLING COLLIDER CHILD")
-                .get(0)
-            {
-                attack_timer.0.reset();
-                commands.entity(child).despawn_recursive();
-            }
+    if combo.phase != AttackPhase::Ready {
+        return;
+    }
+    let Some(kind) = combo.buffered.take().or(pressed) else {
+        return;
+    };
 
-            attacking.0 = false;
-        }
+    let chain = attacks.chain(kind);
+    if chain.is_empty() {
+        return;
+    }
+    // The same attack continues the chain, starting over after its last step
+    let step_index = match combo.kind {
+        Some(current) if current == kind => (combo.step + 1) % chain.len(),
+        _ => 0,
+    };
+    let step = chain[step_index];
+    if !stamina.try_spend(step.stamina_cost) {
+        return;
+    }
+
+    let hitbox = commands
+        .spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(step.hitbox.mesh()).into(),
+                material: materials.add(ColorMaterial::from(Color::BLUE)),
+                ..default()
+            },
+            step.hitbox.collider(),
+            ActiveEvents::COLLISION_EVENTS,
+            Sensor,
+            Layer::PlayerAttack.collision_groups(),
+            AttackHitbox {
+                damage: attack_power.0 * step.damage_multiplier,
+            },
+        ))
+        .id();
+    commands.entity(entity).add_child(hitbox);
+
+    combo.kind = Some(kind);
+    combo.step = step_index;
+    combo.phase = AttackPhase::Active;
+    combo.timer = step.active;
+}
+
+// Fires a projectile towards the cursor, or the way the player is moving when
+// there is no cursor in the window
+pub fn ranged_attack_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut cooldown: Local<f32>,
+    mouse: Res<Input<MouseButton>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
+    // Not there in the headless tests
+    windows: Option<Res<Windows>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    stamina_config: Res<StaminaConfig>,
+    mut player: Query<(Entity, &GlobalTransform, &Velocity, &mut Stamina), With<Player>>,
+) {
+    *cooldown = (*cooldown - time.delta_seconds()).max(0.0);
+
+    if *cooldown > 0.0
+        || input_locks.is_locked()
+        || !mouse.just_pressed(settings.key_bindings.secondary_attack)
+    {
+        return;
+    }
+
+    let Ok((entity, transform, velocity, mut stamina)) = player.get_single_mut() else {
+        return;
+    };
+    let position = transform.translation().truncate();
+
+    let cursor = windows
+        .as_ref()
+        .and_then(|windows| windows.get_primary())
+        .and_then(|window| window.cursor_position())
+        .zip(cameras.iter().next())
+        .and_then(|(cursor, (camera, camera_transform))| {
+            camera.viewport_to_world(camera_transform, cursor)
+        })
+        .map(|ray| ray.origin.truncate());
+    let direction = match cursor {
+        Some(target) => target - position,
+        None if velocity.linvel != Vec2::ZERO => velocity.linvel,
+        None => Vec2::X,
+    };
+    if direction == Vec2::ZERO || !stamina.try_spend(stamina_config.ranged_attack_cost) {
+        return;
     }
+
+    spawn_projectile(
+        &mut commands,
+        RANGED_ATTACK,
+        entity,
+        RANGED_ATTACK_POWER,
+        Layer::PlayerProjectile,
+        position,
+        direction,
+    );
+    *cooldown = RANGED_ATTACK_COOLDOWN_SECS;
 }
 
 pub fn Attack_Collider_Handler(
     mut collision_events: EventReader<CollisionEvent>,
-    mut enemy_data: Query<(&Enemy, &mut EnemyStats, Entity)>,
+    targets: Query<Entity, Or<(With<Enemy>, With<BreakableTile>)>>,
+    hitboxes: Query<&AttackHitbox>,
+    mut damage_events: EventWriter<DamageEvent>,
 ) {
     for collision_event in collision_events.iter() {
         match collision_event {
             CollisionEvent::Started(entity1, entity2, flag) => {
                 if flag == &CollisionEventFlags::SENSOR {
-                    let this = enemy_data.get_mut(*entity1);
-                    if let Ok((enemy, mut stats, entity)) = enemy_data.get_mut(*entity1) {
-                        enemy.attack(ATTACK_POWER, &mut stats);
+                    // Rapier doesn't guarantee which of the two entities comes first
+                    let (target_entity, other) = if targets.contains(*entity1) {
+                        (*entity1, *entity2)
+                    } else {
+                        (*entity2, *entity1)
+                    };
+
+                    // Projectiles are sensors too, projectile_hit_system handles those
+                    if !targets.contains(target_entity) {
+                        continue;
+                    }
+                    if let Ok(hitbox) = hitboxes.get(other) {
+                        damage_events.send(DamageEvent {
+                            target: target_entity,
+                            amount: hitbox.damage,
+                        });
                     }
                 }
             }
@@ -184,3 +367,209 @@ pub fn Attack_Collider_Handler(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::save::SaveData;
+    use crate::game_core::test_harness::TestApp;
+    use crate::game_core::world::LevelTileGrid;
+
+    fn player_velocity(test_app: &mut TestApp) -> Vec2 {
+        let player = test_app.player();
+        test_app.get::<Velocity>(player).unwrap().linvel
+    }
+
+    #[test]
+    fn player_accelerates_up_to_max_speed() {
+        let mut test_app = TestApp::sandbox();
+        test_app.press_key(KeyCode::D);
+
+        test_app.step(5);
+        let early = player_velocity(&mut test_app);
+        assert!(early.x > 0.0);
+        assert!(early.x < PLAYER_MOVEMENT.max_speed);
+        assert_eq!(early.y, 0.0);
+
+        test_app.step(5);
+        assert!(player_velocity(&mut test_app).x > early.x);
+
+        test_app.step(60);
+        let top = player_velocity(&mut test_app);
+        assert!((top.length() - PLAYER_MOVEMENT.max_speed).abs() < 0.01);
+    }
+
+    #[test]
+    fn player_diagonal_speed_is_capped() {
+        let mut test_app = TestApp::sandbox();
+        test_app.press_key(KeyCode::W);
+        test_app.press_key(KeyCode::D);
+
+        test_app.step(60);
+        let velocity = player_velocity(&mut test_app);
+        assert!(velocity.length() <= PLAYER_MOVEMENT.max_speed + 0.01);
+        assert!((velocity.x - velocity.y).abs() < 0.01);
+    }
+
+    #[test]
+    fn player_decelerates_to_a_stop() {
+        let mut test_app = TestApp::sandbox();
+        test_app.press_key(KeyCode::D);
+        test_app.step(60);
+        test_app.release_key(KeyCode::D);
+
+        test_app.step(5);
+        let slowing = player_velocity(&mut test_app);
+        assert!(slowing.x > 0.0);
+        assert!(slowing.x < PLAYER_MOVEMENT.max_speed);
+
+        // 200 px/s at 700 px/s^2 takes under 0.3 seconds
+        test_app.step(30);
+        assert_eq!(player_velocity(&mut test_app), Vec2::ZERO);
+    }
+
+    #[test]
+    fn swing_kills_slime() {
+        let mut test_app = TestApp::sandbox();
+        let slime = test_app.enemy_named("Terry Pratchet").unwrap();
+        let player = test_app.player();
+
+        // Put the player right next to the slime, inside the reach of the attack
+        let slime_position = test_app.get::<Transform>(slime).unwrap().translation;
+        test_app.get_mut::<Transform>(player).unwrap().translation =
+            slime_position - Vec3::new(20.0, 0.0, 0.0);
+        test_app.get_mut::<EnemyStats>(slime).unwrap().health = ATTACK_POWER;
+        test_app.step(1);
+
+        test_app.click_mouse(MouseButton::Left);
+        test_app.step(5);
+
+        assert!(!test_app.exists(slime));
+    }
+
+    #[test]
+    fn swing_breaks_breakable_tile() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        let player_position = test_app.get::<Transform>(player).unwrap().translation;
+
+        let coords = GridCoords { x: 3, y: 4 };
+        let (level, tile) = test_app.spawn_breakable_tile(
+            coords,
+            player_position.truncate() + Vec2::new(20.0, 0.0),
+            ATTACK_POWER,
+        );
+        test_app.step(1);
+
+        test_app.click_mouse(MouseButton::Left);
+        test_app.step(5);
+
+        assert!(!test_app.exists(tile));
+        assert_eq!(
+            test_app.get::<LevelTileGrid>(level).unwrap().value(coords),
+            0
+        );
+        let save_data = test_app.app.world.resource::<SaveData>();
+        assert_eq!(
+            save_data.broken_tiles("test-level").collect::<Vec<_>>(),
+            vec![coords]
+        );
+    }
+
+    #[test]
+    fn swing_out_of_reach_misses() {
+        let mut test_app = TestApp::sandbox();
+        let slime = test_app.enemy_named("Terry Pratchet").unwrap();
+        let health = test_app.get::<EnemyStats>(slime).unwrap().health;
+
+        test_app.click_mouse(MouseButton::Left);
+        test_app.step(10);
+
+        assert!(test_app.exists(slime));
+        assert_eq!(test_app.get::<EnemyStats>(slime).unwrap().health, health);
+    }
+
+    fn combo(test_app: &mut TestApp) -> ComboState {
+        let player = test_app.player();
+        test_app.get::<ComboState>(player).unwrap().clone()
+    }
+
+    fn hitbox_damage(test_app: &mut TestApp) -> Vec<f32> {
+        test_app
+            .app
+            .world
+            .query::<&AttackHitbox>()
+            .iter(&test_app.app.world)
+            .map(|hitbox| hitbox.damage)
+            .collect()
+    }
+
+    #[test]
+    fn second_press_in_chain_window_continues_combo() {
+        let mut test_app = TestApp::sandbox();
+        let attacks = test_app.app.world.resource::<AttackDefinitions>().clone();
+        let second = attacks
+            .step(AttackKind::Light, 1)
+            .unwrap()
+            .damage_multiplier;
+
+        test_app.click_mouse(MouseButton::Left);
+        // Past the first step's active and recovery time
+        test_app.step(14);
+        assert_eq!(combo(&mut test_app).phase, AttackPhase::Ready);
+        test_app.click_mouse(MouseButton::Left);
+
+        let state = combo(&mut test_app);
+        assert_eq!(state.step, 1);
+        assert_eq!(state.phase, AttackPhase::Active);
+        assert_eq!(hitbox_damage(&mut test_app), vec![ATTACK_POWER * second]);
+    }
+
+    #[test]
+    fn combo_starts_over_after_chain_window() {
+        let mut test_app = TestApp::sandbox();
+
+        test_app.click_mouse(MouseButton::Left);
+        test_app.step(60);
+        test_app.click_mouse(MouseButton::Left);
+
+        assert_eq!(combo(&mut test_app).step, 0);
+    }
+
+    #[test]
+    fn press_during_recovery_is_buffered() {
+        let mut test_app = TestApp::sandbox();
+
+        test_app.click_mouse(MouseButton::Left);
+        test_app.step(8);
+        assert_eq!(combo(&mut test_app).phase, AttackPhase::Recovery);
+        test_app.click_mouse(MouseButton::Left);
+        assert_eq!(combo(&mut test_app).step, 0);
+
+        // Plays as soon as recovery is over, without pressing again
+        test_app.step(5);
+        let state = combo(&mut test_app);
+        assert_eq!(state.step, 1);
+        assert_eq!(state.phase, AttackPhase::Active);
+    }
+
+    #[test]
+    fn heavy_attack_starts_its_own_chain() {
+        let mut test_app = TestApp::sandbox();
+        let attacks = test_app.app.world.resource::<AttackDefinitions>().clone();
+        let heavy = attacks
+            .step(AttackKind::Heavy, 0)
+            .unwrap()
+            .damage_multiplier;
+
+        test_app.click_mouse(MouseButton::Left);
+        test_app.step(14);
+        test_app.press_key(KeyCode::Q);
+        test_app.step(1);
+
+        let state = combo(&mut test_app);
+        assert_eq!(state.kind, Some(AttackKind::Heavy));
+        assert_eq!(state.step, 0);
+        assert_eq!(hitbox_damage(&mut test_app), vec![ATTACK_POWER * heavy]);
+    }
+}
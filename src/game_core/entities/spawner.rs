@@ -0,0 +1,413 @@
+// Spawners create enemies of an archetype in waves once the player comes close
+// Spawners with an encounter id lock the room: every Barrier with the same id
+// blocks the way until all the waves of all those spawners are cleared
+
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::entities::enemy_archetypes::DEFAULT_ARCHETYPE;
+use crate::game_core::entities::entity_components::{Enemy, EnemyStats, Player};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+static BARRIER_COLOR: Color = Color::rgba(0.45, 0.3, 0.2, 0.9);
+
+// Spawned enemies appear up to this far from the spawner
+pub static SPAWN_SPREAD: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SpawnerState {
+    // Waiting for the player to come within the activation radius
+    #[default]
+    Idle,
+    Active {
+        wave: u32,
+        spawned_in_wave: u32,
+        // Seconds until the next enemy can spawn
+        timer: f32,
+    },
+    // Every wave has been spawned and killed
+    Cleared,
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Spawner {
+    pub archetype: String,
+    // Enemies in each wave
+    pub count: u32,
+    // Seconds between two enemies
+    pub interval: f32,
+    pub max_alive: u32,
+    pub activation_radius: f32,
+    pub waves: u32,
+    pub encounter_id: Option<String>,
+    pub state: SpawnerState,
+}
+
+impl Default for Spawner {
+    fn default() -> Self {
+        Spawner {
+            archetype: DEFAULT_ARCHETYPE.to_string(),
+            count: 3,
+            interval: 1.0,
+            max_alive: 3,
+            activation_radius: 96.0,
+            waves: 1,
+            encounter_id: None,
+            state: SpawnerState::Idle,
+        }
+    }
+}
+
+impl From<EntityInstance> for Spawner {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut spawner = Spawner::default();
+        for field in entity_instance.field_instances {
+            match (field.identifier.as_ref(), field.value) {
+                ("Archetype", FieldValue::String(Some(archetype))) => spawner.archetype = archetype,
+                ("Count", FieldValue::Int(Some(count))) => spawner.count = count.max(0) as u32,
+                ("Interval", FieldValue::Float(Some(interval))) => spawner.interval = interval,
+                ("Max_alive", FieldValue::Int(Some(max_alive))) => {
+                    spawner.max_alive = max_alive.max(1) as u32
+                }
+                ("Activation_radius", FieldValue::Float(Some(radius))) => {
+                    spawner.activation_radius = radius
+                }
+                ("Waves", FieldValue::Int(Some(waves))) => spawner.waves = waves.max(1) as u32,
+                ("Encounter_id", FieldValue::String(encounter_id)) => {
+                    spawner.encounter_id = encounter_id
+                }
+                _ => {}
+            }
+        }
+        spawner
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct SpawnerBundle {
+    #[from_entity_instance]
+    pub spawner: Spawner,
+}
+
+// Put on enemies created by a spawner, so it knows how many of its own are alive
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SpawnedBy(pub Entity);
+
+// Blocks the way while the encounter with the same id is going on
+#[derive(Component, Clone, Debug, Default)]
+pub struct Barrier {
+    pub encounter_id: String,
+    pub size: Vec2,
+}
+
+impl From<EntityInstance> for Barrier {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut barrier = Barrier {
+            size: Vec2::new(entity_instance.width as f32, entity_instance.height as f32),
+            ..default()
+        };
+        for field in entity_instance.field_instances {
+            if field.identifier == "Encounter_id" {
+                if let FieldValue::String(Some(encounter_id)) = field.value {
+                    barrier.encounter_id = encounter_id;
+                }
+            }
+        }
+        barrier
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct BarrierBundle {
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+
+    #[from_entity_instance]
+    pub barrier: Barrier,
+}
+
+// Same components as an Enemy placed in LDtk, on_enemy_spawned does the rest
+// The parent is the one of the spawner or trigger, the level's entity layer,
+// so the enemy goes away with the level
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    archetype: &str,
+    translation: Vec3,
+    parent: Option<&Parent>,
+) -> Entity {
+    let enemy = commands
+        .spawn((
+            SpriteSheetBundle {
+                transform: Transform::from_translation(translation),
+                ..default()
+            },
+            Enemy {
+                name: archetype.to_string(),
+                archetype: archetype.to_string(),
+                ..default()
+            },
+            EnemyStats::default(),
+        ))
+        .id();
+    if let Some(parent) = parent {
+        commands.entity(parent.get()).add_child(enemy);
+    }
+    enemy
+}
+
+pub fn spawner_activation_system(
+    players: Query<&GlobalTransform, With<Player>>,
+    mut spawners: Query<(&mut Spawner, &GlobalTransform)>,
+) {
+    let Ok(player_transform) = players.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation().truncate();
+
+    for (mut spawner, transform) in spawners.iter_mut() {
+        if spawner.state != SpawnerState::Idle {
+            continue;
+        }
+
+        let distance = transform.translation().truncate().distance(player_position);
+        if distance <= spawner.activation_radius {
+            spawner.state = SpawnerState::Active {
+                wave: 0,
+                spawned_in_wave: 0,
+                timer: 0.0,
+            };
+        }
+    }
+}
+
+pub fn spawner_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut spawners: Query<(Entity, &mut Spawner, &Transform, Option<&Parent>)>,
+    spawned: Query<&SpawnedBy>,
+) {
+    let mut alive: HashMap<Entity, u32> = HashMap::new();
+    for spawned_by in spawned.iter() {
+        *alive.entry(spawned_by.0).or_insert(0) += 1;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    for (spawner_entity, mut spawner, transform, parent) in spawners.iter_mut() {
+        let SpawnerState::Active {
+            mut wave,
+            mut spawned_in_wave,
+            mut timer,
+        } = spawner.state
+        else {
+            continue;
+        };
+        let alive = alive.get(&spawner_entity).copied().unwrap_or(0);
+
+        timer = (timer - time.delta_seconds()).max(0.0);
+
+        if spawned_in_wave < spawner.count && alive < spawner.max_alive && timer <= 0.0 {
+            let offset = Vec2::new(
+                rng.gen_range(-SPAWN_SPREAD..=SPAWN_SPREAD),
+                rng.gen_range(-SPAWN_SPREAD..=SPAWN_SPREAD),
+            );
+
+            let translation = transform.translation + offset.extend(0.0);
+            let enemy = spawn_enemy(&mut commands, &spawner.archetype, translation, parent);
+            commands.entity(enemy).insert(SpawnedBy(spawner_entity));
+
+            spawned_in_wave += 1;
+            timer = spawner.interval;
+        } else if spawned_in_wave >= spawner.count && alive == 0 {
+            // Enemies spawned this frame only show up in `alive` next frame,
+            // hence the else
+            wave += 1;
+            spawned_in_wave = 0;
+            timer = spawner.interval;
+        }
+
+        spawner.state = if wave >= spawner.waves {
+            SpawnerState::Cleared
+        } else {
+            SpawnerState::Active {
+                wave,
+                spawned_in_wave,
+                timer,
+            }
+        };
+    }
+}
+
+// Raises the barriers of every encounter with an active spawner, and drops
+// them once all of its spawners are cleared
+pub fn barrier_system(
+    mut commands: Commands,
+    spawners: Query<&Spawner>,
+    mut barriers: Query<(
+        Entity,
+        &Barrier,
+        &mut Sprite,
+        &mut Visibility,
+        Option<&Collider>,
+    )>,
+) {
+    let active_encounters: HashSet<&str> = spawners
+        .iter()
+        .filter(|spawner| matches!(spawner.state, SpawnerState::Active { .. }))
+        .filter_map(|spawner| spawner.encounter_id.as_deref())
+        .collect();
+
+    for (entity, barrier, mut sprite, mut visibility, collider) in barriers.iter_mut() {
+        let active = active_encounters.contains(barrier.encounter_id.as_str());
+        if visibility.is_visible != active {
+            visibility.is_visible = active;
+        }
+
+        match (active, collider.is_some()) {
+            (true, false) => {
+                sprite.color = BARRIER_COLOR;
+                sprite.custom_size = Some(barrier.size);
+                commands.entity(entity).insert((
+                    Collider::cuboid(barrier.size.x / 2.0, barrier.size.y / 2.0),
+                    RigidBody::Fixed,
+                    Layer::World.collision_groups(),
+                ));
+            }
+            (false, true) => {
+                commands.entity(entity).remove::<Collider>();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::test_harness::TestApp;
+
+    fn spawn_spawner(test_app: &mut TestApp, spawner: Spawner) -> Entity {
+        let player = test_app.player();
+        let position = test_app.get::<Transform>(player).unwrap().translation;
+        test_app
+            .app
+            .world
+            .spawn((
+                spawner,
+                TransformBundle::from(Transform::from_translation(
+                    position + Vec3::new(0.0, 60.0, 0.0),
+                )),
+            ))
+            .id()
+    }
+
+    fn spawned_enemies(test_app: &mut TestApp, spawner: Entity) -> Vec<Entity> {
+        test_app
+            .app
+            .world
+            .query::<(Entity, &SpawnedBy)>()
+            .iter(&test_app.app.world)
+            .filter(|(_, spawned_by)| spawned_by.0 == spawner)
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    fn kill_all(test_app: &mut TestApp, enemies: Vec<Entity>) {
+        for enemy in enemies {
+            test_app.app.world.entity_mut(enemy).despawn_recursive();
+        }
+    }
+
+    #[test]
+    fn spawned_enemies_get_archetype_stats() {
+        let mut test_app = TestApp::sandbox();
+        let spawner = spawn_spawner(
+            &mut test_app,
+            Spawner {
+                count: 5,
+                max_alive: 2,
+                interval: 0.1,
+                ..default()
+            },
+        );
+
+        test_app.step(60);
+
+        let enemies = spawned_enemies(&mut test_app, spawner);
+        assert_eq!(enemies.len(), 2);
+        for enemy in enemies {
+            assert_eq!(test_app.get::<EnemyStats>(enemy).unwrap().health, 10.0);
+            assert!(test_app.get::<Collider>(enemy).is_some());
+        }
+    }
+
+    #[test]
+    fn spawner_out_of_range_stays_idle() {
+        let mut test_app = TestApp::sandbox();
+        let spawner = spawn_spawner(
+            &mut test_app,
+            Spawner {
+                activation_radius: 10.0,
+                ..default()
+            },
+        );
+
+        test_app.step(60);
+
+        assert_eq!(
+            test_app.get::<Spawner>(spawner).unwrap().state,
+            SpawnerState::Idle
+        );
+        assert!(spawned_enemies(&mut test_app, spawner).is_empty());
+    }
+
+    #[test]
+    fn encounter_locks_barriers_until_every_wave_is_cleared() {
+        let mut test_app = TestApp::sandbox();
+        let spawner = spawn_spawner(
+            &mut test_app,
+            Spawner {
+                count: 1,
+                waves: 2,
+                interval: 0.1,
+                encounter_id: Some("arena".to_string()),
+                ..default()
+            },
+        );
+        let barrier = test_app
+            .app
+            .world
+            .spawn((
+                SpriteBundle::default(),
+                Barrier {
+                    encounter_id: "arena".to_string(),
+                    size: Vec2::new(32.0, 16.0),
+                },
+            ))
+            .id();
+
+        test_app.step(10);
+        assert!(test_app.get::<Collider>(barrier).is_some());
+
+        // First wave
+        let enemies = spawned_enemies(&mut test_app, spawner);
+        assert_eq!(enemies.len(), 1);
+        kill_all(&mut test_app, enemies);
+        test_app.step(30);
+        assert!(test_app.get::<Collider>(barrier).is_some());
+
+        // Second and last wave
+        let enemies = spawned_enemies(&mut test_app, spawner);
+        assert_eq!(enemies.len(), 1);
+        kill_all(&mut test_app, enemies);
+        test_app.step(2);
+
+        assert_eq!(
+            test_app.get::<Spawner>(spawner).unwrap().state,
+            SpawnerState::Cleared
+        );
+        assert!(test_app.get::<Collider>(barrier).is_none());
+    }
+}
@@ -0,0 +1,214 @@
+// Player stamina and dashing, tuned from assets/data/stamina.ron
+// The player's EquipmentLoad picks the stamina pool and regeneration, so
+// heavier gear means waiting longer between attacks and dashes
+
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::entities::entity_components::{Dash, EquipmentLoad, Player, Stamina};
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::settings::{Action, Settings};
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub static STAMINA_PATH: &str = "assets/data/stamina.ron";
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct StaminaLoad {
+    pub max: f32,
+    pub regen_rate: f32,
+    pub regen_delay: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct DashConfig {
+    pub cost: f32,
+    pub speed: f32,
+    pub duration: f32,
+}
+
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct StaminaConfig {
+    pub dash: DashConfig,
+    pub ranged_attack_cost: f32,
+    pub loads: HashMap<EquipmentLoad, StaminaLoad>,
+}
+
+impl Default for StaminaConfig {
+    fn default() -> Self {
+        let stamina = Stamina::default();
+        StaminaConfig {
+            dash: DashConfig {
+                cost: 25.0,
+                speed: 450.0,
+                duration: 0.15,
+            },
+            ranged_attack_cost: 10.0,
+            loads: HashMap::from([(
+                EquipmentLoad::Medium,
+                StaminaLoad {
+                    max: stamina.max,
+                    regen_rate: stamina.regen_rate,
+                    regen_delay: stamina.regen_delay,
+                },
+            )]),
+        }
+    }
+}
+
+impl StaminaConfig {
+    pub fn load() -> StaminaConfig {
+        load_ron_or_default(STAMINA_PATH)
+    }
+}
+
+// Runs when the player spawns too, since Changed includes Added
+pub fn apply_equipment_load_system(
+    config: Res<StaminaConfig>,
+    mut players: Query<(&EquipmentLoad, &mut Stamina), Changed<EquipmentLoad>>,
+) {
+    for (load, mut stamina) in players.iter_mut() {
+        let Some(stats) = config.loads.get(load) else {
+            warn!("No stamina defined for the {:?} equipment load", load);
+            continue;
+        };
+        stamina.max = stats.max;
+        stamina.regen_rate = stats.regen_rate;
+        stamina.regen_delay = stats.regen_delay;
+        stamina.current = stamina.current.min(stamina.max);
+    }
+}
+
+pub fn stamina_regen_system(time: Res<Time>, mut staminas: Query<&mut Stamina>) {
+    for mut stamina in staminas.iter_mut() {
+        stamina.since_used += time.delta_seconds();
+        if stamina.since_used >= stamina.regen_delay && stamina.current < stamina.max {
+            stamina.current =
+                (stamina.current + stamina.regen_rate * time.delta_seconds()).min(stamina.max);
+        }
+    }
+}
+
+// Dashes the way the movement keys point, or the way the player is already
+// going when none are held
+pub fn dash_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
+    config: Res<StaminaConfig>,
+    mut players: Query<(&mut Dash, &mut Stamina, &mut Velocity), With<Player>>,
+) {
+    let Ok((mut dash, mut stamina, mut velocity)) = players.get_single_mut() else {
+        return;
+    };
+
+    if dash.remaining > 0.0 {
+        dash.remaining = (dash.remaining - time.delta_seconds()).max(0.0);
+        velocity.linvel = dash.direction * config.dash.speed;
+        return;
+    }
+
+    let bindings = &settings.key_bindings;
+    if input_locks.is_locked() || !bindings.just_pressed(Action::Dash, &keyboard_input) {
+        return;
+    }
+
+    let mut direction = Vec2::ZERO;
+    for (action, step) in [
+        (Action::MoveUp, Vec2::Y),
+        (Action::MoveDown, Vec2::NEG_Y),
+        (Action::MoveLeft, Vec2::NEG_X),
+        (Action::MoveRight, Vec2::X),
+    ] {
+        if bindings.pressed(action, &keyboard_input) {
+            direction += step;
+        }
+    }
+    if direction == Vec2::ZERO {
+        direction = velocity.linvel;
+    }
+    let direction = direction.normalize_or_zero();
+
+    if direction == Vec2::ZERO || !stamina.try_spend(config.dash.cost) {
+        return;
+    }
+
+    dash.direction = direction;
+    dash.remaining = config.dash.duration;
+    velocity.linvel = direction * config.dash.speed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::entities::attacks::{AttackDefinitions, AttackKind};
+    use crate::game_core::entities::entity_components::AttackHitbox;
+    use crate::game_core::entities::player::PLAYER_MOVEMENT;
+    use crate::game_core::test_harness::TestApp;
+
+    fn stamina(test_app: &mut TestApp) -> Stamina {
+        let player = test_app.player();
+        test_app.get::<Stamina>(player).unwrap().clone()
+    }
+
+    #[test]
+    fn dash_spends_stamina_and_outruns_walking() {
+        let mut test_app = TestApp::sandbox();
+        let config = test_app.app.world.resource::<StaminaConfig>().clone();
+        let before = stamina(&mut test_app).current;
+
+        test_app.press_key(KeyCode::D);
+        test_app.press_key(KeyCode::Space);
+        test_app.step(2);
+
+        let player = test_app.player();
+        assert!(test_app.get::<Velocity>(player).unwrap().linvel.x > PLAYER_MOVEMENT.max_speed);
+        assert_eq!(stamina(&mut test_app).current, before - config.dash.cost);
+    }
+
+    #[test]
+    fn no_attack_without_stamina() {
+        let mut test_app = TestApp::sandbox();
+        let cost = test_app
+            .app
+            .world
+            .resource::<AttackDefinitions>()
+            .step(AttackKind::Light, 0)
+            .unwrap()
+            .stamina_cost;
+        let player = test_app.player();
+        let mut drained = test_app.get_mut::<Stamina>(player).unwrap();
+        drained.current = cost / 2.0;
+        drained.since_used = 0.0;
+
+        test_app.click_mouse(MouseButton::Left);
+        test_app.step(2);
+
+        let world = &mut test_app.app.world;
+        let hitboxes = world.query::<&AttackHitbox>().iter(world).count();
+        assert_eq!(hitboxes, 0);
+        assert_eq!(stamina(&mut test_app).current, cost / 2.0);
+    }
+
+    #[test]
+    fn heavier_gear_regenerates_slower() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        let mut regained = Vec::new();
+
+        for load in [EquipmentLoad::Light, EquipmentLoad::Heavy] {
+            *test_app.get_mut::<EquipmentLoad>(player).unwrap() = load;
+            test_app.step(1);
+            let mut drained = test_app.get_mut::<Stamina>(player).unwrap();
+            drained.current = 0.0;
+            drained.since_used = 0.0;
+
+            test_app.step(120);
+            regained.push(stamina(&mut test_app).current);
+        }
+
+        assert!(regained[0] > regained[1]);
+    }
+}
@@ -0,0 +1,340 @@
+// Trigger areas placed in LDtk, for scripting levels from the editor
+// When the player walks into one whose conditions hold, it runs every action
+// set on it: set flags, start a dialogue, spawn enemies, play a sound, shake
+// the camera and change level. "Once" triggers are kept in the save data by
+// entity iid so they don't fire again
+
+use crate::game_core::audio::{Sound, SoundEvent};
+use crate::game_core::camera::CameraShake;
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::dialogue::{conditions_hold, Condition, Conversation, Dialogues};
+use crate::game_core::entities::entity_components::{Inventory, Player};
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::entities::spawner::{spawn_enemy, SPAWN_SPREAD};
+use crate::game_core::save::SaveData;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+#[derive(Clone, Debug, Default)]
+pub struct TriggerActions {
+    pub set_flags: Vec<String>,
+    pub clear_flags: Vec<String>,
+    // File name under assets/dialogue, the trigger itself is the one talking
+    pub dialogue: Option<String>,
+    pub speaker: String,
+    pub spawn_archetype: Option<String>,
+    pub spawn_count: u32,
+    pub sound: Option<Sound>,
+    // LDtk level identifier
+    pub level: Option<String>,
+    // In pixels, see CameraShake
+    pub camera_shake: f32,
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Trigger {
+    pub iid: String,
+    pub size: Vec2,
+    pub conditions: Vec<Condition>,
+    pub once: bool,
+    pub actions: TriggerActions,
+    // Whether the player was inside last frame, so staying inside doesn't fire again
+    pub inside: bool,
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Trigger {
+            iid: String::new(),
+            size: Vec2::splat(16.0),
+            conditions: Vec::new(),
+            once: true,
+            actions: TriggerActions::default(),
+            inside: false,
+        }
+    }
+}
+
+// "flag" needs the flag to be set, "!flag" needs it not to be
+fn parse_condition(condition: &str) -> Condition {
+    match condition.strip_prefix('!') {
+        Some(flag) => Condition::NotFlag(flag.to_string()),
+        None => Condition::Flag(condition.to_string()),
+    }
+}
+
+fn strings(values: Vec<Option<String>>) -> Vec<String> {
+    values.into_iter().flatten().collect()
+}
+
+impl From<EntityInstance> for Trigger {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut trigger = Trigger {
+            iid: entity_instance.iid.clone(),
+            size: Vec2::new(entity_instance.width as f32, entity_instance.height as f32),
+            ..default()
+        };
+        let actions = &mut trigger.actions;
+        for field in entity_instance.field_instances {
+            match (field.identifier.as_ref(), field.value) {
+                ("Conditions", FieldValue::Strings(conditions)) => {
+                    trigger.conditions = strings(conditions)
+                        .iter()
+                        .map(|condition| parse_condition(condition))
+                        .collect()
+                }
+                ("Once", FieldValue::Bool(once)) => trigger.once = once,
+                ("Set_flags", FieldValue::Strings(flags)) => actions.set_flags = strings(flags),
+                ("Clear_flags", FieldValue::Strings(flags)) => actions.clear_flags = strings(flags),
+                ("Dialogue", FieldValue::String(dialogue)) => actions.dialogue = dialogue,
+                ("Speaker", FieldValue::String(Some(speaker))) => actions.speaker = speaker,
+                ("Spawn_archetype", FieldValue::String(archetype)) => {
+                    actions.spawn_archetype = archetype
+                }
+                ("Spawn_count", FieldValue::Int(Some(count))) => {
+                    actions.spawn_count = count.max(0) as u32
+                }
+                ("Sound", FieldValue::String(Some(sound))) => {
+                    actions.sound = ron::from_str(&sound)
+                        .map_err(|error| warn!("Unknown trigger sound {}: {}", sound, error))
+                        .ok()
+                }
+                ("Level", FieldValue::String(level)) => actions.level = level,
+                ("Camera_shake", FieldValue::Float(Some(shake))) => actions.camera_shake = shake,
+                _ => {}
+            }
+        }
+        trigger
+    }
+}
+
+// The sensor is sized in on_trigger_spawned, since triggers can be resized in LDtk
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct TriggerBundle {
+    #[from_entity_instance]
+    pub trigger: Trigger,
+}
+
+pub fn on_trigger_spawned(
+    mut commands: Commands,
+    triggers: Query<(Entity, &Trigger), Added<Trigger>>,
+) {
+    for (entity, trigger) in triggers.iter() {
+        commands.entity(entity).insert((
+            Collider::cuboid(trigger.size.x / 2.0, trigger.size.y / 2.0),
+            Sensor,
+            Layer::Trigger.collision_groups(),
+        ));
+    }
+}
+
+pub fn trigger_system(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut players: Query<(Entity, &mut Inventory), With<Player>>,
+    mut triggers: Query<(Entity, &mut Trigger, &Transform, Option<&Parent>)>,
+    mut save_data: ResMut<SaveData>,
+    mut conversation: ResMut<Conversation>,
+    dialogues: Res<Dialogues>,
+    mut input_locks: ResMut<InputLocks>,
+    mut camera_shake: ResMut<CameraShake>,
+    mut sound_events: EventWriter<SoundEvent>,
+    // Missing in tests, which don't load levels through the LdtkPlugin
+    level_selection: Option<ResMut<LevelSelection>>,
+) {
+    let Ok((player, mut inventory)) = players.get_single_mut() else {
+        return;
+    };
+    let mut next_level = None;
+
+    for (entity, mut trigger, transform, parent) in triggers.iter_mut() {
+        let inside = rapier_context.intersection_pair(player, entity) == Some(true);
+        if inside == trigger.inside {
+            continue;
+        }
+        trigger.inside = inside;
+
+        if !inside
+            || (trigger.once && save_data.fired_triggers.contains(&trigger.iid))
+            || !conditions_hold(&trigger.conditions, &save_data, &inventory)
+        {
+            continue;
+        }
+        if trigger.once {
+            save_data.fired_triggers.insert(trigger.iid.clone());
+        }
+
+        let actions = &trigger.actions;
+        if !actions.set_flags.is_empty() || !actions.clear_flags.is_empty() {
+            save_data.flags.extend(actions.set_flags.iter().cloned());
+            for flag in actions.clear_flags.iter() {
+                save_data.flags.remove(flag);
+            }
+        }
+
+        if let Some(dialogue) = &actions.dialogue {
+            if !conversation.is_active() {
+                conversation.start(
+                    entity,
+                    &actions.speaker,
+                    dialogues.get(dialogue),
+                    &mut save_data,
+                    &mut inventory,
+                    &mut input_locks,
+                );
+            }
+        }
+
+        if let Some(archetype) = &actions.spawn_archetype {
+            let mut rng = rand::thread_rng();
+            for _ in 0..actions.spawn_count {
+                let offset = Vec2::new(
+                    rng.gen_range(-SPAWN_SPREAD..=SPAWN_SPREAD),
+                    rng.gen_range(-SPAWN_SPREAD..=SPAWN_SPREAD),
+                );
+                let translation = transform.translation + offset.extend(0.0);
+                spawn_enemy(&mut commands, archetype, translation, parent);
+            }
+        }
+
+        if let Some(sound) = actions.sound {
+            sound_events.send(SoundEvent(sound));
+        }
+
+        if actions.camera_shake > 0.0 {
+            camera_shake.shake(actions.camera_shake);
+        }
+
+        if actions.level.is_some() {
+            next_level = actions.level.clone();
+        }
+    }
+
+    // Last, since the level change despawns the triggers of this level
+    if let (Some(level), Some(mut level_selection)) = (next_level, level_selection) {
+        *level_selection = LevelSelection::Identifier(level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::entities::entity_components::Enemy;
+    use crate::game_core::test_harness::TestApp;
+
+    // Right where the player stands
+    fn spawn_trigger(test_app: &mut TestApp, trigger: Trigger) {
+        let player = test_app.player();
+        let position = test_app.get::<Transform>(player).unwrap().translation;
+        test_app.app.world.spawn((
+            Trigger {
+                iid: "trigger-iid".to_string(),
+                ..trigger
+            },
+            TransformBundle::from(Transform::from_translation(position)),
+        ));
+        test_app.step(3);
+    }
+
+    fn leave_and_come_back(test_app: &mut TestApp) {
+        let player = test_app.player();
+        test_app.get_mut::<Transform>(player).unwrap().translation.x += 100.0;
+        test_app.step(3);
+        test_app.get_mut::<Transform>(player).unwrap().translation.x -= 100.0;
+        test_app.step(3);
+    }
+
+    fn has_flag(test_app: &TestApp, flag: &str) -> bool {
+        test_app
+            .app
+            .world
+            .resource::<SaveData>()
+            .flags
+            .contains(flag)
+    }
+
+    #[test]
+    fn fires_once_on_enter() {
+        let mut test_app = TestApp::sandbox();
+        spawn_trigger(
+            &mut test_app,
+            Trigger {
+                actions: TriggerActions {
+                    set_flags: vec!["entered".to_string()],
+                    camera_shake: 4.0,
+                    ..default()
+                },
+                ..default()
+            },
+        );
+
+        assert!(has_flag(&test_app, "entered"));
+        assert!(test_app.app.world.resource::<CameraShake>().strength > 0.0);
+
+        test_app
+            .app
+            .world
+            .resource_mut::<SaveData>()
+            .flags
+            .remove("entered");
+        leave_and_come_back(&mut test_app);
+        assert!(!has_flag(&test_app, "entered"));
+    }
+
+    #[test]
+    fn conditions_hold_back_the_spawn() {
+        let mut test_app = TestApp::sandbox();
+        let skeletons = |test_app: &mut TestApp| {
+            let mut enemies = test_app.app.world.query::<&Enemy>();
+            enemies
+                .iter(&test_app.app.world)
+                .filter(|enemy| enemy.archetype == "skeleton")
+                .count()
+        };
+        let before = skeletons(&mut test_app);
+
+        spawn_trigger(
+            &mut test_app,
+            Trigger {
+                conditions: vec![Condition::Flag("ambush".to_string())],
+                actions: TriggerActions {
+                    spawn_archetype: Some("skeleton".to_string()),
+                    spawn_count: 2,
+                    ..default()
+                },
+                ..default()
+            },
+        );
+        assert_eq!(skeletons(&mut test_app), before);
+
+        test_app
+            .app
+            .world
+            .resource_mut::<SaveData>()
+            .flags
+            .insert("ambush".to_string());
+        leave_and_come_back(&mut test_app);
+        assert_eq!(skeletons(&mut test_app), before + 2);
+    }
+
+    #[test]
+    fn starts_a_dialogue() {
+        let mut test_app = TestApp::sandbox();
+        spawn_trigger(
+            &mut test_app,
+            Trigger {
+                actions: TriggerActions {
+                    dialogue: Some("hermit".to_string()),
+                    speaker: "Hermit".to_string(),
+                    ..default()
+                },
+                ..default()
+            },
+        );
+
+        assert!(test_app.app.world.resource::<Conversation>().is_active());
+        assert!(test_app.app.world.resource::<InputLocks>().is_locked());
+    }
+}
@@ -0,0 +1,367 @@
+// Merges grid cells into as few rectangles as possible
+// Used to build one big collider per rectangle instead of one per tile
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use std::collections::{HashMap, HashSet};
+
+/// Represents a wide wall that is 1 tile tall
+/// Used to spawn wall collisions
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+struct Plate {
+    left: i32,
+    right: i32,
+}
+
+/// A rectangle of grid cells, all bounds are inclusive
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Hash)]
+pub struct GridRect {
+    pub left: i32,
+    pub right: i32,
+    pub top: i32,
+    pub bottom: i32,
+}
+
+impl GridRect {
+    pub fn contains(&self, coords: GridCoords) -> bool {
+        coords.x >= self.left
+            && coords.x <= self.right
+            && coords.y >= self.bottom
+            && coords.y <= self.top
+    }
+
+    // Size in pixels of the rectangle, for tiles of `grid_size` pixels
+    pub fn size(&self, grid_size: i32) -> Vec2 {
+        Vec2::new(
+            (self.right - self.left + 1) as f32,
+            (self.top - self.bottom + 1) as f32,
+        ) * grid_size as f32
+    }
+
+    // Center in pixels, relative to the bottom left corner of the level
+    pub fn center(&self, grid_size: i32) -> Vec2 {
+        Vec2::new(
+            (self.left + self.right + 1) as f32,
+            (self.bottom + self.top + 1) as f32,
+        ) * grid_size as f32
+            / 2.
+    }
+}
+
+// Takes the cells of a `width` x `height` grid and returns rectangles that cover
+// every one of them exactly once. Cells outside the grid are ignored
+pub fn merge_cells_into_rects(
+    cells: &HashSet<GridCoords>,
+    width: i32,
+    height: i32,
+) -> Vec<GridRect> {
+    // combine cells into flat "plates" in each individual row
+    let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+    for y in 0..height {
+        let mut row_plates: Vec<Plate> = Vec::new();
+        let mut plate_start = None;
+
+        // + 1 to the width so the algorithm "terminates" plates that touch the right
+        // edge
+        for x in 0..width + 1 {
+            match (plate_start, cells.contains(&GridCoords { x, y })) {
+                (Some(s), false) => {
+                    row_plates.push(Plate {
+                        left: s,
+                        right: x - 1,
+                    });
+                    plate_start = None;
+                }
+                (None, true) if x < width => plate_start = Some(x),
+                _ => (),
+            }
+        }
+
+        plate_stack.push(row_plates);
+    }
+
+    // combine "plates" into rectangles across multiple rows
+    let mut rects: Vec<GridRect> = Vec::new();
+    let mut previous_rects: HashMap<Plate, GridRect> = HashMap::new();
+
+    // an extra empty row so the algorithm "terminates" the rects that touch the top
+    // edge
+    plate_stack.push(Vec::new());
+
+    for (y, row) in plate_stack.iter().enumerate() {
+        let mut current_rects: HashMap<Plate, GridRect> = HashMap::new();
+        for plate in row {
+            if let Some(previous_rect) = previous_rects.remove(plate) {
+                current_rects.insert(
+                    *plate,
+                    GridRect {
+                        top: previous_rect.top + 1,
+                        ..previous_rect
+                    },
+                );
+            } else {
+                current_rects.insert(
+                    *plate,
+                    GridRect {
+                        bottom: y as i32,
+                        top: y as i32,
+                        left: plate.left,
+                        right: plate.right,
+                    },
+                );
+            }
+        }
+
+        // Any plates that weren't removed above have terminated
+        rects.append(&mut previous_rects.values().copied().collect());
+        previous_rects = current_rects;
+    }
+
+    rects
+}
+
+// Keeps `rects` an exact cover of `cells` after `coords` was added to or removed
+// from `cells`. Only the rectangles containing or touching `coords` are merged
+// again, everything else is left as it is
+pub fn update_rects_around(
+    rects: &mut Vec<GridRect>,
+    cells: &HashSet<GridCoords>,
+    coords: GridCoords,
+    width: i32,
+    height: i32,
+) {
+    let touches = |rect: &GridRect| {
+        coords.x >= rect.left - 1
+            && coords.x <= rect.right + 1
+            && coords.y >= rect.bottom - 1
+            && coords.y <= rect.top + 1
+    };
+
+    let mut region: HashSet<GridCoords> = HashSet::new();
+    rects.retain(|rect| {
+        if !touches(rect) {
+            return true;
+        }
+        for x in rect.left..=rect.right {
+            for y in rect.bottom..=rect.top {
+                region.insert(GridCoords { x, y });
+            }
+        }
+        false
+    });
+
+    region.insert(coords);
+    region.retain(|cell| cells.contains(cell));
+
+    rects.append(&mut merge_cells_into_rects(&region, width, height));
+}
+
+// Every non-empty cell of an IntGrid layer and its value, in GridCoords (y going up)
+pub fn int_grid_values(layer: &LayerInstance) -> HashMap<GridCoords, i32> {
+    let mut values = HashMap::new();
+    for (index, &cell_value) in layer.int_grid_csv.iter().enumerate() {
+        if cell_value != 0 {
+            let index = index as i32;
+            values.insert(
+                GridCoords {
+                    x: index % layer.c_wid,
+                    // the csv goes from the top row down
+                    y: layer.c_hei - 1 - index / layer.c_wid,
+                },
+                cell_value,
+            );
+        }
+    }
+    values
+}
+
+// Cells of an IntGrid layer holding `value`, in GridCoords (y going up)
+pub fn int_grid_cells(layer: &LayerInstance, value: i32) -> HashSet<GridCoords> {
+    int_grid_values(layer)
+        .into_iter()
+        .filter(|&(_, cell_value)| cell_value == value)
+        .map(|(coords, _)| coords)
+        .collect()
+}
+
+// Rectangles covering every cell of `value` in an IntGrid layer
+pub fn merge_int_grid_value(layer: &LayerInstance, value: i32) -> Vec<GridRect> {
+    merge_cells_into_rects(&int_grid_cells(layer, value), layer.c_wid, layer.c_hei)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Every input cell is covered by exactly one rectangle, and the rectangles
+    // don't cover anything else
+    fn assert_exact_cover(cells: &HashSet<GridCoords>, width: i32, height: i32) {
+        let rects = merge_cells_into_rects(cells, width, height);
+        assert_rects_cover(&rects, cells, width, height);
+    }
+
+    fn assert_rects_cover(
+        rects: &[GridRect],
+        cells: &HashSet<GridCoords>,
+        width: i32,
+        height: i32,
+    ) {
+        for x in 0..width {
+            for y in 0..height {
+                let coords = GridCoords { x, y };
+                let covering = rects.iter().filter(|rect| rect.contains(coords)).count();
+                let expected = if cells.contains(&coords) { 1 } else { 0 };
+                assert_eq!(
+                    covering, expected,
+                    "cell {:?} covered {} times, cells: {:?}, rects: {:?}",
+                    coords, covering, cells, rects
+                );
+            }
+        }
+
+        let covered_area: i32 = rects
+            .iter()
+            .map(|rect| (rect.right - rect.left + 1) * (rect.top - rect.bottom + 1))
+            .sum();
+        assert_eq!(covered_area as usize, cells.len());
+    }
+
+    #[test]
+    fn empty_grid_has_no_rects() {
+        assert!(merge_cells_into_rects(&HashSet::new(), 10, 10).is_empty());
+    }
+
+    #[test]
+    fn full_grid_is_one_rect() {
+        let cells = (0..6)
+            .flat_map(|x| (0..4).map(move |y| GridCoords { x, y }))
+            .collect();
+        assert_eq!(
+            merge_cells_into_rects(&cells, 6, 4),
+            vec![GridRect {
+                left: 0,
+                right: 5,
+                top: 3,
+                bottom: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn l_shape_is_two_rects() {
+        let cells: HashSet<GridCoords> = [(0, 0), (1, 0), (2, 0), (0, 1), (0, 2)]
+            .iter()
+            .map(|&(x, y)| GridCoords { x, y })
+            .collect();
+        assert_eq!(merge_cells_into_rects(&cells, 3, 3).len(), 2);
+        assert_exact_cover(&cells, 3, 3);
+    }
+
+    #[test]
+    fn cells_outside_the_grid_are_ignored() {
+        let cells: HashSet<GridCoords> = [(0, 0), (5, 0), (0, 5), (-1, 0)]
+            .iter()
+            .map(|&(x, y)| GridCoords { x, y })
+            .collect();
+        assert_eq!(
+            merge_cells_into_rects(&cells, 2, 2),
+            vec![GridRect::default()]
+        );
+    }
+
+    #[test]
+    fn random_grids_are_covered_exactly() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+
+        for _ in 0..500 {
+            let width = rng.gen_range(1..20);
+            let height = rng.gen_range(1..20);
+            let density = rng.gen_range(0.0..1.0);
+
+            let mut cells = HashSet::new();
+            for x in 0..width {
+                for y in 0..height {
+                    if rng.gen_bool(density) {
+                        cells.insert(GridCoords { x, y });
+                    }
+                }
+            }
+
+            assert_exact_cover(&cells, width, height);
+        }
+    }
+
+    #[test]
+    fn random_edits_keep_an_exact_cover() {
+        let mut rng = StdRng::seed_from_u64(0xed17);
+
+        for _ in 0..50 {
+            let width = rng.gen_range(1..16);
+            let height = rng.gen_range(1..16);
+
+            let mut cells = HashSet::new();
+            for x in 0..width {
+                for y in 0..height {
+                    if rng.gen_bool(0.5) {
+                        cells.insert(GridCoords { x, y });
+                    }
+                }
+            }
+            let mut rects = merge_cells_into_rects(&cells, width, height);
+
+            for _ in 0..40 {
+                let coords = GridCoords {
+                    x: rng.gen_range(0..width),
+                    y: rng.gen_range(0..height),
+                };
+                if !cells.remove(&coords) {
+                    cells.insert(coords);
+                }
+
+                update_rects_around(&mut rects, &cells, coords, width, height);
+                assert_rects_cover(&rects, &cells, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn edits_leave_far_rects_alone() {
+        let cells: HashSet<GridCoords> = [(0, 0), (1, 0), (5, 5), (5, 6)]
+            .iter()
+            .map(|&(x, y)| GridCoords { x, y })
+            .collect();
+        let mut rects = merge_cells_into_rects(&cells, 8, 8);
+        let far_rect = *rects.iter().find(|rect| rect.left == 5).unwrap();
+
+        let mut edited = cells.clone();
+        edited.remove(&GridCoords { x: 1, y: 0 });
+        update_rects_around(&mut rects, &edited, GridCoords { x: 1, y: 0 }, 8, 8);
+
+        assert!(rects.contains(&far_rect));
+        assert_rects_cover(&rects, &edited, 8, 8);
+    }
+
+    #[test]
+    fn int_grid_csv_is_flipped_to_grid_coords() {
+        let layer = LayerInstance {
+            c_wid: 3,
+            c_hei: 2,
+            // top row: 1 0 0, bottom row: 0 0 2
+            int_grid_csv: vec![1, 0, 0, 0, 0, 2],
+            ..default()
+        };
+
+        assert_eq!(
+            int_grid_cells(&layer, 1),
+            HashSet::from([GridCoords { x: 0, y: 1 }])
+        );
+        assert_eq!(
+            int_grid_cells(&layer, 2),
+            HashSet::from([GridCoords { x: 2, y: 0 }])
+        );
+    }
+}
@@ -0,0 +1,614 @@
+// 2D lighting, drawn as a quad over each level that multiplies the colours
+// under it. The ambient light follows a day/night clock tuned from
+// assets/data/lighting.ron, point lights can be put on any entity (or placed
+// as Light entities in LDtk) and the solid tiles cast shadows
+//
+// Without the shader (it failed to load, there is no GPU to run it on, or
+// lighting is off in the settings) the overlays are hidden and the game is lit
+// like before. A shader that loads but doesn't compile for the GPU's backend
+// only shows up as an error in the log, turning lighting off in the settings
+// is the way out then
+
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::entities::entity_components::Player;
+use crate::game_core::settings::Settings;
+use crate::game_core::visibility::blocks_sight;
+use crate::game_core::world::{IntGridColliders, LevelTileGrid};
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{
+    AsBindGroup, BlendComponent, BlendFactor, BlendOperation, BlendState, Extent3d,
+    RenderPipelineDescriptor, ShaderRef, ShaderType, SpecializedMeshPipelineError,
+    TextureDimension, TextureFormat,
+};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::texture::ImageSampler;
+use bevy::sprite::{Material2d, Material2dKey, MaterialMesh2dBundle};
+use bevy_ecs_ldtk::prelude::*;
+use serde::Deserialize;
+
+pub static LIGHTING_PATH: &str = "assets/data/lighting.ron";
+pub static LIGHTING_SHADER_PATH: &str = "shaders/lighting.wgsl";
+
+// Has to match the size of the lights array in the shader
+pub const MAX_LIGHTS: usize = 16;
+
+// Above the level, below the fog of war
+static LIGHTING_Z: f32 = 90.0;
+
+#[derive(Component, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PointLight2d {
+    pub color: Color,
+    // In pixels, the light fades out to nothing there
+    pub radius: f32,
+    pub intensity: f32,
+    // From 0 (steady) to 1 (flickers all the way down)
+    pub flicker: f32,
+}
+
+impl Default for PointLight2d {
+    fn default() -> Self {
+        PointLight2d {
+            color: Color::rgb(1.0, 0.7, 0.36),
+            radius: 80.0,
+            intensity: 1.0,
+            flicker: 0.0,
+        }
+    }
+}
+
+impl From<EntityInstance> for PointLight2d {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut light = PointLight2d::default();
+        for field in entity_instance.field_instances {
+            match (field.identifier.as_ref(), field.value) {
+                ("Color", FieldValue::Color(color)) => light.color = color,
+                ("Radius", FieldValue::Float(Some(radius))) => light.radius = radius,
+                ("Intensity", FieldValue::Float(Some(intensity))) => light.intensity = intensity,
+                ("Flicker", FieldValue::Float(Some(flicker))) => {
+                    light.flicker = flicker.clamp(0.0, 1.0)
+                }
+                _ => {}
+            }
+        }
+        light
+    }
+}
+
+// Torches and lamps placed in LDtk
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct LightBundle {
+    #[from_entity_instance]
+    pub light: PointLight2d,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AmbientKey {
+    pub hour: f32,
+    pub color: [f32; 3],
+}
+
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LightingConfig {
+    // Real seconds for a whole day
+    pub day_length_secs: f32,
+    pub start_hour: f32,
+    // Blended between, wrapping around midnight
+    pub ambient: Vec<AmbientKey>,
+    pub player_light: Option<PointLight2d>,
+}
+
+impl Default for LightingConfig {
+    // Always full daylight, the way the game looked before lighting
+    fn default() -> Self {
+        LightingConfig {
+            day_length_secs: 600.0,
+            start_hour: 8.0,
+            ambient: vec![AmbientKey {
+                hour: 0.0,
+                color: [1.0, 1.0, 1.0],
+            }],
+            player_light: None,
+        }
+    }
+}
+
+impl LightingConfig {
+    pub fn load() -> LightingConfig {
+        let mut config: LightingConfig = load_ron_or_default(LIGHTING_PATH);
+        config.ambient.sort_by(|a, b| a.hour.total_cmp(&b.hour));
+        config
+    }
+}
+
+#[derive(Resource)]
+pub struct DayNightClock {
+    // From 0 to 24
+    pub hour: f32,
+}
+
+impl FromWorld for DayNightClock {
+    fn from_world(world: &mut World) -> Self {
+        let start_hour = world
+            .get_resource::<LightingConfig>()
+            .map_or(0.0, |config| config.start_hour);
+        DayNightClock {
+            hour: start_hour.rem_euclid(24.0),
+        }
+    }
+}
+
+// The ambient light at `hour`, `keys` have to be sorted by hour
+pub fn ambient_at(keys: &[AmbientKey], hour: f32) -> Vec3 {
+    let (Some(first), Some(last)) = (keys.first(), keys.last()) else {
+        return Vec3::ONE;
+    };
+    let from = keys
+        .iter()
+        .rev()
+        .find(|key| key.hour <= hour)
+        .unwrap_or(last);
+    let to = keys.iter().find(|key| key.hour > hour).unwrap_or(first);
+
+    let span = (to.hour - from.hour).rem_euclid(24.0);
+    let t = if span > 0.0 {
+        (hour - from.hour).rem_euclid(24.0) / span
+    } else {
+        0.0
+    };
+    Vec3::from(from.color).lerp(Vec3::from(to.color), t)
+}
+
+#[derive(ShaderType, Clone, Copy, Debug, Default)]
+pub struct GpuLight {
+    pub color: Vec4,
+    // In pixels from the bottom left of the level
+    pub position: Vec2,
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+#[derive(ShaderType, Clone, Debug, Default)]
+pub struct LightingUniform {
+    pub ambient: Vec4,
+    pub level_size: Vec2,
+    pub cell_size: f32,
+    pub light_count: u32,
+    pub lights: [GpuLight; MAX_LIGHTS],
+}
+
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "5d7c2a41-8f0e-4b6a-9c3d-2e1f7a9b4c60"]
+pub struct LightingMaterial {
+    #[uniform(0)]
+    pub lighting: LightingUniform,
+    // White where a cell blocks light, one pixel per cell
+    #[texture(1)]
+    #[sampler(2)]
+    pub occluders: Handle<Image>,
+}
+
+impl Material2d for LightingMaterial {
+    fn fragment_shader() -> ShaderRef {
+        LIGHTING_SHADER_PATH.into()
+    }
+
+    // Multiplies what is already drawn by the light instead of blending over it
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let target = descriptor
+            .fragment
+            .as_mut()
+            .and_then(|fragment| fragment.targets.get_mut(0))
+            .and_then(|target| target.as_mut());
+        if let Some(target) = target {
+            target.blend = Some(BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::Dst,
+                    dst_factor: BlendFactor::Zero,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::Zero,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+pub struct LightingState {
+    pub shader: Handle<Shader>,
+    // False without a GPU, or once the shader failed to load
+    pub available: bool,
+}
+
+// The lighting quad of a level
+#[derive(Component)]
+pub struct LightingOverlay;
+
+pub fn setup_lighting(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    render_device: Option<Res<RenderDevice>>,
+) {
+    // The renderer only adds the device when it found a backend to draw with
+    if render_device.is_none() {
+        warn!("No GPU to draw the lighting with, playing without lighting");
+    }
+    // Same handle the material uses, kept to find out whether it loaded
+    commands.insert_resource(LightingState {
+        shader: asset_server.load(LIGHTING_SHADER_PATH),
+        available: render_device.is_some(),
+    });
+}
+
+pub fn lighting_fallback_system(asset_server: Res<AssetServer>, mut state: ResMut<LightingState>) {
+    if state.available && asset_server.get_load_state(&state.shader) == LoadState::Failed {
+        warn!(
+            "Could not load {}, playing without lighting",
+            LIGHTING_SHADER_PATH
+        );
+        state.available = false;
+    }
+}
+
+pub fn day_night_system(
+    time: Res<Time>,
+    config: Res<LightingConfig>,
+    mut clock: ResMut<DayNightClock>,
+) {
+    if config.day_length_secs <= 0.0 {
+        return;
+    }
+    clock.hour =
+        (clock.hour + time.delta_seconds() * 24.0 / config.day_length_secs).rem_euclid(24.0);
+}
+
+pub fn attach_player_light_system(
+    mut commands: Commands,
+    config: Res<LightingConfig>,
+    players: Query<Entity, Added<Player>>,
+) {
+    let Some(light) = &config.player_light else {
+        return;
+    };
+    for player in players.iter() {
+        commands.entity(player).insert(light.clone());
+    }
+}
+
+// RGBA pixels of the occluder texture, starting from the top row like images do
+pub fn render_occluders(grid: &LevelTileGrid, colliders: &IntGridColliders) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((grid.width * grid.height * 4) as usize);
+    for row in 0..grid.height {
+        for x in 0..grid.width {
+            let coords = GridCoords {
+                x,
+                y: grid.height - 1 - row,
+            };
+            let value = if blocks_sight(grid, colliders, coords) {
+                255
+            } else {
+                0
+            };
+            pixels.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+    pixels
+}
+
+fn occluder_image(images: &mut Assets<Image>, size: Extent3d, pixels: Vec<u8>) -> Handle<Image> {
+    let mut image = Image::new(
+        size,
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8Unorm,
+    );
+    image.sampler_descriptor = ImageSampler::nearest();
+    images.add(image)
+}
+
+// Adds the lighting quad to new levels, and redraws the shadows when tiles change
+pub fn lighting_overlay_system(
+    mut commands: Commands,
+    changed_levels: Query<(Entity, &LevelTileGrid), Changed<LevelTileGrid>>,
+    overlays: Query<(&Parent, &Handle<LightingMaterial>), With<LightingOverlay>>,
+    colliders: Res<IntGridColliders>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<LightingMaterial>>,
+) {
+    for (level, grid) in changed_levels.iter() {
+        let size = Extent3d {
+            width: grid.width as u32,
+            height: grid.height as u32,
+            depth_or_array_layers: 1,
+        };
+        let pixels = render_occluders(grid, &colliders);
+
+        let overlay = overlays
+            .iter()
+            .find(|(parent, _)| parent.get() == level)
+            .and_then(|(_, material)| materials.get_mut(material));
+        if let Some(material) = overlay {
+            // Tile edits keep the level's size, the texture is redrawn where it is
+            match images.get_mut(&material.occluders) {
+                Some(image) if image.texture_descriptor.size == size => image.data = pixels,
+                _ => material.occluders = occluder_image(&mut images, size, pixels),
+            }
+            continue;
+        }
+        let occluders = occluder_image(&mut images, size, pixels);
+
+        let size = Vec2::new(grid.width as f32, grid.height as f32) * grid.grid_size as f32;
+        let overlay = commands
+            .spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(Mesh::from(shape::Quad::new(size))).into(),
+                    material: materials.add(LightingMaterial {
+                        lighting: LightingUniform::default(),
+                        occluders,
+                    }),
+                    // The quad is centered on its transform
+                    transform: Transform::from_translation((size / 2.0).extend(LIGHTING_Z)),
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                },
+                LightingOverlay,
+            ))
+            .id();
+        commands.entity(level).add_child(overlay);
+    }
+}
+
+// Dims a flickering light a little differently every frame, lights next to
+// each other don't flicker together
+fn flicker(light: &PointLight2d, seconds: f32, position: Vec2) -> f32 {
+    let noise =
+        (seconds * 11.0 + position.x * 0.37).sin() * (seconds * 7.3 + position.y * 0.21).sin();
+    1.0 - light.flicker * (0.5 + 0.5 * noise)
+}
+
+pub fn lighting_system(
+    time: Res<Time>,
+    clock: Res<DayNightClock>,
+    config: Res<LightingConfig>,
+    settings: Res<Settings>,
+    state: Option<Res<LightingState>>,
+    levels: Query<(&LevelTileGrid, &GlobalTransform)>,
+    lights: Query<(&PointLight2d, &GlobalTransform)>,
+    players: Query<&GlobalTransform, With<Player>>,
+    mut overlays: Query<
+        (&Parent, &Handle<LightingMaterial>, &mut Visibility),
+        With<LightingOverlay>,
+    >,
+    mut materials: ResMut<Assets<LightingMaterial>>,
+) {
+    let enabled = settings.window.lighting && state.map_or(false, |state| state.available);
+    let ambient = ambient_at(&config.ambient, clock.hour).extend(1.0);
+    let seconds = time.elapsed_seconds();
+    let player_position = players
+        .get_single()
+        .map(|transform| transform.translation().truncate())
+        .ok();
+
+    for (parent, material, mut visibility) in overlays.iter_mut() {
+        if visibility.is_visible != enabled {
+            visibility.is_visible = enabled;
+        }
+        if !enabled {
+            continue;
+        }
+        let Ok((grid, level_transform)) = levels.get(parent.get()) else {
+            continue;
+        };
+        let Some(material) = materials.get_mut(material) else {
+            continue;
+        };
+
+        let origin = level_transform.translation().truncate();
+        let level_size = Vec2::new(grid.width as f32, grid.height as f32) * grid.grid_size as f32;
+
+        // Lights that reach into the level, the closest to the player first
+        // when there are more than the shader takes
+        let mut level_lights: Vec<(f32, GpuLight)> = lights
+            .iter()
+            .filter_map(|(light, transform)| {
+                let position = transform.translation().truncate() - origin;
+                let reaches = position.cmpge(Vec2::splat(-light.radius)).all()
+                    && position.cmple(level_size + light.radius).all();
+                reaches.then(|| {
+                    let distance =
+                        player_position.map_or(0.0, |player| player.distance(position + origin));
+                    let gpu_light = GpuLight {
+                        color: Vec4::from(light.color),
+                        position,
+                        radius: light.radius,
+                        intensity: light.intensity * flicker(light, seconds, position),
+                    };
+                    (distance, gpu_light)
+                })
+            })
+            .collect();
+        level_lights.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut uniform = LightingUniform {
+            ambient,
+            level_size,
+            cell_size: grid.grid_size as f32,
+            light_count: level_lights.len().min(MAX_LIGHTS) as u32,
+            ..default()
+        };
+        for (slot, (_, light)) in uniform.lights.iter_mut().zip(level_lights) {
+            *slot = light;
+        }
+        material.lighting = uniform;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::test_harness::TestApp;
+
+    fn keys() -> Vec<AmbientKey> {
+        vec![
+            AmbientKey {
+                hour: 6.0,
+                color: [1.0, 1.0, 1.0],
+            },
+            AmbientKey {
+                hour: 18.0,
+                color: [0.2, 0.2, 0.4],
+            },
+        ]
+    }
+
+    #[test]
+    fn ambient_blends_between_keys() {
+        assert!(ambient_at(&keys(), 6.0).abs_diff_eq(Vec3::ONE, 1e-5));
+        assert!(ambient_at(&keys(), 12.0).abs_diff_eq(Vec3::new(0.6, 0.6, 0.7), 1e-5));
+    }
+
+    #[test]
+    fn ambient_wraps_past_midnight() {
+        // Halfway from 18:00 to 6:00 the next morning
+        assert!(ambient_at(&keys(), 0.0).abs_diff_eq(Vec3::new(0.6, 0.6, 0.7), 1e-5));
+        assert!(ambient_at(&keys(), 18.0).abs_diff_eq(Vec3::new(0.2, 0.2, 0.4), 1e-5));
+    }
+
+    // A 16 by 16 level with a wall in the fourth column
+    fn spawn_level(test_app: &mut TestApp, origin: Vec2) -> Entity {
+        test_app
+            .app
+            .world
+            .spawn((
+                LevelTileGrid {
+                    level_iid: "test-level".to_string(),
+                    layer: "Fences".to_string(),
+                    width: 16,
+                    height: 16,
+                    grid_size: 16,
+                    cells: (0..16).map(|y| (GridCoords { x: 3, y }, 1)).collect(),
+                },
+                TransformBundle::from(Transform::from_translation(origin.extend(0.0))),
+            ))
+            .id()
+    }
+
+    fn overlay(test_app: &mut TestApp) -> (Entity, Handle<LightingMaterial>) {
+        let world = &mut test_app.app.world;
+        let (overlay, material) = world
+            .query_filtered::<(Entity, &Handle<LightingMaterial>), With<LightingOverlay>>()
+            .single(world);
+        (overlay, material.clone())
+    }
+
+    fn occluders(test_app: &mut TestApp) -> (Handle<Image>, Vec<u8>) {
+        let (_, material) = overlay(test_app);
+        let world = &test_app.app.world;
+        let materials = world.resource::<Assets<LightingMaterial>>();
+        let handle = materials.get(&material).unwrap().occluders.clone();
+        let data = world
+            .resource::<Assets<Image>>()
+            .get(&handle)
+            .unwrap()
+            .data
+            .clone();
+        (handle, data)
+    }
+
+    #[test]
+    fn without_a_gpu_the_overlays_stay_hidden() {
+        let mut test_app = TestApp::new();
+        spawn_level(&mut test_app, Vec2::ZERO);
+        test_app.step(2);
+
+        assert!(test_app.app.world.resource::<Settings>().window.lighting);
+        assert!(!test_app.app.world.resource::<LightingState>().available);
+        let (overlay, _) = overlay(&mut test_app);
+        assert!(!test_app.get::<Visibility>(overlay).unwrap().is_visible);
+    }
+
+    #[test]
+    fn occluders_are_redrawn_in_place() {
+        let mut test_app = TestApp::new();
+        let level = spawn_level(&mut test_app, Vec2::ZERO);
+        test_app.step(2);
+
+        // Top row first, one RGBA pixel per cell
+        let (handle, data) = occluders(&mut test_app);
+        let grid = test_app.get::<LevelTileGrid>(level).unwrap();
+        let colliders = test_app.app.world.resource::<IntGridColliders>();
+        assert_eq!(data, render_occluders(grid, colliders));
+        assert_eq!(&data[3 * 4..4 * 4], &[255, 255, 255, 255]);
+        assert_eq!(&data[4 * 4..5 * 4], &[0, 0, 0, 255]);
+
+        // Breaks a hole through the wall at the top
+        test_app
+            .get_mut::<LevelTileGrid>(level)
+            .unwrap()
+            .cells
+            .remove(&GridCoords { x: 3, y: 15 });
+        test_app.step(1);
+
+        let (redrawn, data) = occluders(&mut test_app);
+        assert_eq!(redrawn, handle);
+        assert_eq!(&data[3 * 4..4 * 4], &[0, 0, 0, 255]);
+        assert_eq!(&data[(16 + 3) * 4..(16 + 4) * 4], &[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn lights_in_reach_go_into_the_uniform() {
+        let mut test_app = TestApp::new();
+        test_app.step(1);
+        test_app.app.world.resource_mut::<LightingState>().available = true;
+
+        let origin = Vec2::new(100.0, 50.0);
+        spawn_level(&mut test_app, origin);
+        let torch = PointLight2d {
+            color: Color::rgb(1.0, 0.5, 0.25),
+            radius: 40.0,
+            intensity: 0.5,
+            flicker: 0.0,
+        };
+        let far_away = origin + Vec2::new(600.0, 0.0);
+        for position in [origin + Vec2::new(32.0, 64.0), far_away] {
+            test_app.app.world.spawn((
+                torch.clone(),
+                TransformBundle::from(Transform::from_translation(position.extend(0.0))),
+            ));
+        }
+        test_app.step(2);
+
+        let (overlay, material) = overlay(&mut test_app);
+        assert!(test_app.get::<Visibility>(overlay).unwrap().is_visible);
+
+        let world = &test_app.app.world;
+        let hour = world.resource::<DayNightClock>().hour;
+        let ambient = ambient_at(&world.resource::<LightingConfig>().ambient, hour);
+        let materials = world.resource::<Assets<LightingMaterial>>();
+        let lighting = &materials.get(&material).unwrap().lighting;
+        assert!(lighting.ambient.abs_diff_eq(ambient.extend(1.0), 1e-5));
+        assert_eq!(lighting.level_size, Vec2::splat(256.0));
+        assert_eq!(lighting.cell_size, 16.0);
+        assert_eq!(lighting.light_count, 1);
+
+        let light = &lighting.lights[0];
+        assert_eq!(light.position, Vec2::new(32.0, 64.0));
+        assert_eq!(light.color, Vec4::from(torch.color));
+        assert_eq!(light.radius, 40.0);
+        assert_eq!(light.intensity, 0.5);
+    }
+}
@@ -1,4 +1,23 @@
 pub mod attack;
+pub mod audio;
 pub mod camera;
+pub mod collision_layers;
+pub mod data;
+#[cfg(feature = "debug")]
+pub mod debug;
+pub mod dialogue;
 pub mod entities;
+pub mod grid_rects;
+pub mod lighting;
+pub mod particles;
+pub mod projectile;
+pub mod quest;
+pub mod save;
+pub mod settings;
+#[cfg(test)]
+pub mod test_harness;
+pub mod tile_animation;
+pub mod ui;
+pub mod visibility;
 pub mod world;
+pub mod y_sort;
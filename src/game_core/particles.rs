@@ -0,0 +1,552 @@
+// Small CPU particle system, used for dust, hit sparks, death bursts and debris
+
+use crate::game_core::attack::{EnemyDeathEvent, EnemyHitEvent};
+use crate::game_core::entities::entity_components::Player;
+use crate::game_core::world::TileBrokenEvent;
+use crate::game_core::y_sort::{sort_z, YSort};
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+pub static DUST_PARTICLES_PATH: &str =
+    "tile_sets/mystic_woods_free_2.1/sprites/particles/dust_particles_01.png";
+pub static DUST_PARTICLE_FRAMES: usize = 4;
+
+// Below this speed the player counts as standing still (no footstep dust)
+pub static FOOTSTEP_MIN_SPEED: f32 = 20.0;
+
+// Bursts start at the middle of whatever was hit, about this far above its feet
+pub static BURST_HEIGHT: f32 = 12.0;
+// Over every layer, for bursts while there is no sorted player to go by
+pub static UNSORTED_BURST_Z: f32 = 10.0;
+
+#[derive(Resource, Default)]
+pub struct ParticleAssets {
+    pub dust: Handle<TextureAtlas>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EmitterMode {
+    // Spawns this many particles once, then the emitter entity is despawned
+    OneShot(u32),
+    // Spawns `spawn_rate` particles per second while active
+    Continuous,
+}
+
+#[derive(Component, Clone)]
+pub struct ParticleEmitter {
+    pub mode: EmitterMode,
+    pub active: bool,
+    pub spawn_rate: f32,
+    pub lifetime: f32,
+    // Particles go in `direction`, rotated randomly by up to +- `spread` radians
+    pub direction: Vec2,
+    pub spread: f32,
+    pub speed: (f32, f32),
+    pub gravity: Vec2,
+    pub start_color: Color,
+    pub end_color: Color,
+    pub size: f32,
+    // When set, particles are drawn from the atlas and step through `frames` over their life
+    pub atlas: Option<Handle<TextureAtlas>>,
+    pub frames: usize,
+    spawn_accumulator: f32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::Continuous,
+            active: true,
+            spawn_rate: 10.0,
+            lifetime: 0.5,
+            direction: Vec2::Y,
+            spread: std::f32::consts::PI,
+            speed: (10.0, 20.0),
+            gravity: Vec2::ZERO,
+            start_color: Color::WHITE,
+            end_color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+            size: 2.0,
+            atlas: None,
+            frames: 1,
+            spawn_accumulator: 0.0,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    pub fn footstep_dust(atlas: Handle<TextureAtlas>) -> Self {
+        ParticleEmitter {
+            active: false,
+            spawn_rate: 8.0,
+            lifetime: 0.4,
+            direction: Vec2::Y,
+            spread: 0.8,
+            speed: (5.0, 12.0),
+            start_color: Color::rgba(1.0, 1.0, 1.0, 0.8),
+            end_color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+            atlas: Some(atlas),
+            frames: DUST_PARTICLE_FRAMES,
+            ..default()
+        }
+    }
+
+    pub fn hit_sparks() -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::OneShot(10),
+            lifetime: 0.25,
+            speed: (60.0, 120.0),
+            gravity: Vec2::new(0.0, -200.0),
+            start_color: Color::rgb(1.0, 0.9, 0.4),
+            end_color: Color::rgba(1.0, 0.3, 0.0, 0.0),
+            size: 1.5,
+            ..default()
+        }
+    }
+
+    pub fn slime_death_burst() -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::OneShot(24),
+            lifetime: 0.6,
+            speed: (30.0, 80.0),
+            gravity: Vec2::new(0.0, -150.0),
+            start_color: Color::rgb(0.45, 0.85, 0.35),
+            end_color: Color::rgba(0.2, 0.5, 0.15, 0.0),
+            size: 2.5,
+            ..default()
+        }
+    }
+
+    pub fn tile_debris() -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::OneShot(16),
+            lifetime: 0.5,
+            direction: Vec2::Y,
+            spread: 1.2,
+            speed: (40.0, 90.0),
+            gravity: Vec2::new(0.0, -300.0),
+            start_color: Color::rgb(0.55, 0.36, 0.2),
+            end_color: Color::rgba(0.35, 0.22, 0.12, 0.0),
+            size: 2.0,
+            ..default()
+        }
+    }
+}
+
+#[derive(Component, Clone, Default)]
+pub struct Particle {
+    pub velocity: Vec2,
+    pub gravity: Vec2,
+    pub age: f32,
+    pub lifetime: f32,
+    pub start_color: Color,
+    pub end_color: Color,
+    pub frames: usize,
+}
+
+// Marks the emitter that follows an entity around and kicks up dust when it moves
+#[derive(Component, Clone, Default)]
+pub struct FootstepDust;
+
+pub fn setup_particle_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let dust_texture = asset_server.load(DUST_PARTICLES_PATH);
+    let dust_atlas = TextureAtlas::from_grid(
+        dust_texture,
+        Vec2::new(12.0, 12.0),
+        DUST_PARTICLE_FRAMES,
+        1,
+        None,
+        None,
+    );
+
+    commands.insert_resource(ParticleAssets {
+        dust: texture_atlases.add(dust_atlas),
+    });
+}
+
+fn lerp_color(start: Color, end: Color, t: f32) -> Color {
+    let [r1, g1, b1, a1] = start.as_rgba_f32();
+    let [r2, g2, b2, a2] = end.as_rgba_f32();
+    Color::rgba(
+        r1 + (r2 - r1) * t,
+        g1 + (g2 - g1) * t,
+        b1 + (b2 - b1) * t,
+        a1 + (a2 - a1) * t,
+    )
+}
+
+fn spawn_particle(commands: &mut Commands, emitter: &ParticleEmitter, position: Vec3) {
+    let mut rng = rand::thread_rng();
+
+    let angle = rng.gen_range(-emitter.spread..=emitter.spread);
+    let speed = rng.gen_range(emitter.speed.0..=emitter.speed.1);
+    let velocity = Vec2::from_angle(angle).rotate(emitter.direction.normalize_or_zero()) * speed;
+
+    let particle = Particle {
+        velocity,
+        gravity: emitter.gravity,
+        age: 0.0,
+        lifetime: emitter.lifetime,
+        start_color: emitter.start_color,
+        end_color: emitter.end_color,
+        frames: emitter.frames.max(1),
+    };
+    let transform = Transform::from_translation(position);
+
+    match &emitter.atlas {
+        Some(atlas) => {
+            commands.spawn((
+                SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        color: emitter.start_color,
+                        ..default()
+                    },
+                    texture_atlas: atlas.clone(),
+                    transform,
+                    ..default()
+                },
+                particle,
+            ));
+        }
+        None => {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: emitter.start_color,
+                        custom_size: Some(Vec2::splat(emitter.size)),
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+                particle,
+            ));
+        }
+    }
+}
+
+// Bursts are sorted in the band of the entity layer, taken from the player, as
+// if they stood a bit lower than where they start. That puts them over what
+// they came from but still behind whatever stands in front of it
+fn burst_z(players: &Query<&YSort, With<Player>>, position: Vec2) -> f32 {
+    match players.iter().next().and_then(|y_sort| y_sort.layer_z) {
+        Some(layer_z) => sort_z(layer_z, position.y - BURST_HEIGHT),
+        None => UNSORTED_BURST_Z,
+    }
+}
+
+pub fn emit_particles_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut emitters: Query<(Entity, &mut ParticleEmitter, &GlobalTransform)>,
+) {
+    for (entity, mut emitter, transform) in emitters.iter_mut() {
+        // Particles live in world space, so they don't follow the emitter after spawning
+        let position = transform.translation();
+
+        match emitter.mode {
+            EmitterMode::OneShot(count) => {
+                for _ in 0..count {
+                    spawn_particle(&mut commands, &emitter, position);
+                }
+                commands.entity(entity).despawn_recursive();
+            }
+            EmitterMode::Continuous => {
+                if !emitter.active {
+                    emitter.spawn_accumulator = 0.0;
+                    continue;
+                }
+
+                emitter.spawn_accumulator += emitter.spawn_rate * time.delta_seconds();
+                while emitter.spawn_accumulator >= 1.0 {
+                    emitter.spawn_accumulator -= 1.0;
+                    spawn_particle(&mut commands, &emitter, position);
+                }
+            }
+        }
+    }
+}
+
+pub fn update_particles_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        Option<&mut Sprite>,
+        Option<&mut TextureAtlasSprite>,
+    )>,
+) {
+    let delta = time.delta_seconds();
+
+    for (entity, mut particle, mut transform, sprite, atlas_sprite) in particles.iter_mut() {
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let gravity = particle.gravity;
+        particle.velocity += gravity * delta;
+        transform.translation += (particle.velocity * delta).extend(0.0);
+
+        let life = particle.age / particle.lifetime;
+        let color = lerp_color(particle.start_color, particle.end_color, life);
+
+        if let Some(mut sprite) = sprite {
+            sprite.color = color;
+        }
+        if let Some(mut atlas_sprite) = atlas_sprite {
+            atlas_sprite.color = color;
+            atlas_sprite.index =
+                ((life * particle.frames as f32) as usize).min(particle.frames - 1);
+        }
+    }
+}
+
+pub fn attach_footstep_dust_system(
+    mut commands: Commands,
+    players: Query<Entity, Added<Player>>,
+    particle_assets: Res<ParticleAssets>,
+) {
+    for player in players.iter() {
+        let emitter = commands
+            .spawn((
+                ParticleEmitter::footstep_dust(particle_assets.dust.clone()),
+                FootstepDust,
+                // At the feet of the player, slightly behind it
+                TransformBundle::from(Transform::from_xyz(0.0, -9.0, -0.1)),
+            ))
+            .id();
+        commands.entity(player).add_child(emitter);
+    }
+}
+
+pub fn footstep_dust_system(
+    mut emitters: Query<(&Parent, &mut ParticleEmitter), With<FootstepDust>>,
+    velocities: Query<&Velocity>,
+) {
+    for (parent, mut emitter) in emitters.iter_mut() {
+        if let Ok(velocity) = velocities.get(parent.get()) {
+            emitter.active = velocity.linvel.length() > FOOTSTEP_MIN_SPEED;
+            emitter.direction = -velocity.linvel.normalize_or_zero() + Vec2::Y;
+        }
+    }
+}
+
+pub fn combat_particles_system(
+    mut commands: Commands,
+    mut hit_events: EventReader<EnemyHitEvent>,
+    mut death_events: EventReader<EnemyDeathEvent>,
+    players: Query<&YSort, With<Player>>,
+) {
+    for hit in hit_events.iter() {
+        let z = burst_z(&players, hit.position);
+        commands.spawn((
+            ParticleEmitter::hit_sparks(),
+            TransformBundle::from(Transform::from_translation(hit.position.extend(z))),
+        ));
+    }
+
+    for death in death_events.iter() {
+        let z = burst_z(&players, death.position);
+        commands.spawn((
+            ParticleEmitter::slime_death_burst(),
+            TransformBundle::from(Transform::from_translation(death.position.extend(z))),
+        ));
+    }
+}
+
+pub fn tile_debris_particles_system(
+    mut commands: Commands,
+    mut broken_events: EventReader<TileBrokenEvent>,
+    players: Query<&YSort, With<Player>>,
+) {
+    for broken in broken_events.iter() {
+        let z = burst_z(&players, broken.position);
+        commands.spawn((
+            ParticleEmitter::tile_debris(),
+            TransformBundle::from(Transform::from_translation(broken.position.extend(z))),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::entities::enemy_archetypes::EnemyArchetype;
+    use crate::game_core::test_harness::{TestApp, TEST_TIMESTEP_SECS};
+
+    // Particles drawn as plain sprites, which leaves the footstep dust out
+    fn sprite_particles(test_app: &mut TestApp) -> Vec<(Particle, Vec3)> {
+        test_app
+            .app
+            .world
+            .query_filtered::<(&Particle, &Transform), With<Sprite>>()
+            .iter(&test_app.app.world)
+            .map(|(particle, transform)| (particle.clone(), transform.translation))
+            .collect()
+    }
+
+    fn dust_particles(test_app: &mut TestApp) -> usize {
+        test_app
+            .app
+            .world
+            .query_filtered::<(), (With<Particle>, With<TextureAtlasSprite>)>()
+            .iter(&test_app.app.world)
+            .count()
+    }
+
+    fn player_layer_z(test_app: &mut TestApp) -> f32 {
+        let player = test_app.player();
+        test_app.get::<YSort>(player).unwrap().layer_z.unwrap()
+    }
+
+    #[test]
+    fn one_shot_emitters_burst_once_and_their_particles_die_out() {
+        let mut test_app = TestApp::new();
+        let emitter = test_app
+            .app
+            .world
+            .spawn((
+                ParticleEmitter {
+                    mode: EmitterMode::OneShot(5),
+                    lifetime: 0.1,
+                    ..default()
+                },
+                TransformBundle::from(Transform::from_xyz(32.0, 48.0, 2.0)),
+            ))
+            .id();
+
+        test_app.step(1);
+        assert!(!test_app.exists(emitter));
+        let particles = sprite_particles(&mut test_app);
+        assert_eq!(particles.len(), 5);
+        assert!(particles.iter().all(|(_, position)| position.z == 2.0));
+
+        test_app.step((0.1 / TEST_TIMESTEP_SECS) as u32 + 2);
+        assert!(sprite_particles(&mut test_app).is_empty());
+    }
+
+    #[test]
+    fn continuous_emitters_only_spawn_while_active() {
+        let mut test_app = TestApp::new();
+        let emitter = test_app
+            .app
+            .world
+            .spawn((
+                ParticleEmitter {
+                    active: false,
+                    spawn_rate: 1.0 / TEST_TIMESTEP_SECS,
+                    lifetime: 1.0,
+                    ..default()
+                },
+                TransformBundle::default(),
+            ))
+            .id();
+
+        test_app.step(5);
+        assert!(sprite_particles(&mut test_app).is_empty());
+
+        test_app.get_mut::<ParticleEmitter>(emitter).unwrap().active = true;
+        test_app.step(5);
+        // About one a frame, depending on how the accumulator rounds
+        let spawned = sprite_particles(&mut test_app).len();
+        assert!((4..=5).contains(&spawned), "spawned {}", spawned);
+        assert!(test_app.exists(emitter));
+    }
+
+    #[test]
+    fn walking_kicks_up_dust() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        let (emitter, parent) = test_app
+            .app
+            .world
+            .query_filtered::<(Entity, &Parent), With<FootstepDust>>()
+            .single(&test_app.app.world);
+        assert_eq!(parent.get(), player);
+
+        test_app.step(5);
+        assert!(!test_app.get::<ParticleEmitter>(emitter).unwrap().active);
+        assert_eq!(dust_particles(&mut test_app), 0);
+
+        test_app.press_key(KeyCode::D);
+        test_app.step(20);
+        assert!(test_app.get::<ParticleEmitter>(emitter).unwrap().active);
+        assert!(dust_particles(&mut test_app) > 0);
+
+        // Standing still again, the dust settles and no more is made
+        test_app.release_key(KeyCode::D);
+        test_app.step(60);
+        assert!(!test_app.get::<ParticleEmitter>(emitter).unwrap().active);
+        assert_eq!(dust_particles(&mut test_app), 0);
+    }
+
+    #[test]
+    fn hits_throw_sparks_over_the_enemy() {
+        let mut test_app = TestApp::sandbox();
+        let layer_z = player_layer_z(&mut test_app);
+        let position = Vec2::new(100.0, 80.0);
+        test_app.app.world.send_event(EnemyHitEvent {
+            enemy: Entity::from_raw(u32::MAX),
+            position,
+            damage: 1.0,
+        });
+        test_app.step(2);
+
+        let sparks = sprite_particles(&mut test_app);
+        assert_eq!(sparks.len(), 10);
+        // In front of the enemy that was hit, but still in the band of its layer
+        let enemy_z = sort_z(
+            layer_z,
+            position.y + EnemyArchetype::default().sort_anchor(),
+        );
+        assert!(sparks
+            .iter()
+            .all(|(_, spark)| spark.z > enemy_z && spark.z < layer_z + 1.0));
+    }
+
+    #[test]
+    fn deaths_burst_into_falling_particles() {
+        let mut test_app = TestApp::sandbox();
+        let layer_z = player_layer_z(&mut test_app);
+        let position = Vec2::new(100.0, 80.0);
+        test_app.app.world.send_event(EnemyDeathEvent {
+            enemy: Entity::from_raw(u32::MAX),
+            position,
+            name: "test".to_string(),
+            archetype: "slime".to_string(),
+            xp: 0,
+        });
+        test_app.step(2);
+
+        let burst = sprite_particles(&mut test_app);
+        assert_eq!(burst.len(), 24);
+        let z = sort_z(layer_z, position.y - BURST_HEIGHT);
+        assert!(burst.iter().all(|(_, particle)| particle.z == z));
+
+        // Gravity pulls every particle down the same way
+        let mean_fall = |particles: &[(Particle, Vec3)]| {
+            particles
+                .iter()
+                .map(|(particle, _)| particle.velocity.y)
+                .sum::<f32>()
+                / particles.len() as f32
+        };
+        test_app.step(5);
+        let falling = sprite_particles(&mut test_app);
+        let gravity = ParticleEmitter::slime_death_burst().gravity.y;
+        let expected = mean_fall(&burst) + gravity * 5.0 * TEST_TIMESTEP_SECS;
+        assert!((mean_fall(&falling) - expected).abs() < 1.0);
+
+        let lifetime = ParticleEmitter::slime_death_burst().lifetime;
+        test_app.step((lifetime / TEST_TIMESTEP_SECS) as u32);
+        assert!(sprite_particles(&mut test_app).is_empty());
+    }
+}
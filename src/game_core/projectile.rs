@@ -0,0 +1,200 @@
+// Projectiles for ranged attacks, fired by enemies and by the player
+// They fly in a straight line until they hit something they can damage, a
+// World collider (fences, walls, closed doors...) or run out of lifetime.
+// Damage goes through DamageEvent, same as melee
+
+use crate::game_core::attack::DamageEvent;
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::entities::entity_components::{EnemyStats, Health};
+use crate::game_core::world::BreakableTile;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ProjectileSpec {
+    pub speed: f32,
+    pub lifetime: f32,
+    // Radius of the collider, the sprite is a square of twice that
+    pub radius: f32,
+    pub color: (f32, f32, f32),
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Projectile {
+    // Never hit by its own projectiles
+    pub owner: Entity,
+    pub damage: f32,
+    pub lifetime: f32,
+    pub age: f32,
+}
+
+// `layer` is PlayerProjectile or EnemyAttack, which decides what the projectile can hit
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    spec: ProjectileSpec,
+    owner: Entity,
+    damage: f32,
+    layer: Layer,
+    position: Vec2,
+    direction: Vec2,
+) -> Entity {
+    let (r, g, b) = spec.color;
+
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(r, g, b),
+                    custom_size: Some(Vec2::splat(spec.radius * 2.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(10.0)),
+                ..default()
+            },
+            Projectile {
+                owner,
+                damage,
+                lifetime: spec.lifetime,
+                age: 0.0,
+            },
+            RigidBody::KinematicVelocityBased,
+            Velocity::linear(direction.normalize_or_zero() * spec.speed),
+            Collider::ball(spec.radius),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            // Kinematic bodies don't collide with fixed ones by default
+            ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+            layer.collision_groups(),
+        ))
+        .id()
+}
+
+pub fn projectile_lifetime_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut projectiles: Query<(Entity, &mut Projectile)>,
+) {
+    for (entity, mut projectile) in projectiles.iter_mut() {
+        projectile.age += time.delta_seconds();
+        if projectile.age >= projectile.lifetime {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn projectile_hit_system(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    projectiles: Query<&Projectile>,
+    targets: Query<(), Or<(With<EnemyStats>, With<Health>, With<BreakableTile>)>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for collision_event in collision_events.iter() {
+        let CollisionEvent::Started(entity1, entity2, _) = *collision_event else {
+            continue;
+        };
+
+        // Rapier doesn't guarantee which of the two entities comes first
+        let (projectile_entity, projectile, other) =
+            match (projectiles.get(entity1), projectiles.get(entity2)) {
+                (Ok(projectile), _) => (entity1, projectile, entity2),
+                (_, Ok(projectile)) => (entity2, projectile, entity1),
+                _ => continue,
+            };
+
+        if other == projectile.owner {
+            continue;
+        }
+
+        // Anything else it can touch is solid, so it stops there either way
+        if targets.contains(other) {
+            damage_events.send(DamageEvent {
+                target: other,
+                amount: projectile.damage,
+            });
+        }
+        commands.entity(projectile_entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::entities::player::{HEALTH, RANGED_ATTACK_POWER};
+    use crate::game_core::test_harness::TestApp;
+
+    fn player_position(test_app: &mut TestApp) -> Vec2 {
+        let player = test_app.player();
+        test_app
+            .get::<Transform>(player)
+            .unwrap()
+            .translation
+            .truncate()
+    }
+
+    fn projectile_count(test_app: &mut TestApp) -> usize {
+        test_app
+            .app
+            .world
+            .query::<&Projectile>()
+            .iter(&test_app.app.world)
+            .count()
+    }
+
+    #[test]
+    fn archer_shoots_the_player() {
+        let mut test_app = TestApp::sandbox();
+        let start = player_position(&mut test_app) + Vec2::new(80.0, 0.0);
+        let archer = test_app.spawn_enemy("skeleton_archer", start);
+
+        test_app.step(10);
+        assert_eq!(projectile_count(&mut test_app), 1);
+        // Keeps its distance instead of walking up to the player
+        let position = test_app
+            .get::<Transform>(archer)
+            .unwrap()
+            .translation
+            .truncate();
+        assert!(position.distance(player_position(&mut test_app)) > 60.0);
+
+        test_app.step(60);
+        let player = test_app.player();
+        assert!(test_app.get::<Health>(player).unwrap().0 < HEALTH);
+    }
+
+    #[test]
+    fn player_projectile_kills_slime() {
+        let mut test_app = TestApp::sandbox();
+        // Without a window the projectile goes right
+        let position = player_position(&mut test_app) + Vec2::X * 60.0;
+        let slime = test_app.spawn_enemy("slime", position);
+        test_app.step(1);
+        test_app.get_mut::<EnemyStats>(slime).unwrap().health = RANGED_ATTACK_POWER;
+
+        test_app.click_mouse(MouseButton::Right);
+        test_app.step(30);
+
+        assert!(!test_app.exists(slime));
+        assert_eq!(projectile_count(&mut test_app), 0);
+    }
+
+    #[test]
+    fn projectiles_stop_at_fences() {
+        let mut test_app = TestApp::sandbox();
+        let fence_position = player_position(&mut test_app) + Vec2::X * 30.0;
+        test_app.app.world.spawn((
+            Collider::cuboid(8.0, 8.0),
+            Layer::World.collision_groups(),
+            TransformBundle::from(Transform::from_translation(fence_position.extend(0.0))),
+        ));
+        test_app.step(1);
+
+        test_app.click_mouse(MouseButton::Right);
+        assert_eq!(projectile_count(&mut test_app), 1);
+
+        // Well before the end of its lifetime
+        test_app.step(10);
+        assert_eq!(projectile_count(&mut test_app), 0);
+    }
+}
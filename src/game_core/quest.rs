@@ -0,0 +1,408 @@
+// Quests, defined in assets/data/quests.ron
+// A quest starts once its start flag is set (or right away without one), its
+// objectives are advanced by QuestEvents sent from combat, chests and level
+// changes, and it pays out its reward when every objective is done.
+// Progress lives in the save data, so it carries over between sessions
+
+use crate::game_core::attack::EnemyDeathEvent;
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::entities::chest::ChestOpenedEvent;
+use crate::game_core::entities::entity_components::{Experience, Inventory, Player};
+use crate::game_core::entities::levelling::XpRewardEvent;
+use crate::game_core::save::SaveData;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub static QUESTS_PATH: &str = "assets/data/quests.ron";
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum ObjectiveGoal {
+    // Enemies of an archetype, see enemy_archetypes.ron
+    Kill { archetype: String, count: u32 },
+    // By the chest's "Chest_id" field
+    OpenChest(String),
+    // The player's level, counts levels gained before the quest started too
+    ReachLevel(u32),
+    // By the LDtk level identifier
+    EnterLevel(String),
+}
+
+impl ObjectiveGoal {
+    pub fn target(&self) -> u32 {
+        match self {
+            ObjectiveGoal::Kill { count, .. } => *count,
+            ObjectiveGoal::ReachLevel(level) => *level,
+            ObjectiveGoal::OpenChest(_) | ObjectiveGoal::EnterLevel(_) => 1,
+        }
+    }
+
+    fn advance(&self, progress: u32, event: &QuestEvent) -> u32 {
+        match (self, event) {
+            (ObjectiveGoal::Kill { archetype, .. }, QuestEvent::EnemyKilled(killed))
+                if archetype == killed =>
+            {
+                progress + 1
+            }
+            (ObjectiveGoal::OpenChest(chest_id), QuestEvent::ChestOpened(opened))
+                if chest_id == opened =>
+            {
+                1
+            }
+            (ObjectiveGoal::EnterLevel(level), QuestEvent::LevelEntered(entered))
+                if level == entered =>
+            {
+                1
+            }
+            _ => progress,
+        }
+        .min(self.target())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Objective {
+    // Shown in the quest log
+    pub text: String,
+    pub goal: ObjectiveGoal,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct QuestReward {
+    pub xp: u32,
+    pub keys: Vec<String>,
+    // Story flags, so dialogue can react to finished quests
+    pub flags: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct QuestDefinition {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    // Starts as soon as the game does when None
+    pub start_flag: Option<String>,
+    pub objectives: Vec<Objective>,
+    pub reward: QuestReward,
+}
+
+// In the order the quest log lists them
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct QuestDefinitions {
+    pub quests: Vec<QuestDefinition>,
+}
+
+impl QuestDefinitions {
+    pub fn load() -> QuestDefinitions {
+        load_ron_or_default(QUESTS_PATH)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&QuestDefinition> {
+        self.quests.iter().find(|quest| quest.id == id)
+    }
+}
+
+// A started quest, kept in the save data by quest id
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuestState {
+    // One entry per objective
+    pub progress: Vec<u32>,
+    pub completed: bool,
+}
+
+// Something happened that objectives might be waiting for
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuestEvent {
+    EnemyKilled(String),
+    ChestOpened(String),
+    LevelEntered(String),
+}
+
+pub struct QuestCompletedEvent {
+    pub quest: String,
+}
+
+pub fn start_quests_system(definitions: Res<QuestDefinitions>, mut save_data: ResMut<SaveData>) {
+    // Only flags can start a quest, and they are in the save data
+    if !save_data.is_changed() && !definitions.is_changed() {
+        return;
+    }
+
+    let starting: Vec<&QuestDefinition> = definitions
+        .quests
+        .iter()
+        .filter(|quest| !save_data.quests.contains_key(&quest.id))
+        .filter(|quest| match &quest.start_flag {
+            Some(flag) => save_data.flags.contains(flag),
+            None => true,
+        })
+        .collect();
+
+    for quest in starting {
+        info!("Quest started: {}", quest.title);
+        save_data.quests.insert(
+            quest.id.clone(),
+            QuestState {
+                progress: vec![0; quest.objectives.len()],
+                completed: false,
+            },
+        );
+    }
+}
+
+pub fn gameplay_quest_events_system(
+    mut death_events: EventReader<EnemyDeathEvent>,
+    mut chest_events: EventReader<ChestOpenedEvent>,
+    mut quest_events: EventWriter<QuestEvent>,
+) {
+    for death in death_events.iter() {
+        quest_events.send(QuestEvent::EnemyKilled(death.archetype.clone()));
+    }
+    for chest in chest_events.iter() {
+        quest_events.send(QuestEvent::ChestOpened(chest.chest_id.clone()));
+    }
+}
+
+pub fn level_quest_events_system(
+    mut level_events: EventReader<LevelEvent>,
+    level_query: Query<&Handle<LdtkLevel>>,
+    levels: Res<Assets<LdtkLevel>>,
+    mut quest_events: EventWriter<QuestEvent>,
+) {
+    for level_event in level_events.iter() {
+        let LevelEvent::Spawned(level_iid) = level_event else {
+            continue;
+        };
+
+        if let Some(level) = level_query
+            .iter()
+            .filter_map(|handle| levels.get(handle))
+            .find(|level| &level.level.iid == level_iid)
+        {
+            quest_events.send(QuestEvent::LevelEntered(level.level.identifier.clone()));
+        }
+    }
+}
+
+pub fn quest_progress_system(
+    mut quest_events: EventReader<QuestEvent>,
+    definitions: Res<QuestDefinitions>,
+    mut save_data: ResMut<SaveData>,
+    mut players: Query<(&Experience, &mut Inventory), With<Player>>,
+    mut xp_events: EventWriter<XpRewardEvent>,
+    mut completed_events: EventWriter<QuestCompletedEvent>,
+) {
+    let Ok((experience, mut inventory)) = players.get_single_mut() else {
+        return;
+    };
+    let events: Vec<QuestEvent> = quest_events.iter().cloned().collect();
+
+    for quest in definitions.quests.iter() {
+        let Some(state) = save_data.quests.get(&quest.id) else {
+            continue;
+        };
+        if state.completed {
+            continue;
+        }
+
+        let progress: Vec<u32> = quest
+            .objectives
+            .iter()
+            .enumerate()
+            .map(|(index, objective)| {
+                let progress = state.progress.get(index).copied().unwrap_or(0);
+                match objective.goal {
+                    ObjectiveGoal::ReachLevel(level) => experience.level.min(level),
+                    _ => events.iter().fold(progress, |progress, event| {
+                        objective.goal.advance(progress, event)
+                    }),
+                }
+            })
+            .collect();
+        // Leaves the save data alone when nothing moved, so it isn't saved every frame
+        if progress == state.progress {
+            continue;
+        }
+
+        let completed = quest
+            .objectives
+            .iter()
+            .zip(progress.iter())
+            .all(|(objective, &progress)| progress >= objective.goal.target());
+        save_data.quests.insert(
+            quest.id.clone(),
+            QuestState {
+                progress,
+                completed,
+            },
+        );
+        if !completed {
+            continue;
+        }
+
+        info!("Quest completed: {}", quest.title);
+        let reward = &quest.reward;
+        if reward.xp > 0 {
+            xp_events.send(XpRewardEvent { xp: reward.xp });
+        }
+        for key_id in reward.keys.iter() {
+            inventory.add_key(key_id);
+        }
+        if !reward.keys.is_empty() {
+            save_data.keys = inventory.keys.clone();
+        }
+        save_data.flags.extend(reward.flags.iter().cloned());
+        completed_events.send(QuestCompletedEvent {
+            quest: quest.id.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::entities::chest::Chest;
+    use crate::game_core::entities::levelling::LevellingConfig;
+    use crate::game_core::test_harness::TestApp;
+
+    fn quest(id: &str, goals: Vec<ObjectiveGoal>, reward: QuestReward) -> QuestDefinition {
+        QuestDefinition {
+            id: id.to_string(),
+            title: id.to_string(),
+            objectives: goals
+                .into_iter()
+                .map(|goal| Objective {
+                    text: String::new(),
+                    goal,
+                })
+                .collect(),
+            reward,
+            ..default()
+        }
+    }
+
+    fn sandbox_with(quests: Vec<QuestDefinition>) -> TestApp {
+        let mut test_app = TestApp::new();
+        test_app.app.insert_resource(QuestDefinitions { quests });
+        test_app.load_sandbox();
+        test_app
+    }
+
+    fn state(test_app: &TestApp, id: &str) -> Option<QuestState> {
+        test_app
+            .app
+            .world
+            .resource::<SaveData>()
+            .quests
+            .get(id)
+            .cloned()
+    }
+
+    fn kill(test_app: &mut TestApp, archetype: &str) {
+        test_app.app.world.send_event(EnemyDeathEvent {
+            enemy: Entity::from_raw(u32::MAX),
+            position: Vec2::ZERO,
+            name: "test".to_string(),
+            archetype: archetype.to_string(),
+            xp: 0,
+        });
+        test_app.step(1);
+    }
+
+    #[test]
+    fn kills_complete_the_quest_and_pay_xp() {
+        let reward = QuestReward {
+            xp: 30,
+            ..default()
+        };
+        let goal = ObjectiveGoal::Kill {
+            archetype: "slime".to_string(),
+            count: 2,
+        };
+        let mut test_app = sandbox_with(vec![quest("slimes", vec![goal], reward)]);
+        assert_eq!(state(&test_app, "slimes").unwrap().progress, vec![0]);
+
+        kill(&mut test_app, "skeleton");
+        kill(&mut test_app, "slime");
+        assert_eq!(state(&test_app, "slimes").unwrap().progress, vec![1]);
+
+        kill(&mut test_app, "slime");
+        kill(&mut test_app, "slime");
+        let done = state(&test_app, "slimes").unwrap();
+        assert!(done.completed);
+        assert_eq!(done.progress, vec![2]);
+
+        let config = test_app.app.world.resource::<LevellingConfig>().clone();
+        let player = test_app.player();
+        let experience = test_app.get::<Experience>(player).unwrap();
+        assert_eq!(experience.level, 2);
+        assert_eq!(experience.xp, 30 - config.xp_to_next(1).unwrap());
+    }
+
+    #[test]
+    fn flag_starts_the_quest_and_a_chest_finishes_it() {
+        let reward = QuestReward {
+            keys: vec!["gate".to_string()],
+            flags: vec!["chest_quest_done".to_string()],
+            ..default()
+        };
+        let mut errand = quest(
+            "errand",
+            vec![ObjectiveGoal::OpenChest("old_chest".to_string())],
+            reward,
+        );
+        errand.start_flag = Some("errand_given".to_string());
+        let mut test_app = sandbox_with(vec![errand]);
+        assert!(state(&test_app, "errand").is_none());
+
+        test_app
+            .app
+            .world
+            .resource_mut::<SaveData>()
+            .flags
+            .insert("errand_given".to_string());
+        test_app.step(1);
+        assert!(state(&test_app, "errand").is_some());
+
+        let player = test_app.player();
+        let position = test_app.get::<Transform>(player).unwrap().translation;
+        test_app.app.world.spawn((
+            Chest {
+                iid: "chest-iid".to_string(),
+                chest_id: "old_chest".to_string(),
+                open: false,
+            },
+            TransformBundle::from(Transform::from_translation(
+                position + Vec3::new(16.0, 0.0, 0.0),
+            )),
+        ));
+        test_app.step(1);
+        test_app.press_key(KeyCode::E);
+        test_app.step(2);
+
+        assert!(state(&test_app, "errand").unwrap().completed);
+        assert!(test_app.get::<Inventory>(player).unwrap().has_key("gate"));
+        let save_data = test_app.app.world.resource::<SaveData>();
+        assert!(save_data.opened_chests.contains("chest-iid"));
+        assert!(save_data.flags.contains("chest_quest_done"));
+    }
+
+    #[test]
+    fn levels_gained_before_the_quest_count() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        test_app.get_mut::<Experience>(player).unwrap().level = 3;
+
+        let goal = ObjectiveGoal::ReachLevel(3);
+        test_app.app.insert_resource(QuestDefinitions {
+            quests: vec![quest("level", vec![goal], QuestReward::default())],
+        });
+        test_app.step(2);
+
+        assert!(state(&test_app, "level").unwrap().completed);
+    }
+}
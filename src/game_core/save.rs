@@ -0,0 +1,70 @@
+// Progress that survives restarting the game, saved next to settings.ron
+
+use crate::game_core::data::{load_ron_or_default, resolve_path, save_ron};
+use crate::game_core::quest::QuestState;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+pub static SAVE_PATH: &str = "save.ron";
+
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    // Broken tiles for each level, by level iid
+    pub broken_tiles: HashMap<String, HashSet<(i32, i32)>>,
+    // Entity iids of the doors that have been opened
+    pub opened_doors: HashSet<String>,
+    // Entity iids of the keys that have been picked up
+    pub collected_keys: HashSet<String>,
+    // Keys the player is carrying, by key id
+    pub keys: HashMap<String, u32>,
+    // Story flags set by dialogue and quests
+    pub flags: HashSet<String>,
+    // Entity iids of the chests that have been opened
+    pub opened_chests: HashSet<String>,
+    // Started quests, by quest id
+    pub quests: HashMap<String, QuestState>,
+    // Entity iids of the triggers that only fire once and already have
+    pub fired_triggers: HashSet<String>,
+    // The player's level and XP towards the next one, level 0 until the
+    // first XP is gained
+    pub level: u32,
+    pub xp: u32,
+}
+
+impl SaveData {
+    // No save yet is a fresh game, not something to warn about
+    pub fn load() -> SaveData {
+        if !resolve_path(SAVE_PATH).exists() {
+            return SaveData::default();
+        }
+        load_ron_or_default(SAVE_PATH)
+    }
+
+    pub fn save(&self) {
+        save_ron(SAVE_PATH, self);
+    }
+
+    pub fn break_tile(&mut self, level_iid: &str, coords: GridCoords) {
+        self.broken_tiles
+            .entry(level_iid.to_string())
+            .or_default()
+            .insert((coords.x, coords.y));
+    }
+
+    pub fn broken_tiles(&self, level_iid: &str) -> impl Iterator<Item = GridCoords> + '_ {
+        self.broken_tiles
+            .get(level_iid)
+            .into_iter()
+            .flatten()
+            .map(|&(x, y)| GridCoords { x, y })
+    }
+}
+
+pub fn save_game_system(save_data: Res<SaveData>) {
+    if save_data.is_changed() && !save_data.is_added() {
+        save_data.save();
+    }
+}
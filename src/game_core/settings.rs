@@ -0,0 +1,276 @@
+// User configuration, loaded from and saved to a ron file next to the game
+
+use crate::game_core::data::{load_ron_or_default, resolve_path, save_ron};
+use bevy::prelude::*;
+use bevy::window::PresentMode;
+use serde::{Deserialize, Serialize};
+
+pub static SETTINGS_PATH: &str = "settings.ron";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    // Every volume is between 0 and 1, master multiplies the other two
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master_volume: 1.0,
+            music_volume: 0.6,
+            sfx_volume: 0.8,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn music(&self) -> f32 {
+        self.master_volume * self.music_volume
+    }
+
+    pub fn sfx(&self) -> f32 {
+        self.master_volume * self.sfx_volume
+    }
+}
+
+// Resolutions offered by the settings menu
+pub static RESOLUTIONS: [(f32, f32); 4] = [
+    (1024.0, 576.0),
+    (1280.0, 720.0),
+    (1600.0, 900.0),
+    (1920.0, 1080.0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayMode {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl DisplayMode {
+    pub fn window_mode(&self) -> WindowMode {
+        match self {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: f32,
+    pub height: f32,
+    pub mode: DisplayMode,
+    pub vsync: bool,
+    pub ui_scale: f64,
+    // 2D lighting and the day/night cycle, off draws the levels as they are.
+    // Also the fallback for GPUs the lighting shader doesn't work on
+    pub lighting: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            width: 1024.0,
+            height: 576.0,
+            mode: DisplayMode::Windowed,
+            vsync: true,
+            ui_scale: 1.0,
+            lighting: true,
+        }
+    }
+}
+
+impl WindowSettings {
+    // Whether going from `other` to these settings needs the window itself changed,
+    // the UI scale and lighting don't touch it
+    pub fn window_differs(&self, other: &WindowSettings) -> bool {
+        self.width != other.width
+            || self.height != other.height
+            || self.mode != other.mode
+            || self.vsync != other.vsync
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Interact,
+    Dash,
+    HeavyAttack,
+    QuestLog,
+    Map,
+    SettingsMenu,
+}
+
+pub static ACTIONS: [Action; 10] = [
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Interact,
+    Action::Dash,
+    Action::HeavyAttack,
+    Action::QuestLog,
+    Action::Map,
+    Action::SettingsMenu,
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub move_up: Vec<KeyCode>,
+    pub move_down: Vec<KeyCode>,
+    pub move_left: Vec<KeyCode>,
+    pub move_right: Vec<KeyCode>,
+    pub interact: Vec<KeyCode>,
+    pub dash: Vec<KeyCode>,
+    pub heavy_attack: Vec<KeyCode>,
+    pub quest_log: Vec<KeyCode>,
+    pub map: Vec<KeyCode>,
+    pub settings_menu: Vec<KeyCode>,
+    pub attack: MouseButton,
+    pub secondary_attack: MouseButton,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            move_up: vec![KeyCode::W, KeyCode::Up],
+            move_down: vec![KeyCode::S, KeyCode::Down],
+            move_left: vec![KeyCode::A, KeyCode::Left],
+            move_right: vec![KeyCode::D, KeyCode::Right],
+            interact: vec![KeyCode::E],
+            dash: vec![KeyCode::Space],
+            heavy_attack: vec![KeyCode::Q],
+            quest_log: vec![KeyCode::J],
+            map: vec![KeyCode::M],
+            settings_menu: vec![KeyCode::Escape],
+            attack: MouseButton::Left,
+            secondary_attack: MouseButton::Right,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn keys(&self, action: Action) -> &Vec<KeyCode> {
+        match action {
+            Action::MoveUp => &self.move_up,
+            Action::MoveDown => &self.move_down,
+            Action::MoveLeft => &self.move_left,
+            Action::MoveRight => &self.move_right,
+            Action::Interact => &self.interact,
+            Action::Dash => &self.dash,
+            Action::HeavyAttack => &self.heavy_attack,
+            Action::QuestLog => &self.quest_log,
+            Action::Map => &self.map,
+            Action::SettingsMenu => &self.settings_menu,
+        }
+    }
+
+    pub fn keys_mut(&mut self, action: Action) -> &mut Vec<KeyCode> {
+        match action {
+            Action::MoveUp => &mut self.move_up,
+            Action::MoveDown => &mut self.move_down,
+            Action::MoveLeft => &mut self.move_left,
+            Action::MoveRight => &mut self.move_right,
+            Action::Interact => &mut self.interact,
+            Action::Dash => &mut self.dash,
+            Action::HeavyAttack => &mut self.heavy_attack,
+            Action::QuestLog => &mut self.quest_log,
+            Action::Map => &mut self.map,
+            Action::SettingsMenu => &mut self.settings_menu,
+        }
+    }
+
+    // The action other than `action` that `key` is already bound to, if any
+    pub fn bound_elsewhere(&self, key: KeyCode, action: Action) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .copied()
+            .find(|&other| other != action && self.keys(other).contains(&key))
+    }
+
+    pub fn pressed(&self, action: Action, keyboard_input: &Input<KeyCode>) -> bool {
+        keyboard_input.any_pressed(self.keys(action).iter().copied())
+    }
+
+    pub fn just_pressed(&self, action: Action, keyboard_input: &Input<KeyCode>) -> bool {
+        keyboard_input.any_just_pressed(self.keys(action).iter().copied())
+    }
+}
+
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub audio: AudioSettings,
+    pub key_bindings: KeyBindings,
+}
+
+impl Settings {
+    // Falls back to the defaults if the file is missing or can't be parsed,
+    // so a broken config never stops the game from starting
+    pub fn load() -> Settings {
+        // No file yet is the normal first launch, not worth a warning
+        if !resolve_path(SETTINGS_PATH).exists() {
+            return Settings::default();
+        }
+        load_ron_or_default(SETTINGS_PATH)
+    }
+
+    pub fn save(&self) {
+        save_ron(SETTINGS_PATH, self);
+    }
+}
+
+pub fn save_settings_system(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
+
+pub fn apply_window_settings_system(
+    settings: Res<Settings>,
+    mut applied: Local<Option<WindowSettings>>,
+    mut windows: ResMut<Windows>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    // Volumes and key bindings change the settings too, those leave the window alone
+    if applied.as_ref() == Some(&settings.window) {
+        return;
+    }
+
+    let window_settings = &settings.window;
+    let window_changed = applied
+        .as_ref()
+        .map_or(true, |previous| window_settings.window_differs(previous));
+    if window_changed {
+        if let Some(window) = windows.get_primary_mut() {
+            window.set_resolution(window_settings.width, window_settings.height);
+            window.set_mode(window_settings.mode.window_mode());
+            window.set_present_mode(window_settings.present_mode());
+        }
+    }
+    if ui_scale.scale != window_settings.ui_scale {
+        ui_scale.scale = window_settings.ui_scale;
+    }
+    *applied = Some(window_settings.clone());
+}
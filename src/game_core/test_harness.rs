@@ -0,0 +1,431 @@
+// Headless App for testing gameplay systems
+// Runs Rapier and the game systems without a window or renderer, with a fixed
+// timestep so every test run steps exactly the same way
+
+use crate::game_core::attack::{self, DamageEvent, EnemyDeathEvent, EnemyHitEvent};
+use crate::game_core::audio::{self, SoundEvent};
+use crate::game_core::camera::CameraShake;
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::data::resolve_path;
+use crate::game_core::dialogue::{self, Conversation, Dialogues};
+use crate::game_core::entities::attacks::AttackDefinitions;
+use crate::game_core::entities::chest::{self, ChestBundle, ChestOpenedEvent};
+use crate::game_core::entities::door::{self, DoorBundle, KeyBundle};
+use crate::game_core::entities::enemy;
+use crate::game_core::entities::enemy_archetypes::EnemyArchetypes;
+use crate::game_core::entities::entity_components::{
+    ColliderBundle, Enemy, EnemyStats, Inventory, Player, PlayerStatsBundle,
+};
+use crate::game_core::entities::interaction::{self, InteractionTarget};
+use crate::game_core::entities::levelling::{self, LevelUpEvent, LevellingConfig, XpRewardEvent};
+use crate::game_core::entities::npc;
+use crate::game_core::entities::player::{self, InputLocks};
+use crate::game_core::entities::spawner;
+use crate::game_core::entities::stamina::{self, StaminaConfig};
+use crate::game_core::entities::trigger;
+use crate::game_core::lighting::{
+    self, DayNightClock, LightingConfig, LightingMaterial, PointLight2d,
+};
+use crate::game_core::particles;
+use crate::game_core::projectile;
+use crate::game_core::quest::{self, QuestCompletedEvent, QuestDefinitions, QuestEvent};
+use crate::game_core::save::SaveData;
+use crate::game_core::settings::Settings;
+use crate::game_core::tile_animation::{self, TileAnimationManifest};
+use crate::game_core::ui::minimap::{self, MapExploration};
+use crate::game_core::visibility::{self, FieldOfView};
+use crate::game_core::world::{
+    self, BreakableTile, IntGridColliders, LevelTileGrid, SpeedModifier, TileBrokenEvent,
+    TileEditEvent,
+};
+use crate::game_core::y_sort::{self, YSortConfig};
+use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use bevy::time::TimePlugin;
+use bevy::transform::TransformSystem;
+use bevy_ecs_ldtk::ldtk::LdtkJson;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_ldtk::utils::{
+    calculate_transform_from_entity_instance, create_entity_definition_map,
+};
+use bevy_rapier2d::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
+
+pub static TEST_TIMESTEP_SECS: f32 = 1.0 / 60.0;
+
+pub static SANDBOX_LDTK_PATH: &str = "assets/ldtk/sandbox.ldtk";
+
+pub struct TestApp {
+    pub app: App,
+    now: Instant,
+}
+
+impl TestApp {
+    pub fn new() -> TestApp {
+        let mut app = App::new();
+
+        // Time is driven by `step` instead of the real clock
+        app.add_plugins(MinimalPlugins.build().disable::<TimePlugin>())
+            .insert_resource(Time::default())
+            .add_plugin(AssetPlugin::default())
+            .add_plugin(ScenePlugin)
+            .add_plugin(bevy::transform::TransformPlugin)
+            .add_plugin(bevy::hierarchy::HierarchyPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<ColorMaterial>()
+            .add_asset::<Image>()
+            .add_asset::<TextureAtlas>()
+            .add_asset::<LightingMaterial>()
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+            .insert_resource(RapierConfiguration {
+                gravity: Vec2::ZERO,
+                timestep_mode: TimestepMode::Fixed {
+                    dt: TEST_TIMESTEP_SECS,
+                    substeps: 1,
+                },
+                ..default()
+            })
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<MouseButton>>()
+            .init_resource::<InputLocks>()
+            .insert_resource(Settings::default())
+            .insert_resource(SaveData::default())
+            .insert_resource(IntGridColliders::default())
+            .insert_resource(EnemyArchetypes::load())
+            .insert_resource(AttackDefinitions::load())
+            .insert_resource(StaminaConfig::load())
+            .insert_resource(LevellingConfig::load())
+            .insert_resource(QuestDefinitions::load())
+            .init_resource::<Conversation>()
+            .init_resource::<CameraShake>()
+            .insert_resource(Dialogues::load())
+            .init_resource::<InteractionTarget>()
+            .init_resource::<FieldOfView>()
+            .init_resource::<MapExploration>()
+            .insert_resource(LightingConfig::load())
+            .init_resource::<DayNightClock>()
+            .insert_resource(YSortConfig::load())
+            .insert_resource(TileAnimationManifest::load())
+            .add_event::<DamageEvent>()
+            .add_event::<EnemyHitEvent>()
+            .add_event::<EnemyDeathEvent>()
+            .add_event::<LevelUpEvent>()
+            .add_event::<XpRewardEvent>()
+            .add_event::<ChestOpenedEvent>()
+            .add_event::<QuestEvent>()
+            .add_event::<QuestCompletedEvent>()
+            .add_event::<SoundEvent>()
+            .add_event::<TileEditEvent>()
+            .add_event::<TileBrokenEvent>()
+            .add_system(player::player_movement_system)
+            .add_system(player::attack_handler_system)
+            .add_system(player::ranged_attack_system)
+            .add_system(stamina::apply_equipment_load_system)
+            .add_system(stamina::stamina_regen_system)
+            .add_system(stamina::dash_system)
+            .add_system(levelling::restore_level_system)
+            .add_system(levelling::experience_system)
+            .add_system(projectile::projectile_lifetime_system)
+            .add_system(enemy::on_enemy_spawned)
+            .add_system(enemy::enemy_ai_system)
+            .add_system(spawner::spawner_activation_system.before(spawner::spawner_system))
+            .add_system(spawner::spawner_system)
+            .add_system(spawner::barrier_system.after(spawner::spawner_system))
+            .add_system(door::on_door_spawned)
+            .add_system(door::on_key_spawned)
+            .add_system(door::restore_inventory_system)
+            .add_system(door::key_pickup_system)
+            .add_system(interaction::interaction_target_system)
+            .add_system(door::door_interaction_system.after(interaction::interaction_target_system))
+            .add_system(dialogue::dialogue_input_system)
+            .add_system(
+                npc::npc_interaction_system
+                    .after(dialogue::dialogue_input_system)
+                    .after(interaction::interaction_target_system),
+            )
+            .add_system(
+                chest::chest_interaction_system.after(interaction::interaction_target_system),
+            )
+            .add_system(trigger::on_trigger_spawned)
+            .add_system(trigger::trigger_system)
+            .add_system(quest::start_quests_system)
+            .add_system(quest::gameplay_quest_events_system)
+            .add_system(
+                quest::quest_progress_system
+                    .after(quest::start_quests_system)
+                    .after(quest::gameplay_quest_events_system),
+            )
+            .add_system(visibility::field_of_view_system)
+            .add_system(
+                visibility::hide_unseen_enemies_system.after(visibility::field_of_view_system),
+            )
+            .add_system(minimap::explore_map_system.after(visibility::field_of_view_system))
+            .add_startup_system(lighting::setup_lighting)
+            .add_system(lighting::lighting_fallback_system)
+            .add_system(lighting::day_night_system)
+            .add_system(lighting::attach_player_light_system)
+            .add_system(lighting::lighting_overlay_system)
+            .add_system(
+                lighting::lighting_system
+                    .after(lighting::day_night_system)
+                    .after(lighting::lighting_overlay_system),
+            )
+            .add_system(audio::combat_sounds_system)
+            .add_system(audio::footstep_sound_system)
+            .add_system(audio::level_up_sound_system)
+            .add_system(audio::quest_complete_sound_system)
+            .add_startup_system(particles::setup_particle_assets)
+            .add_system(particles::attach_footstep_dust_system)
+            .add_system(particles::footstep_dust_system)
+            .add_system(particles::emit_particles_system)
+            .add_system(particles::update_particles_system)
+            .add_system(particles::combat_particles_system)
+            .add_system(particles::tile_debris_particles_system)
+            .add_system(y_sort::attach_y_sort_system)
+            .add_system(world::break_tiles_system.before(world::edit_int_grid_system))
+            .add_system(world::edit_int_grid_system)
+            .add_startup_system(tile_animation::setup_tile_animations)
+            .add_system(
+                tile_animation::sync_animated_tiles_system.after(world::edit_int_grid_system),
+            )
+            .add_system(world::tile_zone_system.after(player::player_movement_system))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                y_sort::y_sort_system.before(TransformSystem::TransformPropagate),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, player::Attack_Collider_Handler)
+            .add_system_to_stage(CoreStage::PostUpdate, projectile::projectile_hit_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                attack::apply_damage_system
+                    .after(player::Attack_Collider_Handler)
+                    .after(projectile::projectile_hit_system),
+            );
+
+        let now = Instant::now();
+        app.world.resource_mut::<Time>().update_with_instant(now);
+
+        TestApp { app, now }
+    }
+
+    // A TestApp with the first sandbox level loaded, what most tests start from
+    pub fn sandbox() -> TestApp {
+        let mut test_app = TestApp::new();
+        test_app.load_sandbox();
+        test_app
+    }
+
+    // For tests that need to change resources before the level is loaded
+    pub fn load_sandbox(&mut self) {
+        self.load_ldtk_level(SANDBOX_LDTK_PATH, 0);
+        self.step(1);
+    }
+
+    // Spawns the Player, Enemy, Light, Door, Key and Chest entities of one
+    // level of an LDtk file, the same way the LdtkPlugin would but without
+    // sprites for the first three
+    pub fn load_ldtk_level(&mut self, path: &str, level_index: usize) {
+        let contents =
+            fs::read_to_string(resolve_path(path)).expect("LDtk file should be readable");
+        let ldtk: LdtkJson = serde_json::from_str(&contents).expect("LDtk file should parse");
+        let entity_definitions = create_entity_definition_map(&ldtk.defs.entities);
+
+        let level = &ldtk.levels[level_index];
+        for layer in level.layer_instances.iter().flatten() {
+            for entity_instance in layer.entity_instances.iter() {
+                let transform = calculate_transform_from_entity_instance(
+                    entity_instance,
+                    &entity_definitions,
+                    level.px_hei,
+                    0.0,
+                );
+                match entity_instance.identifier.as_ref() {
+                    "Door" => {
+                        self.spawn_ldtk_bundle::<DoorBundle>(entity_instance, layer, transform)
+                    }
+                    "Key" => self.spawn_ldtk_bundle::<KeyBundle>(entity_instance, layer, transform),
+                    "Chest" => {
+                        self.spawn_ldtk_bundle::<ChestBundle>(entity_instance, layer, transform)
+                    }
+                    _ => self.spawn_entity_instance(entity_instance.clone(), transform),
+                }
+            }
+        }
+    }
+
+    // Builds the bundle registered for the entity in main.rs, the way the
+    // LdtkPlugin does, and puts it at `transform`
+    fn spawn_ldtk_bundle<B: LdtkEntity + Bundle>(
+        &mut self,
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        transform: Transform,
+    ) {
+        let world = &mut self.app.world;
+        let bundle = world.resource_scope(|world, asset_server: Mut<AssetServer>| {
+            let mut texture_atlases = world.resource_mut::<Assets<TextureAtlas>>();
+            B::bundle_entity(
+                entity_instance,
+                layer_instance,
+                None,
+                None,
+                &asset_server,
+                &mut texture_atlases,
+            )
+        });
+        world.spawn(bundle).insert(transform);
+    }
+
+    pub fn spawn_entity_instance(&mut self, entity_instance: EntityInstance, transform: Transform) {
+        let world = &mut self.app.world;
+        match entity_instance.identifier.as_ref() {
+            "Player" => {
+                world.spawn((
+                    PlayerStatsBundle::from(entity_instance.clone()),
+                    ColliderBundle::from(entity_instance.clone()),
+                    Player,
+                    SpeedModifier::default(),
+                    Inventory::default(),
+                    TransformBundle::from(transform),
+                    entity_instance,
+                ));
+            }
+            "Enemy" => {
+                // on_enemy_spawned adds the collider from the archetype
+                world.spawn((
+                    Enemy::from(entity_instance),
+                    EnemyStats::default(),
+                    TransformBundle::from(transform),
+                ));
+            }
+            "Light" => {
+                world.spawn((
+                    PointLight2d::from(entity_instance),
+                    TransformBundle::from(transform),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn spawn_enemy(&mut self, archetype: &str, position: Vec2) -> Entity {
+        self.app
+            .world
+            .spawn((
+                Enemy {
+                    name: archetype.to_string(),
+                    archetype: archetype.to_string(),
+                    ..default()
+                },
+                EnemyStats::default(),
+                TransformBundle::from(Transform::from_translation(position.extend(0.0))),
+            ))
+            .id()
+    }
+
+    // Spawns a level holding a single breakable tile at `coords`, with the
+    // tile's sensor at `position` instead of going through a real LDtk level
+    pub fn spawn_breakable_tile(
+        &mut self,
+        coords: GridCoords,
+        position: Vec2,
+        health: f32,
+    ) -> (Entity, Entity) {
+        let world = &mut self.app.world;
+        let tile = world
+            .spawn((
+                BreakableTile {
+                    coords,
+                    value: 1,
+                    health,
+                },
+                Collider::cuboid(8.0, 8.0),
+                Sensor,
+                Layer::Breakable.collision_groups(),
+                TransformBundle::from(Transform::from_translation(position.extend(0.0))),
+            ))
+            .id();
+        let level = world
+            .spawn((
+                LevelTileGrid {
+                    level_iid: "test-level".to_string(),
+                    layer: "Fences".to_string(),
+                    width: 24,
+                    height: 27,
+                    grid_size: 16,
+                    cells: HashMap::from([(coords, 1)]),
+                },
+                TransformBundle::default(),
+            ))
+            .push_children(&[tile])
+            .id();
+        (level, tile)
+    }
+
+    pub fn press_key(&mut self, key: KeyCode) {
+        self.app.world.resource_mut::<Input<KeyCode>>().press(key);
+    }
+
+    pub fn release_key(&mut self, key: KeyCode) {
+        self.app.world.resource_mut::<Input<KeyCode>>().release(key);
+    }
+
+    // Pressed for one frame, released on the next
+    pub fn click_mouse(&mut self, button: MouseButton) {
+        self.app
+            .world
+            .resource_mut::<Input<MouseButton>>()
+            .press(button);
+        self.step(1);
+        self.app
+            .world
+            .resource_mut::<Input<MouseButton>>()
+            .release(button);
+    }
+
+    pub fn step(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.now += Duration::from_secs_f32(TEST_TIMESTEP_SECS);
+            self.app
+                .world
+                .resource_mut::<Time>()
+                .update_with_instant(self.now);
+
+            self.app.update();
+
+            // just_pressed and just_released only last for a single frame
+            self.app.world.resource_mut::<Input<KeyCode>>().clear();
+            self.app.world.resource_mut::<Input<MouseButton>>().clear();
+        }
+    }
+
+    pub fn player(&mut self) -> Entity {
+        self.app
+            .world
+            .query_filtered::<Entity, With<Player>>()
+            .single(&self.app.world)
+    }
+
+    pub fn enemy_named(&mut self, name: &str) -> Option<Entity> {
+        self.app
+            .world
+            .query::<(Entity, &Enemy)>()
+            .iter(&self.app.world)
+            .find(|(_, enemy)| enemy.name == name)
+            .map(|(entity, _)| entity)
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.app.world.get::<T>(entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<Mut<T>> {
+        self.app.world.get_mut::<T>(entity)
+    }
+
+    pub fn exists(&self, entity: Entity) -> bool {
+        self.app.world.get_entity(entity).is_some()
+    }
+}
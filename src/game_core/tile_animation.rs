@@ -0,0 +1,283 @@
+// Animated tiles, like water, that bevy_ecs_ldtk would otherwise render as static
+// The IntGrid values listed in the manifest get a sprite per cell that cycles
+// through the frames of its animation. Every tile of an animation shares the
+// same clock, so they all stay on the same frame
+
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::world::{LevelTileGrid, TileEditEvent};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+pub static TILE_ANIMATIONS_PATH: &str = "assets/data/tile_animations.ron";
+
+// Between the ground layers and the fences, so water covers the ground but
+// not what stands in it
+pub static ANIMATED_TILE_Z: f32 = 1.5;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TileAnimation {
+    // One image per frame (relative to assets/), all laid out as the same tile sheet
+    pub frames: Vec<String>,
+    pub frame_secs: f32,
+    pub columns: usize,
+    pub rows: usize,
+    pub tile_size: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimatedIntGridValue {
+    pub animation: String,
+    // Which tile of the sheet to show. Every cell of the value shows the same
+    // one, so shores and edges have to come from the tiles drawn around them
+    pub tile_index: usize,
+}
+
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TileAnimationManifest {
+    pub animations: HashMap<String, TileAnimation>,
+    pub int_grid: HashMap<i32, AnimatedIntGridValue>,
+}
+
+impl TileAnimationManifest {
+    pub fn load() -> TileAnimationManifest {
+        load_ron_or_default(TILE_ANIMATIONS_PATH)
+    }
+}
+
+// One atlas per frame of every animation
+#[derive(Resource, Default)]
+pub struct TileAnimationAtlases(pub HashMap<String, Vec<Handle<TextureAtlas>>>);
+
+#[derive(Resource, Default)]
+pub struct TileAnimationClock {
+    pub elapsed: f32,
+}
+
+#[derive(Clone, Debug, Component)]
+pub struct AnimatedTile {
+    pub animation: String,
+    pub coords: GridCoords,
+}
+
+// Frame to show after `elapsed` seconds, looping
+pub fn frame_at(elapsed: f32, frame_secs: f32, frame_count: usize) -> usize {
+    if frame_count == 0 || frame_secs <= 0.0 {
+        return 0;
+    }
+    (elapsed / frame_secs) as usize % frame_count
+}
+
+pub fn setup_tile_animations(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    manifest: Res<TileAnimationManifest>,
+) {
+    let mut atlases = TileAnimationAtlases::default();
+
+    for (name, animation) in manifest.animations.iter() {
+        let frames = animation
+            .frames
+            .iter()
+            .map(|path| {
+                texture_atlases.add(TextureAtlas::from_grid(
+                    asset_server.load(path.as_str()),
+                    Vec2::splat(animation.tile_size),
+                    animation.columns,
+                    animation.rows,
+                    None,
+                    None,
+                ))
+            })
+            .collect();
+        atlases.0.insert(name.clone(), frames);
+    }
+
+    commands.insert_resource(atlases);
+}
+
+fn spawn_animated_tile(
+    commands: &mut Commands,
+    level_entity: Entity,
+    grid: &LevelTileGrid,
+    coords: GridCoords,
+    manifest: &TileAnimationManifest,
+    atlases: &TileAnimationAtlases,
+) {
+    let Some(animated) = manifest.int_grid.get(&grid.value(coords)) else {
+        return;
+    };
+    let Some(first_frame) = atlases
+        .0
+        .get(&animated.animation)
+        .and_then(|frames| frames.first())
+    else {
+        return;
+    };
+
+    let GridCoords { x, y } = coords;
+    let half_tile = grid.grid_size as f32 / 2.;
+    let tile = commands
+        .spawn((
+            SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(animated.tile_index),
+                texture_atlas: first_frame.clone(),
+                transform: Transform::from_xyz(
+                    (x * grid.grid_size) as f32 + half_tile,
+                    (y * grid.grid_size) as f32 + half_tile,
+                    ANIMATED_TILE_Z,
+                ),
+                ..default()
+            },
+            AnimatedTile {
+                animation: animated.animation.clone(),
+                coords,
+            },
+        ))
+        .id();
+    commands.entity(level_entity).add_child(tile);
+}
+
+// Spawns the animated tiles of a level once its grid is known. Edited cells
+// only get their own tile added or removed, so the rest keep animating
+pub fn sync_animated_tiles_system(
+    mut commands: Commands,
+    mut edit_events: EventReader<TileEditEvent>,
+    changed_grids: Query<(Entity, &LevelTileGrid), Changed<LevelTileGrid>>,
+    animated_tiles: Query<(Entity, &AnimatedTile, &Parent)>,
+    manifest: Res<TileAnimationManifest>,
+    atlases: Res<TileAnimationAtlases>,
+) {
+    let mut edited: HashMap<Entity, HashSet<GridCoords>> = HashMap::new();
+    for edit in edit_events.iter() {
+        edited.entry(edit.level).or_default().insert(edit.coords);
+    }
+
+    for (level_entity, grid) in changed_grids.iter() {
+        // A grid changed without edits is a new (or respawned) level
+        let cells = edited.get(&level_entity);
+        for (tile_entity, tile, parent) in animated_tiles.iter() {
+            let stale = cells.map_or(true, |cells| cells.contains(&tile.coords));
+            if parent.get() == level_entity && stale {
+                commands.entity(tile_entity).despawn_recursive();
+            }
+        }
+
+        let coords: Vec<GridCoords> = match cells {
+            Some(cells) => cells.iter().copied().collect(),
+            None => grid.cells.keys().copied().collect(),
+        };
+        for coords in coords {
+            spawn_animated_tile(
+                &mut commands,
+                level_entity,
+                grid,
+                coords,
+                &manifest,
+                &atlases,
+            );
+        }
+    }
+}
+
+pub fn animate_tiles_system(
+    time: Res<Time>,
+    mut clock: ResMut<TileAnimationClock>,
+    manifest: Res<TileAnimationManifest>,
+    atlases: Res<TileAnimationAtlases>,
+    mut tiles: Query<(&AnimatedTile, &mut Handle<TextureAtlas>)>,
+) {
+    clock.elapsed += time.delta_seconds();
+
+    for (tile, mut atlas) in tiles.iter_mut() {
+        let (Some(animation), Some(frames)) = (
+            manifest.animations.get(&tile.animation),
+            atlases.0.get(&tile.animation),
+        ) else {
+            continue;
+        };
+
+        let frame = &frames[frame_at(clock.elapsed, animation.frame_secs, frames.len())];
+        if *atlas != *frame {
+            *atlas = frame.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::test_harness::TestApp;
+
+    fn animated_tiles(test_app: &mut TestApp) -> HashMap<GridCoords, Entity> {
+        let world = &mut test_app.app.world;
+        world
+            .query::<(Entity, &AnimatedTile)>()
+            .iter(world)
+            .map(|(entity, tile)| (tile.coords, entity))
+            .collect()
+    }
+
+    #[test]
+    fn frames_loop() {
+        assert_eq!(frame_at(0.0, 0.2, 6), 0);
+        assert_eq!(frame_at(0.25, 0.2, 6), 1);
+        assert_eq!(frame_at(1.15, 0.2, 6), 5);
+        assert_eq!(frame_at(1.21, 0.2, 6), 0);
+    }
+
+    #[test]
+    fn empty_animations_stay_on_the_first_frame() {
+        assert_eq!(frame_at(3.0, 0.2, 0), 0);
+        assert_eq!(frame_at(3.0, 0.0, 6), 0);
+    }
+
+    #[test]
+    fn edits_only_touch_the_edited_water() {
+        let mut test_app = TestApp::new();
+        let pond = [GridCoords { x: 2, y: 2 }, GridCoords { x: 3, y: 2 }];
+        let level = test_app
+            .app
+            .world
+            .spawn((
+                LevelTileGrid {
+                    level_iid: "test-level".to_string(),
+                    layer: "Fences".to_string(),
+                    width: 8,
+                    height: 8,
+                    grid_size: 16,
+                    cells: pond.iter().map(|&coords| (coords, 3)).collect(),
+                },
+                TransformBundle::default(),
+            ))
+            .id();
+        test_app.step(1);
+        let before = animated_tiles(&mut test_app);
+        assert_eq!(before.len(), 2);
+
+        let mut edit = |coords, value| {
+            test_app.app.world.send_event(TileEditEvent {
+                level,
+                coords,
+                value,
+            });
+            test_app.step(1);
+            animated_tiles(&mut test_app)
+        };
+
+        // Placing water adds its tile, and leaves the others animating
+        let added = GridCoords { x: 4, y: 2 };
+        let after = edit(added, 3);
+        assert_eq!(after.len(), 3);
+        assert!(pond.iter().all(|coords| after[coords] == before[coords]));
+
+        let after = edit(pond[0], 0);
+        assert_eq!(after.len(), 2);
+        assert!(!after.contains_key(&pond[0]));
+        assert_eq!(after[&pond[1]], before[&pond[1]]);
+        assert!(after.contains_key(&added));
+    }
+}
@@ -0,0 +1,157 @@
+// The box along the bottom of the screen showing the current conversation, and
+// the prompt telling the player they can talk to someone nearby
+
+use super::{UiFont, PANEL_COLOR};
+use crate::game_core::dialogue::Conversation;
+use crate::game_core::entities::interaction::InteractionTarget;
+use crate::game_core::entities::npc::Npc;
+use crate::game_core::settings::{Action, Settings};
+use bevy::prelude::*;
+
+static SPEAKER_COLOR: Color = Color::rgb(0.95, 0.8, 0.4);
+static BOX_WIDTH: f32 = 600.0;
+
+#[derive(Component)]
+pub struct DialogueBoxRoot;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DialogueBoxText {
+    Speaker,
+    Line,
+    Choices,
+}
+
+#[derive(Component)]
+pub struct TalkPrompt;
+
+pub fn setup_dialogue_box(mut commands: Commands, font: Res<UiFont>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    bottom: Val::Px(24.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            z_index: ZIndex::Global(50),
+            ..default()
+        })
+        .with_children(|root| {
+            root.spawn((TextBundle::from_section("", font.style(16.0)), TalkPrompt));
+
+            root.spawn((
+                NodeBundle {
+                    style: Style {
+                        display: Display::None,
+                        flex_direction: FlexDirection::Column,
+                        size: Size::new(Val::Px(BOX_WIDTH), Val::Auto),
+                        padding: UiRect::all(Val::Px(12.0)),
+                        ..default()
+                    },
+                    background_color: PANEL_COLOR.into(),
+                    ..default()
+                },
+                DialogueBoxRoot,
+            ))
+            .with_children(|panel| {
+                let mut speaker_style = font.style(18.0);
+                speaker_style.color = SPEAKER_COLOR;
+                panel.spawn((
+                    TextBundle::from_section("", speaker_style).with_style(Style {
+                        margin: UiRect::bottom(Val::Px(6.0)),
+                        ..default()
+                    }),
+                    DialogueBoxText::Speaker,
+                ));
+                panel.spawn((
+                    TextBundle::from_section("", font.style(16.0)).with_style(Style {
+                        max_size: Size::new(Val::Px(BOX_WIDTH - 24.0), Val::Undefined),
+                        ..default()
+                    }),
+                    DialogueBoxText::Line,
+                ));
+                panel.spawn((
+                    TextBundle::from_section("", font.style(16.0)).with_style(Style {
+                        margin: UiRect::top(Val::Px(8.0)),
+                        ..default()
+                    }),
+                    DialogueBoxText::Choices,
+                ));
+            });
+        });
+}
+
+pub fn dialogue_box_system(
+    conversation: Res<Conversation>,
+    mut root: Query<&mut Style, With<DialogueBoxRoot>>,
+    mut texts: Query<(&mut Text, &DialogueBoxText)>,
+) {
+    if !conversation.is_changed() {
+        return;
+    }
+
+    if let Ok(mut style) = root.get_single_mut() {
+        let display = if conversation.is_active() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    let Some(active) = conversation.0.as_ref() else {
+        return;
+    };
+
+    for (mut text, part) in texts.iter_mut() {
+        let value = match part {
+            DialogueBoxText::Speaker => active.speaker().to_string(),
+            DialogueBoxText::Line => active.visible_text(),
+            // Choices wait for the typewriter so they aren't picked before being read
+            DialogueBoxText::Choices if active.fully_shown() => (0..active.choices.len())
+                .filter_map(|index| active.choice(index))
+                .enumerate()
+                .map(|(index, choice)| format!("{}. {}", index + 1, choice.text))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            DialogueBoxText::Choices => String::new(),
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+pub fn talk_prompt_system(
+    settings: Res<Settings>,
+    target: Res<InteractionTarget>,
+    conversation: Res<Conversation>,
+    npcs: Query<&Npc>,
+    mut prompts: Query<&mut Text, With<TalkPrompt>>,
+) {
+    let value = match target.0.and_then(|entity| npcs.get(entity).ok()) {
+        Some(npc) if !conversation.is_active() => {
+            let key = settings
+                .key_bindings
+                .keys(Action::Interact)
+                .first()
+                .map(|key| format!("{:?}", key))
+                .unwrap_or_default();
+            format!("[{}] Talk to {}", key, npc.name)
+        }
+        _ => String::new(),
+    };
+
+    for mut text in prompts.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// Bars in the top left corner showing the player's health, stamina and
+// experience, plus a message whenever the player levels up
+
+use super::{UiFont, PANEL_COLOR};
+use crate::game_core::entities::entity_components::{
+    Experience, Health, MaxHealth, Player, Stamina,
+};
+use crate::game_core::entities::levelling::{LevelUpEvent, LevellingConfig};
+use bevy::prelude::*;
+
+static BAR_WIDTH: f32 = 160.0;
+static BAR_HEIGHT: f32 = 10.0;
+static HEALTH_COLOR: Color = Color::rgb(0.8, 0.2, 0.2);
+static STAMINA_COLOR: Color = Color::rgb(0.3, 0.75, 0.3);
+// Shown while the stamina bar is nearly empty
+static STAMINA_LOW_COLOR: Color = Color::rgb(0.5, 0.5, 0.3);
+static STAMINA_LOW_FRACTION: f32 = 0.1;
+static XP_COLOR: Color = Color::rgb(0.35, 0.55, 0.9);
+static LEVEL_UP_SECS: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HudStat {
+    Health,
+    Stamina,
+    Experience,
+}
+
+// The filled part of a bar
+#[derive(Component)]
+pub struct HudBar(pub HudStat);
+
+#[derive(Component)]
+pub struct LevelText;
+
+// Seconds left showing the level up message
+#[derive(Component, Default)]
+pub struct LevelUpText(pub f32);
+
+pub fn setup_hud(mut commands: Commands, font: Res<UiFont>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(12.0),
+                    top: Val::Px(12.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|root| {
+            spawn_bar(root, &font, "HP", HudStat::Health, HEALTH_COLOR);
+            spawn_bar(root, &font, "ST", HudStat::Stamina, STAMINA_COLOR);
+            spawn_bar(root, &font, "XP", HudStat::Experience, XP_COLOR);
+            root.spawn((TextBundle::from_section("", font.style(14.0)), LevelText));
+            root.spawn((
+                TextBundle::from_section("", font.style(20.0)).with_style(Style {
+                    margin: UiRect::top(Val::Px(8.0)),
+                    ..default()
+                }),
+                LevelUpText::default(),
+            ));
+        });
+}
+
+fn spawn_bar(root: &mut ChildBuilder, font: &UiFont, label: &str, stat: HudStat, color: Color) {
+    root.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            margin: UiRect::bottom(Val::Px(4.0)),
+            ..default()
+        },
+        ..default()
+    })
+    .with_children(|row| {
+        row.spawn(
+            TextBundle::from_section(label, font.style(14.0)).with_style(Style {
+                size: Size::new(Val::Px(28.0), Val::Auto),
+                ..default()
+            }),
+        );
+        row.spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(BAR_WIDTH), Val::Px(BAR_HEIGHT)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        })
+        .with_children(|background| {
+            background.spawn((
+                NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    ..default()
+                },
+                HudBar(stat),
+            ));
+        });
+    });
+}
+
+pub fn hud_system(
+    config: Res<LevellingConfig>,
+    players: Query<(&Health, &MaxHealth, &Stamina, &Experience), With<Player>>,
+    mut bars: Query<(&HudBar, &mut Style, &mut BackgroundColor)>,
+    mut level_texts: Query<&mut Text, With<LevelText>>,
+) {
+    let Ok((health, max_health, stamina, experience)) = players.get_single() else {
+        return;
+    };
+
+    for (bar, mut style, mut color) in bars.iter_mut() {
+        let fraction = match bar.0 {
+            HudStat::Health => health.0 / max_health.0,
+            HudStat::Stamina => {
+                let fraction = stamina.current / stamina.max;
+                *color = if fraction < STAMINA_LOW_FRACTION {
+                    STAMINA_LOW_COLOR.into()
+                } else {
+                    STAMINA_COLOR.into()
+                };
+                fraction
+            }
+            // Stays full at the level cap
+            HudStat::Experience => match config.xp_to_next(experience.level) {
+                Some(needed) => experience.xp as f32 / needed as f32,
+                None => 1.0,
+            },
+        };
+        style.size.width = Val::Percent(fraction.clamp(0.0, 1.0) * 100.0);
+    }
+
+    let level = format!("Level {}", experience.level);
+    for mut text in level_texts.iter_mut() {
+        // Only touch the text when it changes, so it isn't laid out again every frame
+        if text.sections[0].value != level {
+            text.sections[0].value = level.clone();
+        }
+    }
+}
+
+pub fn level_up_text_system(
+    time: Res<Time>,
+    mut level_up_events: EventReader<LevelUpEvent>,
+    mut texts: Query<(&mut Text, &mut LevelUpText)>,
+) {
+    let level = level_up_events.iter().map(|event| event.level).max();
+
+    for (mut text, mut shown) in texts.iter_mut() {
+        if let Some(level) = level {
+            text.sections[0].value = format!("Level up! Now level {}", level);
+            shown.0 = LEVEL_UP_SECS;
+        } else if shown.0 > 0.0 {
+            shown.0 -= time.delta_seconds();
+            if shown.0 <= 0.0 {
+                text.sections[0].value.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,466 @@
+// Minimap of the level the player is in, drawn from its IntGrid with one pixel
+// per cell. Cells only show up once the player has seen them, and the map key
+// switches between the corner of the screen and a full screen view
+
+use super::PANEL_COLOR;
+use crate::game_core::entities::chest::Chest;
+use crate::game_core::entities::entity_components::{Enemy, Player};
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::entities::trigger::Trigger;
+use crate::game_core::settings::{Action, Settings};
+use crate::game_core::visibility::FieldOfView;
+use crate::game_core::world::{IntGridColliders, LevelTileGrid, TileBehaviour};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use bevy_ecs_ldtk::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+// Longest side of the map, in pixels
+static MINIMAP_SIZE: f32 = 160.0;
+static FULL_MAP_SIZE: f32 = 560.0;
+
+static FLOOR_COLOR: [u8; 4] = [60, 70, 55, 230];
+static WALL_COLOR: [u8; 4] = [205, 200, 185, 255];
+static WATER_COLOR: [u8; 4] = [60, 100, 180, 255];
+// Slow, hazard and ledge tiles
+static ZONE_COLOR: [u8; 4] = [140, 110, 60, 240];
+static UNEXPLORED_COLOR: [u8; 4] = [0, 0, 0, 0];
+static FULL_MAP_BACKDROP: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapIcon {
+    Player,
+    Enemy,
+    Chest,
+    // Triggers that change level
+    Exit,
+}
+
+impl MapIcon {
+    fn color(&self) -> Color {
+        match self {
+            MapIcon::Player => Color::rgb(0.3, 0.9, 1.0),
+            MapIcon::Enemy => Color::rgb(0.9, 0.25, 0.2),
+            MapIcon::Chest => Color::rgb(1.0, 0.85, 0.2),
+            MapIcon::Exit => Color::rgb(0.75, 0.4, 0.95),
+        }
+    }
+
+    fn size(&self) -> f32 {
+        match self {
+            MapIcon::Player => 6.0,
+            _ => 4.0,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct MapExploration {
+    // Cells the player has seen, by level iid
+    pub revealed: HashMap<String, HashSet<GridCoords>>,
+    // The level entity the player is in
+    pub current: Option<Entity>,
+}
+
+#[derive(Resource, Default)]
+pub struct MapView {
+    pub full: bool,
+}
+
+#[derive(Component)]
+pub struct MinimapRoot;
+
+#[derive(Component)]
+pub struct MinimapImage;
+
+// Icons are kept around and reused, the ones not needed are hidden
+#[derive(Component)]
+pub struct MinimapIconNode;
+
+// Cells within `radius` of `center` that are inside a width x height grid
+pub fn cells_around(center: GridCoords, radius: i32, width: i32, height: i32) -> Vec<GridCoords> {
+    let mut cells = Vec::new();
+    for y in (center.y - radius).max(0)..=(center.y + radius).min(height - 1) {
+        for x in (center.x - radius).max(0)..=(center.x + radius).min(width - 1) {
+            let (dx, dy) = (x - center.x, y - center.y);
+            if dx * dx + dy * dy <= radius * radius {
+                cells.push(GridCoords { x, y });
+            }
+        }
+    }
+    cells
+}
+
+fn cell_color(value: i32, colliders: &IntGridColliders) -> [u8; 4] {
+    match colliders.values.get(&value).map(|tile| tile.behaviour) {
+        Some(TileBehaviour::Solid) => WALL_COLOR,
+        Some(TileBehaviour::Water) => WATER_COLOR,
+        Some(_) => ZONE_COLOR,
+        None => FLOOR_COLOR,
+    }
+}
+
+// RGBA pixels of the map, starting from the top row like images do
+pub fn render_map(
+    grid: &LevelTileGrid,
+    revealed: &HashSet<GridCoords>,
+    colliders: &IntGridColliders,
+) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((grid.width * grid.height * 4) as usize);
+    for row in 0..grid.height {
+        for x in 0..grid.width {
+            let coords = GridCoords {
+                x,
+                y: grid.height - 1 - row,
+            };
+            let color = if revealed.contains(&coords) {
+                cell_color(grid.value(coords), colliders)
+            } else {
+                UNEXPLORED_COLOR
+            };
+            pixels.extend_from_slice(&color);
+        }
+    }
+    pixels
+}
+
+// Where `position` is on the level, from (0, 0) at the bottom left to (1, 1)
+// at the top right
+fn level_fraction(grid: &LevelTileGrid, origin: Vec2, position: Vec2) -> Vec2 {
+    let size = Vec2::new(grid.width as f32, grid.height as f32) * grid.grid_size as f32;
+    (position - origin) / size
+}
+
+fn inside(fraction: Vec2) -> bool {
+    (0.0..1.0).contains(&fraction.x) && (0.0..1.0).contains(&fraction.y)
+}
+
+fn fraction_to_cell(grid: &LevelTileGrid, fraction: Vec2) -> GridCoords {
+    GridCoords {
+        x: (fraction.x * grid.width as f32) as i32,
+        y: (fraction.y * grid.height as f32) as i32,
+    }
+}
+
+pub fn setup_minimap(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(4.0)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                z_index: ZIndex::Global(40),
+                ..default()
+            },
+            MinimapRoot,
+        ))
+        .with_children(|root| {
+            root.spawn((ImageBundle::default(), MinimapImage));
+        });
+}
+
+// Reveals the cells in the player's field of view, so walls keep what is behind
+// them hidden until the player gets around them
+pub fn explore_map_system(
+    view: Res<FieldOfView>,
+    levels: Query<&LevelTileGrid>,
+    mut exploration: ResMut<MapExploration>,
+) {
+    if !view.is_changed() {
+        return;
+    }
+    let Some(level) = view.level else {
+        return;
+    };
+    let Ok(grid) = levels.get(level) else {
+        return;
+    };
+
+    if exploration.current != Some(level) {
+        exploration.current = Some(level);
+    }
+
+    // Only touches the resource when something new shows up, the map is redrawn on changes
+    let unrevealed = match exploration.revealed.get(&grid.level_iid) {
+        Some(revealed) => view.visible.iter().any(|cell| !revealed.contains(cell)),
+        None => true,
+    };
+    if unrevealed {
+        exploration
+            .revealed
+            .entry(grid.level_iid.clone())
+            .or_default()
+            .extend(view.visible.iter().copied());
+    }
+}
+
+// The map image is made once and redrawn in place, a new one is only needed
+// when the player moves to a level of another size
+pub fn minimap_image_system(
+    exploration: Res<MapExploration>,
+    levels: Query<&LevelTileGrid>,
+    changed_levels: Query<(), Changed<LevelTileGrid>>,
+    colliders: Res<IntGridColliders>,
+    mut images: ResMut<Assets<Image>>,
+    mut minimap: Query<&mut UiImage, With<MinimapImage>>,
+    mut map_image: Local<Option<Handle<Image>>>,
+) {
+    if !exploration.is_changed() && changed_levels.is_empty() {
+        return;
+    }
+    let Some(grid) = exploration.current.and_then(|level| levels.get(level).ok()) else {
+        return;
+    };
+    let empty = HashSet::new();
+    let revealed = exploration.revealed.get(&grid.level_iid).unwrap_or(&empty);
+
+    let size = Extent3d {
+        width: grid.width as u32,
+        height: grid.height as u32,
+        depth_or_array_layers: 1,
+    };
+    let pixels = render_map(grid, revealed, &colliders);
+
+    let existing = map_image
+        .as_ref()
+        .and_then(|handle| images.get_mut(handle))
+        .filter(|image| image.texture_descriptor.size == size);
+    if let Some(image) = existing {
+        image.data = pixels;
+        return;
+    }
+
+    let mut image = Image::new(
+        size,
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // Keeps the cells sharp when scaled up
+    image.sampler_descriptor = ImageSampler::nearest();
+
+    let handle = images.add(image);
+    for mut ui_image in minimap.iter_mut() {
+        // The old image is freed once nothing holds its handle
+        *ui_image = UiImage(handle.clone());
+    }
+    *map_image = Some(handle);
+}
+
+pub fn toggle_map_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
+    mut view: ResMut<MapView>,
+) {
+    if !input_locks.is_locked()
+        && settings
+            .key_bindings
+            .just_pressed(Action::Map, &keyboard_input)
+    {
+        view.full = !view.full;
+    }
+}
+
+// Puts the map in the corner or in the middle of the screen, sized to the level
+pub fn minimap_layout_system(
+    view: Res<MapView>,
+    exploration: Res<MapExploration>,
+    levels: Query<&LevelTileGrid>,
+    mut roots: Query<(&mut Style, &mut BackgroundColor), With<MinimapRoot>>,
+    mut images: Query<&mut Style, (With<MinimapImage>, Without<MinimapRoot>)>,
+) {
+    if !view.is_changed() && !exploration.is_changed() {
+        return;
+    }
+
+    let longest_side = if view.full {
+        FULL_MAP_SIZE
+    } else {
+        MINIMAP_SIZE
+    };
+    if let Some(grid) = exploration.current.and_then(|level| levels.get(level).ok()) {
+        let scale = longest_side / grid.width.max(grid.height).max(1) as f32;
+        let size = Size::new(
+            Val::Px(grid.width as f32 * scale),
+            Val::Px(grid.height as f32 * scale),
+        );
+        for mut style in images.iter_mut() {
+            if style.size != size {
+                style.size = size;
+            }
+        }
+    }
+
+    if !view.is_changed() {
+        return;
+    }
+    for (mut style, mut color) in roots.iter_mut() {
+        if view.full {
+            style.position = UiRect::all(Val::Px(0.0));
+            style.size = Size::new(Val::Percent(100.0), Val::Percent(100.0));
+            *color = FULL_MAP_BACKDROP.into();
+        } else {
+            style.position = UiRect {
+                right: Val::Px(12.0),
+                bottom: Val::Px(12.0),
+                ..default()
+            };
+            style.size = Style::default().size;
+            *color = PANEL_COLOR.into();
+        }
+    }
+}
+
+pub fn minimap_icons_system(
+    mut commands: Commands,
+    exploration: Res<MapExploration>,
+    view: Res<FieldOfView>,
+    levels: Query<(&LevelTileGrid, &GlobalTransform)>,
+    players: Query<&GlobalTransform, With<Player>>,
+    enemies: Query<&GlobalTransform, With<Enemy>>,
+    chests: Query<&GlobalTransform, With<Chest>>,
+    triggers: Query<(&GlobalTransform, &Trigger)>,
+    minimap: Query<Entity, With<MinimapImage>>,
+    mut icons: Query<(&mut Style, &mut BackgroundColor), With<MinimapIconNode>>,
+    mut pool: Local<Vec<Entity>>,
+) {
+    let Ok(image) = minimap.get_single() else {
+        return;
+    };
+    let Some((grid, level_transform)) =
+        exploration.current.and_then(|level| levels.get(level).ok())
+    else {
+        return;
+    };
+    let origin = level_transform.translation().truncate();
+    let empty = HashSet::new();
+    let revealed = exploration.revealed.get(&grid.level_iid).unwrap_or(&empty);
+
+    let mut markers: Vec<(MapIcon, Vec2)> = Vec::new();
+    // Only shown when on one of `cells`, or always without any
+    let mut add = |icon: MapIcon, transform: &GlobalTransform, cells: Option<&HashSet<_>>| {
+        let fraction = level_fraction(grid, origin, transform.translation().truncate());
+        let shown = cells.map_or(true, |cells| {
+            cells.contains(&fraction_to_cell(grid, fraction))
+        });
+        if inside(fraction) && shown {
+            markers.push((icon, fraction));
+        }
+    };
+    for transform in chests.iter() {
+        add(MapIcon::Chest, transform, Some(revealed));
+    }
+    for (transform, trigger) in triggers.iter() {
+        if trigger.actions.level.is_some() {
+            add(MapIcon::Exit, transform, Some(revealed));
+        }
+    }
+    // Enemies move, so only the ones in sight
+    for transform in enemies.iter() {
+        add(MapIcon::Enemy, transform, Some(&view.visible));
+    }
+    // Last so it is drawn on top
+    for transform in players.iter() {
+        add(MapIcon::Player, transform, None);
+    }
+
+    // New icons only show up next frame, once their commands have run
+    while pool.len() < markers.len() {
+        let icon = commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        display: Display::None,
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    },
+                    ..default()
+                },
+                MinimapIconNode,
+            ))
+            .id();
+        commands.entity(image).add_child(icon);
+        pool.push(icon);
+    }
+
+    for (index, &entity) in pool.iter().enumerate() {
+        let Ok((mut style, mut color)) = icons.get_mut(entity) else {
+            continue;
+        };
+        let Some(&(icon, fraction)) = markers.get(index) else {
+            if style.display != Display::None {
+                style.display = Display::None;
+            }
+            continue;
+        };
+
+        let half = icon.size() / 2.0;
+        style.display = Display::Flex;
+        style.size = Size::new(Val::Px(icon.size()), Val::Px(icon.size()));
+        style.position = UiRect {
+            left: Val::Percent(fraction.x * 100.0),
+            bottom: Val::Percent(fraction.y * 100.0),
+            ..default()
+        };
+        style.margin = UiRect {
+            left: Val::Px(-half),
+            bottom: Val::Px(-half),
+            ..default()
+        };
+        *color = icon.color().into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: i32, height: i32, cells: HashMap<GridCoords, i32>) -> LevelTileGrid {
+        LevelTileGrid {
+            level_iid: "test-level".to_string(),
+            layer: "Fences".to_string(),
+            width,
+            height,
+            grid_size: 16,
+            cells,
+        }
+    }
+
+    #[test]
+    fn reveals_a_circle_inside_the_level() {
+        let cells = cells_around(GridCoords { x: 0, y: 0 }, 2, 10, 10);
+
+        assert!(cells.iter().all(|cell| cell.x >= 0 && cell.y >= 0));
+        assert!(cells.contains(&GridCoords { x: 2, y: 0 }));
+        assert!(!cells.contains(&GridCoords { x: 2, y: 2 }));
+    }
+
+    #[test]
+    fn unexplored_cells_stay_blank() {
+        let grid = grid(2, 1, HashMap::from([(GridCoords { x: 0, y: 0 }, 1)]));
+        let revealed = HashSet::from([GridCoords { x: 0, y: 0 }]);
+
+        let pixels = render_map(&grid, &revealed, &IntGridColliders::default());
+
+        assert_eq!(pixels[0..4], WALL_COLOR);
+        assert_eq!(pixels[4..8], UNEXPLORED_COLOR);
+    }
+
+    #[test]
+    fn image_starts_at_the_top_row() {
+        let grid = grid(1, 2, HashMap::from([(GridCoords { x: 0, y: 1 }, 1)]));
+        let revealed = HashSet::from([GridCoords { x: 0, y: 0 }, GridCoords { x: 0, y: 1 }]);
+
+        let pixels = render_map(&grid, &revealed, &IntGridColliders::default());
+
+        assert_eq!(pixels[0..4], WALL_COLOR);
+        assert_eq!(pixels[4..8], FLOOR_COLOR);
+    }
+}
@@ -0,0 +1,45 @@
+pub mod dialogue_box;
+pub mod hud;
+pub mod minimap;
+pub mod quest_log;
+pub mod settings_menu;
+
+use bevy::prelude::*;
+
+pub static FONT_PATH: &str = "fonts/DejaVuSans.ttf";
+
+pub static TEXT_COLOR: Color = Color::rgb(0.95, 0.95, 0.9);
+pub static PANEL_COLOR: Color = Color::rgba(0.08, 0.08, 0.1, 0.9);
+pub static BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.25);
+pub static BUTTON_HOVER_COLOR: Color = Color::rgb(0.3, 0.3, 0.38);
+pub static BUTTON_PRESSED_COLOR: Color = Color::rgb(0.4, 0.45, 0.3);
+
+// Shared by every piece of UI so the font is only loaded once
+#[derive(Resource, Default)]
+pub struct UiFont(pub Handle<Font>);
+
+impl UiFont {
+    pub fn style(&self, font_size: f32) -> TextStyle {
+        TextStyle {
+            font: self.0.clone(),
+            font_size,
+            color: TEXT_COLOR,
+        }
+    }
+}
+
+pub fn setup_ui_font(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(UiFont(asset_server.load(FONT_PATH)));
+}
+
+pub fn button_color_system(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<Button>)>,
+) {
+    for (interaction, mut color) in buttons.iter_mut() {
+        *color = match interaction {
+            Interaction::Clicked => BUTTON_PRESSED_COLOR.into(),
+            Interaction::Hovered => BUTTON_HOVER_COLOR.into(),
+            Interaction::None => BUTTON_COLOR.into(),
+        };
+    }
+}
@@ -0,0 +1,194 @@
+// Panel on the right listing the started quests and their objectives, toggled
+// with the quest log key. Unlike the settings menu it doesn't stop the game
+
+use super::{UiFont, PANEL_COLOR};
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::quest::{QuestCompletedEvent, QuestDefinitions};
+use crate::game_core::save::SaveData;
+use crate::game_core::settings::{Action, Settings};
+use bevy::prelude::*;
+
+static LOG_WIDTH: f32 = 280.0;
+static DONE_COLOR: Color = Color::rgb(0.55, 0.55, 0.5);
+static QUEST_COMPLETE_SECS: f32 = 3.0;
+
+#[derive(Resource, Default)]
+pub struct QuestLog {
+    pub open: bool,
+}
+
+#[derive(Component)]
+pub struct QuestLogRoot;
+
+// Rebuilt from the save data whenever it changes
+#[derive(Component)]
+pub struct QuestLogEntries;
+
+// Seconds left showing the quest complete message
+#[derive(Component, Default)]
+pub struct QuestCompleteText(pub f32);
+
+pub fn setup_quest_log(mut commands: Commands, font: Res<UiFont>) {
+    commands.init_resource::<QuestLog>();
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(12.0),
+                        top: Val::Px(12.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    size: Size::new(Val::Px(LOG_WIDTH), Val::Auto),
+                    padding: UiRect::all(Val::Px(12.0)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+            QuestLogRoot,
+        ))
+        .with_children(|panel| {
+            panel.spawn(
+                TextBundle::from_section("Quests", font.style(22.0)).with_style(Style {
+                    margin: UiRect::bottom(Val::Px(8.0)),
+                    ..default()
+                }),
+            );
+            panel.spawn((
+                TextBundle::default().with_style(Style {
+                    max_size: Size::new(Val::Px(LOG_WIDTH - 24.0), Val::Undefined),
+                    ..default()
+                }),
+                QuestLogEntries,
+            ));
+        });
+
+    commands.spawn((
+        TextBundle::from_section("", font.style(20.0)).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(12.0),
+                bottom: Val::Px(12.0),
+                ..default()
+            },
+            ..default()
+        }),
+        QuestCompleteText::default(),
+    ));
+}
+
+pub fn toggle_quest_log_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    input_locks: Res<InputLocks>,
+    mut quest_log: ResMut<QuestLog>,
+    mut root: Query<&mut Style, With<QuestLogRoot>>,
+) {
+    // Also keeps the key from toggling the log while it is being rebound
+    if input_locks.is_locked()
+        || !settings
+            .key_bindings
+            .just_pressed(Action::QuestLog, &keyboard_input)
+    {
+        return;
+    }
+
+    quest_log.open = !quest_log.open;
+    if let Ok(mut style) = root.get_single_mut() {
+        style.display = if quest_log.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+}
+
+pub fn quest_log_system(
+    definitions: Res<QuestDefinitions>,
+    save_data: Res<SaveData>,
+    font: Res<UiFont>,
+    mut entries: Query<&mut Text, With<QuestLogEntries>>,
+) {
+    if !save_data.is_changed() && !definitions.is_changed() {
+        return;
+    }
+
+    let mut sections = Vec::new();
+    // Quests still in progress come first
+    for done in [false, true] {
+        for quest in definitions.quests.iter() {
+            let Some(state) = save_data.quests.get(&quest.id) else {
+                continue;
+            };
+            if state.completed != done {
+                continue;
+            }
+
+            let mut title = font.style(18.0);
+            let mut body = font.style(14.0);
+            if done {
+                title.color = DONE_COLOR;
+                body.color = DONE_COLOR;
+            }
+
+            let suffix = if done { " (done)" } else { "" };
+            sections.push(TextSection::new(
+                format!("{}{}\n", quest.title, suffix),
+                title,
+            ));
+            if done {
+                continue;
+            }
+            sections.push(TextSection::new(
+                format!("{}\n", quest.description),
+                body.clone(),
+            ));
+            for (index, objective) in quest.objectives.iter().enumerate() {
+                let progress = state.progress.get(index).copied().unwrap_or(0);
+                let target = objective.goal.target();
+                let line = if target > 1 {
+                    format!("- {} ({}/{})\n", objective.text, progress, target)
+                } else {
+                    let mark = if progress >= target { " (done)" } else { "" };
+                    format!("- {}{}\n", objective.text, mark)
+                };
+                sections.push(TextSection::new(line, body.clone()));
+            }
+            sections.push(TextSection::new("\n", body));
+        }
+    }
+
+    for mut text in entries.iter_mut() {
+        text.sections = sections.clone();
+    }
+}
+
+pub fn quest_complete_text_system(
+    time: Res<Time>,
+    definitions: Res<QuestDefinitions>,
+    mut completed_events: EventReader<QuestCompletedEvent>,
+    mut texts: Query<(&mut Text, &mut QuestCompleteText)>,
+) {
+    let title = completed_events
+        .iter()
+        .filter_map(|event| definitions.get(&event.quest))
+        .map(|quest| quest.title.clone())
+        .last();
+
+    for (mut text, mut shown) in texts.iter_mut() {
+        if let Some(title) = &title {
+            text.sections[0].value = format!("Quest complete: {}", title);
+            shown.0 = QUEST_COMPLETE_SECS;
+        } else if shown.0 > 0.0 {
+            shown.0 -= time.delta_seconds();
+            if shown.0 <= 0.0 {
+                text.sections[0].value.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,367 @@
+// In-game settings screen, every change is applied (and saved) as soon as it's made
+
+use super::{UiFont, BUTTON_COLOR, PANEL_COLOR};
+use crate::game_core::entities::player::InputLocks;
+use crate::game_core::settings::{Action, DisplayMode, KeyBindings, Settings, RESOLUTIONS};
+use bevy::prelude::*;
+
+static INPUT_LOCK: &str = "settings_menu";
+
+static VOLUME_STEP: f32 = 0.1;
+static UI_SCALE_STEP: f64 = 0.25;
+static UI_SCALE_RANGE: (f64, f64) = (0.5, 2.0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingRow {
+    Resolution,
+    DisplayMode,
+    Vsync,
+    UiScale,
+    Lighting,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Binding(Action),
+    AttackButton,
+    SecondaryAttackButton,
+}
+
+static ROWS: [(SettingRow, &str); 20] = [
+    (SettingRow::Resolution, "Resolution"),
+    (SettingRow::DisplayMode, "Window mode"),
+    (SettingRow::Vsync, "VSync"),
+    (SettingRow::UiScale, "UI scale"),
+    (SettingRow::Lighting, "Lighting"),
+    (SettingRow::MasterVolume, "Master volume"),
+    (SettingRow::MusicVolume, "Music volume"),
+    (SettingRow::SfxVolume, "Effects volume"),
+    (SettingRow::Binding(Action::MoveUp), "Move up"),
+    (SettingRow::Binding(Action::MoveDown), "Move down"),
+    (SettingRow::Binding(Action::MoveLeft), "Move left"),
+    (SettingRow::Binding(Action::MoveRight), "Move right"),
+    (SettingRow::Binding(Action::Interact), "Interact"),
+    (SettingRow::Binding(Action::Dash), "Dash"),
+    (SettingRow::Binding(Action::QuestLog), "Quest log"),
+    (SettingRow::Binding(Action::Map), "Map"),
+    (SettingRow::Binding(Action::SettingsMenu), "Settings menu"),
+    (SettingRow::AttackButton, "Attack"),
+    (SettingRow::Binding(Action::HeavyAttack), "Heavy attack"),
+    (SettingRow::SecondaryAttackButton, "Ranged attack"),
+];
+
+#[derive(Resource, Default)]
+pub struct SettingsMenu {
+    pub open: bool,
+    // The action waiting for a new key, if any
+    pub rebinding: Option<Action>,
+    // The action owning the last key turned down while rebinding
+    pub rejected_for: Option<Action>,
+}
+
+#[derive(Component)]
+pub struct SettingsMenuRoot;
+
+// For bindings, -1 resets to the default keys and +1 waits for a new key
+#[derive(Component)]
+pub struct SettingButton {
+    pub row: SettingRow,
+    pub step: i32,
+}
+
+#[derive(Component)]
+pub struct SettingValueText(pub SettingRow);
+
+pub fn setup_settings_menu(mut commands: Commands, font: Res<UiFont>) {
+    commands.init_resource::<SettingsMenu>();
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            SettingsMenuRoot,
+        ))
+        .with_children(|root| {
+            root.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(16.0)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            })
+            .with_children(|panel| {
+                panel.spawn(
+                    TextBundle::from_section("Settings", font.style(28.0)).with_style(Style {
+                        margin: UiRect::bottom(Val::Px(12.0)),
+                        ..default()
+                    }),
+                );
+
+                for (row, label) in ROWS.iter() {
+                    spawn_row(panel, &font, *row, label);
+                }
+            });
+        });
+}
+
+fn spawn_row(panel: &mut ChildBuilder, font: &UiFont, row: SettingRow, label: &str) {
+    panel
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                margin: UiRect::vertical(Val::Px(2.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|row_node| {
+            row_node.spawn(
+                TextBundle::from_section(label, font.style(18.0)).with_style(Style {
+                    size: Size::new(Val::Px(180.0), Val::Auto),
+                    ..default()
+                }),
+            );
+
+            let (decrease, increase) = match row {
+                SettingRow::Binding(_) => ("Reset", "Rebind"),
+                _ => ("<", ">"),
+            };
+
+            spawn_button(row_node, font, row, -1, decrease);
+            row_node.spawn((
+                TextBundle::from_section("", font.style(18.0)).with_style(Style {
+                    size: Size::new(Val::Px(200.0), Val::Auto),
+                    margin: UiRect::horizontal(Val::Px(8.0)),
+                    ..default()
+                }),
+                SettingValueText(row),
+            ));
+            spawn_button(row_node, font, row, 1, increase);
+        });
+}
+
+fn spawn_button(parent: &mut ChildBuilder, font: &UiFont, row: SettingRow, step: i32, text: &str) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::new(Val::Px(8.0), Val::Px(8.0), Val::Px(2.0), Val::Px(2.0)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            SettingButton { row, step },
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(text, font.style(16.0)));
+        });
+}
+
+pub fn toggle_settings_menu_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    mut menu: ResMut<SettingsMenu>,
+    mut input_locks: ResMut<InputLocks>,
+    mut root: Query<&mut Style, With<SettingsMenuRoot>>,
+) {
+    // The key pressed while rebinding belongs to the binding, not to this toggle
+    if menu.rebinding.is_some()
+        || !settings
+            .key_bindings
+            .just_pressed(Action::SettingsMenu, &keyboard_input)
+    {
+        return;
+    }
+
+    menu.open = !menu.open;
+    if menu.open {
+        input_locks.lock(INPUT_LOCK);
+    } else {
+        input_locks.unlock(INPUT_LOCK);
+    }
+
+    if let Ok(mut style) = root.get_single_mut() {
+        style.display = if menu.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+}
+
+fn step_volume(volume: &mut f32, step: i32) {
+    *volume = (*volume + step as f32 * VOLUME_STEP).clamp(0.0, 1.0);
+}
+
+fn step_index(index: usize, len: usize, step: i32) -> usize {
+    (index as i32 + step).rem_euclid(len as i32) as usize
+}
+
+fn step_mouse_button(button: &mut MouseButton, step: i32) {
+    let buttons = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+    let current = buttons
+        .iter()
+        .position(|candidate| candidate == button)
+        .unwrap_or(0);
+    *button = buttons[step_index(current, buttons.len(), step)];
+}
+
+pub fn settings_button_system(
+    buttons: Query<(&Interaction, &SettingButton), Changed<Interaction>>,
+    mut settings: ResMut<Settings>,
+    mut menu: ResMut<SettingsMenu>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let step = button.step;
+        match button.row {
+            SettingRow::Resolution => {
+                let window = &settings.window;
+                let current = RESOLUTIONS
+                    .iter()
+                    .position(|&(width, height)| width == window.width && height == window.height)
+                    .unwrap_or(0);
+                let (width, height) = RESOLUTIONS[step_index(current, RESOLUTIONS.len(), step)];
+                settings.window.width = width;
+                settings.window.height = height;
+            }
+            SettingRow::DisplayMode => {
+                let modes = [
+                    DisplayMode::Windowed,
+                    DisplayMode::Borderless,
+                    DisplayMode::Fullscreen,
+                ];
+                let current = modes
+                    .iter()
+                    .position(|mode| *mode == settings.window.mode)
+                    .unwrap_or(0);
+                settings.window.mode = modes[step_index(current, modes.len(), step)];
+            }
+            SettingRow::Vsync => settings.window.vsync = !settings.window.vsync,
+            SettingRow::Lighting => settings.window.lighting = !settings.window.lighting,
+            SettingRow::UiScale => {
+                settings.window.ui_scale = (settings.window.ui_scale + step as f64 * UI_SCALE_STEP)
+                    .clamp(UI_SCALE_RANGE.0, UI_SCALE_RANGE.1);
+            }
+            SettingRow::MasterVolume => step_volume(&mut settings.audio.master_volume, step),
+            SettingRow::MusicVolume => step_volume(&mut settings.audio.music_volume, step),
+            SettingRow::SfxVolume => step_volume(&mut settings.audio.sfx_volume, step),
+            SettingRow::Binding(action) => {
+                if step < 0 {
+                    // Defaults another action has been given since are left out
+                    let defaults: Vec<KeyCode> = KeyBindings::default()
+                        .keys(action)
+                        .iter()
+                        .copied()
+                        .filter(|&key| settings.key_bindings.bound_elsewhere(key, action).is_none())
+                        .collect();
+                    *settings.key_bindings.keys_mut(action) = defaults;
+                } else {
+                    menu.rebinding = Some(action);
+                    menu.rejected_for = None;
+                }
+            }
+            SettingRow::AttackButton => step_mouse_button(&mut settings.key_bindings.attack, step),
+            SettingRow::SecondaryAttackButton => {
+                step_mouse_button(&mut settings.key_bindings.secondary_attack, step)
+            }
+        }
+    }
+}
+
+pub fn rebind_key_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut menu: ResMut<SettingsMenu>,
+) {
+    let Some(action) = menu.rebinding else {
+        return;
+    };
+
+    if let Some(&key) = keyboard_input.get_just_pressed().next() {
+        // One key per action, a key that's taken is turned down and the menu keeps waiting
+        if let Some(other) = settings.key_bindings.bound_elsewhere(key, action) {
+            info!("{:?} is already bound to {:?}", key, other);
+            menu.rejected_for = Some(other);
+            return;
+        }
+
+        // The new key becomes the main binding, the previous main key stays as the alternative
+        let keys = settings.key_bindings.keys_mut(action);
+        keys.retain(|bound| *bound != key);
+        keys.insert(0, key);
+        keys.truncate(2);
+        menu.rebinding = None;
+        menu.rejected_for = None;
+    }
+}
+
+fn value_text(row: SettingRow, settings: &Settings, menu: &SettingsMenu) -> String {
+    let percent = |volume: f32| format!("{}%", (volume * 100.0).round());
+    match row {
+        SettingRow::Resolution => format!("{}x{}", settings.window.width, settings.window.height),
+        SettingRow::DisplayMode => format!("{:?}", settings.window.mode),
+        SettingRow::Vsync => if settings.window.vsync { "On" } else { "Off" }.to_string(),
+        SettingRow::UiScale => format!("{:.2}", settings.window.ui_scale),
+        SettingRow::Lighting => if settings.window.lighting {
+            "On"
+        } else {
+            "Off"
+        }
+        .to_string(),
+        SettingRow::MasterVolume => percent(settings.audio.master_volume),
+        SettingRow::MusicVolume => percent(settings.audio.music_volume),
+        SettingRow::SfxVolume => percent(settings.audio.sfx_volume),
+        SettingRow::Binding(action) => {
+            if menu.rebinding == Some(action) {
+                match menu.rejected_for {
+                    Some(other) => format!("Taken by {:?}, press another key...", other),
+                    None => "Press a key...".to_string(),
+                }
+            } else {
+                settings
+                    .key_bindings
+                    .keys(action)
+                    .iter()
+                    .map(|key| format!("{:?}", key))
+                    .collect::<Vec<_>>()
+                    .join(" / ")
+            }
+        }
+        SettingRow::AttackButton => format!("{:?} mouse", settings.key_bindings.attack),
+        SettingRow::SecondaryAttackButton => {
+            format!("{:?} mouse", settings.key_bindings.secondary_attack)
+        }
+    }
+}
+
+pub fn update_settings_text_system(
+    settings: Res<Settings>,
+    menu: Res<SettingsMenu>,
+    mut texts: Query<(&mut Text, &SettingValueText)>,
+) {
+    if !settings.is_changed() && !menu.is_changed() {
+        return;
+    }
+
+    for (mut text, SettingValueText(row)) in texts.iter_mut() {
+        text.sections[0].value = value_text(*row, &settings, &menu);
+    }
+}
@@ -0,0 +1,391 @@
+// What the player can see, worked out on the level grid with the solid tiles
+// (the Fences) blocking the view. Cells out of sight are darkened, cells the
+// minimap has revealed before stay dimmed, and enemies out of sight are hidden.
+// Enemy AI uses the same line of sight check to know whether it can see the player
+
+use crate::game_core::entities::entity_components::{Enemy, Player};
+use crate::game_core::ui::minimap::{cells_around, MapExploration};
+use crate::game_core::world::{IntGridColliders, LevelTileGrid, TileBehaviour};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use bevy::sprite::Anchor;
+use bevy_ecs_ldtk::prelude::*;
+use std::collections::HashSet;
+
+// How far the player sees, in cells
+pub static VIEW_RADIUS: i32 = 10;
+
+// Above the level and particles, below the debug overlays
+static FOG_Z: f32 = 100.0;
+// Alpha of the fog over cells that were seen before and ones never seen
+static EXPLORED_FOG: u8 = 150;
+static UNEXPLORED_FOG: u8 = 245;
+
+#[derive(Resource, Default)]
+pub struct FieldOfView {
+    // The level entity the player is in
+    pub level: Option<Entity>,
+    // The cell the player was in when this was worked out
+    pub center: Option<GridCoords>,
+    pub visible: HashSet<GridCoords>,
+}
+
+// The darkness over a level, one pixel per cell
+#[derive(Component)]
+pub struct FogOfWar;
+
+pub fn blocks_sight(
+    grid: &LevelTileGrid,
+    colliders: &IntGridColliders,
+    coords: GridCoords,
+) -> bool {
+    matches!(
+        colliders
+            .values
+            .get(&grid.value(coords))
+            .map(|tile| tile.behaviour),
+        Some(TileBehaviour::Solid)
+    )
+}
+
+// Cells on the line from `from` to `to`, both included (Bresenham)
+pub fn grid_line(from: GridCoords, to: GridCoords) -> Vec<GridCoords> {
+    let (dx, dy) = ((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let (step_x, step_y) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+    let mut error = dx + dy;
+    let mut cell = from;
+    let mut cells = vec![cell];
+    while cell != to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            cell.x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            cell.y += step_y;
+        }
+        cells.push(cell);
+    }
+    cells
+}
+
+// Only the cells in between can block, so walls themselves can be seen
+pub fn line_of_sight(
+    grid: &LevelTileGrid,
+    colliders: &IntGridColliders,
+    from: GridCoords,
+    to: GridCoords,
+) -> bool {
+    let line = grid_line(from, to);
+    line.iter()
+        .skip(1)
+        .take(line.len().saturating_sub(2))
+        .all(|&cell| !blocks_sight(grid, colliders, cell))
+}
+
+pub fn field_of_view(
+    grid: &LevelTileGrid,
+    colliders: &IntGridColliders,
+    center: GridCoords,
+    radius: i32,
+) -> HashSet<GridCoords> {
+    cells_around(center, radius, grid.width, grid.height)
+        .into_iter()
+        .filter(|&cell| line_of_sight(grid, colliders, center, cell))
+        .collect()
+}
+
+// Not clamped to the level, cells outside it never block anything
+fn position_to_cell(grid: &LevelTileGrid, origin: Vec2, position: Vec2) -> GridCoords {
+    let cell = ((position - origin) / grid.grid_size as f32).floor();
+    GridCoords {
+        x: cell.x as i32,
+        y: cell.y as i32,
+    }
+}
+
+fn contains(grid: &LevelTileGrid, cell: GridCoords) -> bool {
+    (0..grid.width).contains(&cell.x) && (0..grid.height).contains(&cell.y)
+}
+
+#[derive(SystemParam)]
+pub struct LineOfSight<'w, 's> {
+    levels: Query<'w, 's, (Entity, &'static LevelTileGrid, &'static GlobalTransform)>,
+    colliders: Res<'w, IntGridColliders>,
+}
+
+impl<'w, 's> LineOfSight<'w, 's> {
+    // The level `position` is in and its cell there
+    pub fn level_at(&self, position: Vec2) -> Option<(Entity, &LevelTileGrid, GridCoords)> {
+        self.levels.iter().find_map(|(entity, grid, transform)| {
+            let cell = position_to_cell(grid, transform.translation().truncate(), position);
+            contains(grid, cell).then_some((entity, grid, cell))
+        })
+    }
+
+    pub fn cell_in(&self, level: Entity, position: Vec2) -> Option<GridCoords> {
+        let (_, grid, transform) = self.levels.get(level).ok()?;
+        let cell = position_to_cell(grid, transform.translation().truncate(), position);
+        contains(grid, cell).then_some(cell)
+    }
+
+    // Outside of any level there is nothing in the way
+    pub fn can_see(&self, from: Vec2, to: Vec2) -> bool {
+        for (_, grid, transform) in self.levels.iter() {
+            let origin = transform.translation().truncate();
+            let from_cell = position_to_cell(grid, origin, from);
+            if contains(grid, from_cell) {
+                let to_cell = position_to_cell(grid, origin, to);
+                return line_of_sight(grid, &self.colliders, from_cell, to_cell);
+            }
+        }
+        true
+    }
+
+    pub fn field_of_view(&self, grid: &LevelTileGrid, center: GridCoords) -> HashSet<GridCoords> {
+        field_of_view(grid, &self.colliders, center, VIEW_RADIUS)
+    }
+}
+
+pub fn field_of_view_system(
+    players: Query<&GlobalTransform, With<Player>>,
+    line_of_sight: LineOfSight,
+    changed_levels: Query<(), Changed<LevelTileGrid>>,
+    mut view: ResMut<FieldOfView>,
+) {
+    let Ok(player_transform) = players.get_single() else {
+        return;
+    };
+    let Some((level, grid, center)) =
+        line_of_sight.level_at(player_transform.translation().truncate())
+    else {
+        return;
+    };
+
+    // Only worked out again when the player changes cell or a tile is edited
+    if view.level == Some(level) && view.center == Some(center) && changed_levels.is_empty() {
+        return;
+    }
+
+    view.visible = line_of_sight.field_of_view(grid, center);
+    view.level = Some(level);
+    view.center = Some(center);
+}
+
+pub fn hide_unseen_enemies_system(
+    view: Res<FieldOfView>,
+    line_of_sight: LineOfSight,
+    mut enemies: Query<(&GlobalTransform, &mut Visibility), With<Enemy>>,
+) {
+    for (transform, mut visibility) in enemies.iter_mut() {
+        let position = transform.translation().truncate();
+        let seen = match view.level {
+            Some(level) => line_of_sight
+                .cell_in(level, position)
+                .map_or(true, |cell| view.visible.contains(&cell)),
+            None => true,
+        };
+        if visibility.is_visible != seen {
+            visibility.is_visible = seen;
+        }
+    }
+}
+
+// RGBA pixels of the fog, starting from the top row like images do
+pub fn render_fog(
+    grid: &LevelTileGrid,
+    visible: &HashSet<GridCoords>,
+    explored: &HashSet<GridCoords>,
+) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((grid.width * grid.height * 4) as usize);
+    for row in 0..grid.height {
+        for x in 0..grid.width {
+            let coords = GridCoords {
+                x,
+                y: grid.height - 1 - row,
+            };
+            let alpha = if visible.contains(&coords) {
+                0
+            } else if explored.contains(&coords) {
+                EXPLORED_FOG
+            } else {
+                UNEXPLORED_FOG
+            };
+            pixels.extend_from_slice(&[0, 0, 0, alpha]);
+        }
+    }
+    pixels
+}
+
+// Redraws the fog of the player's level in place, adding it to the level the
+// first time
+pub fn fog_of_war_system(
+    mut commands: Commands,
+    view: Res<FieldOfView>,
+    exploration: Res<MapExploration>,
+    levels: Query<&LevelTileGrid>,
+    fogs: Query<(&Parent, &Handle<Image>), With<FogOfWar>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !view.is_changed() && !exploration.is_changed() {
+        return;
+    }
+    let Some(level) = view.level else {
+        return;
+    };
+    let Ok(grid) = levels.get(level) else {
+        return;
+    };
+    let empty = HashSet::new();
+    let explored = exploration.revealed.get(&grid.level_iid).unwrap_or(&empty);
+    let pixels = render_fog(grid, &view.visible, explored);
+
+    if let Some((_, fog)) = fogs.iter().find(|(parent, _)| parent.get() == level) {
+        if let Some(image) = images.get_mut(fog) {
+            image.data = pixels;
+        }
+        return;
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: grid.width as u32,
+            height: grid.height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // Blurs the edges of the fog, unlike the pixel art
+    image.sampler_descriptor = ImageSampler::linear();
+    let handle = images.add(image);
+
+    let size = Vec2::new(grid.width as f32, grid.height as f32) * grid.grid_size as f32;
+    let fog = commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(size),
+                    anchor: Anchor::BottomLeft,
+                    ..default()
+                },
+                texture: handle,
+                transform: Transform::from_xyz(0.0, 0.0, FOG_Z),
+                ..default()
+            },
+            FogOfWar,
+        ))
+        .id();
+    commands.entity(level).add_child(fog);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::test_harness::TestApp;
+    use bevy_rapier2d::prelude::Velocity;
+
+    // A wall running up the whole grid at x == 10
+    fn walled_grid() -> LevelTileGrid {
+        LevelTileGrid {
+            level_iid: "test-level".to_string(),
+            layer: "Fences".to_string(),
+            width: 16,
+            height: 16,
+            grid_size: 16,
+            cells: (0..16).map(|y| (GridCoords { x: 10, y }, 1)).collect(),
+        }
+    }
+
+    #[test]
+    fn walls_block_the_view_but_can_be_seen() {
+        let grid = walled_grid();
+        let colliders = IntGridColliders::default();
+        let visible = field_of_view(&grid, &colliders, GridCoords { x: 8, y: 8 }, VIEW_RADIUS);
+
+        assert!(visible.contains(&GridCoords { x: 9, y: 12 }));
+        assert!(visible.contains(&GridCoords { x: 10, y: 8 }));
+        assert!(!visible.contains(&GridCoords { x: 11, y: 8 }));
+        assert!(!visible.contains(&GridCoords { x: 14, y: 3 }));
+    }
+
+    #[test]
+    fn enemies_behind_walls_are_hidden_and_blind() {
+        let mut test_app = TestApp::sandbox();
+
+        // Puts the player in the middle of cell (8, 8), left of the wall
+        let player = test_app.player();
+        let position = test_app
+            .get::<Transform>(player)
+            .unwrap()
+            .translation
+            .truncate();
+        let origin = position - Vec2::splat(8.5 * 16.0);
+        let level = test_app
+            .app
+            .world
+            .spawn((
+                walled_grid(),
+                TransformBundle::from(Transform::from_translation(origin.extend(0.0))),
+            ))
+            .id();
+
+        let skeleton = test_app.spawn_enemy("skeleton", position + Vec2::new(48.0, 0.0));
+        test_app
+            .app
+            .world
+            .entity_mut(skeleton)
+            .insert(VisibilityBundle::default());
+        test_app.step(10);
+
+        assert!(!test_app.get::<Visibility>(skeleton).unwrap().is_visible);
+        assert_eq!(
+            test_app.get::<Velocity>(skeleton).unwrap().linvel,
+            Vec2::ZERO
+        );
+
+        test_app
+            .get_mut::<LevelTileGrid>(level)
+            .unwrap()
+            .cells
+            .clear();
+        test_app.step(10);
+
+        assert!(test_app.get::<Visibility>(skeleton).unwrap().is_visible);
+        assert!(test_app.get::<Velocity>(skeleton).unwrap().linvel.x < 0.0);
+    }
+
+    #[test]
+    fn the_map_only_reveals_what_was_seen() {
+        let mut test_app = TestApp::sandbox();
+
+        // Puts the player in the middle of cell (8, 8), left of the wall
+        let player = test_app.player();
+        let position = test_app
+            .get::<Transform>(player)
+            .unwrap()
+            .translation
+            .truncate();
+        let origin = position - Vec2::splat(8.5 * 16.0);
+        let level = test_app
+            .app
+            .world
+            .spawn((
+                walled_grid(),
+                TransformBundle::from(Transform::from_translation(origin.extend(0.0))),
+            ))
+            .id();
+        test_app.step(2);
+
+        let exploration = test_app.app.world.resource::<MapExploration>();
+        assert_eq!(exploration.current, Some(level));
+        let revealed = &exploration.revealed["test-level"];
+        assert!(revealed.contains(&GridCoords { x: 8, y: 8 }));
+        assert!(revealed.contains(&GridCoords { x: 10, y: 8 }));
+        assert!(!revealed.contains(&GridCoords { x: 12, y: 8 }));
+    }
+}
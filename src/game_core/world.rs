@@ -3,9 +3,25 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_tilemap::tiles::{TilePos, TileStorage};
+use serde::Deserialize;
 
+use crate::game_core::attack::DamageEvent;
+use crate::game_core::collision_layers::Layer;
+use crate::game_core::data::load_ron_or_default;
+use crate::game_core::save::SaveData;
+use crate::game_core::grid_rects::{
+    int_grid_values, merge_cells_into_rects, update_rects_around, GridRect,
+};
 use std::collections::{HashMap, HashSet};
 
+pub static INT_GRID_COLLIDERS_PATH: &str = "assets/data/int_grid_colliders.ron";
+
+pub static DEFAULT_COLLISION_LAYER: &str = "Fences";
+
+// Time between two hits from the same hazard
+pub static HAZARD_TICK_SECS: f32 = 0.5;
+
 #[derive(
 Copy, Clone, Eq, PartialEq, Debug, Default, Component,
 )]
@@ -16,181 +32,696 @@ pub struct FenceBundle {
     fence: Fence,
 }
 
-// This function takes all the colliders from the fences
-// and combines them into bigger rectangles, improving performance
-pub fn spawn_fence_collision(
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Deserialize)]
+pub enum TileBehaviour {
+    // Blocks everything
+    Solid,
+    // Blocks walking, but not things flying over it
+    Water,
+    // Can only be crossed in `ledge_direction`
+    OneWayLedge,
+    // Slows down whatever walks on it
+    Slow,
+    // Hurts whatever stands on it
+    Hazard,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TileCollider {
+    pub behaviour: TileBehaviour,
+    // Max speed multiplier for Slow tiles
+    pub slow_factor: f32,
+    // Damage every HAZARD_TICK_SECS for Hazard tiles
+    pub damage: f32,
+    // Direction a OneWayLedge lets you go through, (0, -1) is jumping down
+    pub ledge_direction: (f32, f32),
+    // Health of tiles the player can break by attacking them, None if they can't be broken
+    // Goes by IntGrid value, so only the collision layer has breakable tiles
+    pub breakable: Option<f32>,
+}
+
+impl Default for TileCollider {
+    fn default() -> Self {
+        TileCollider {
+            behaviour: TileBehaviour::Solid,
+            slow_factor: 0.5,
+            damage: 1.0,
+            ledge_direction: (0.0, -1.0),
+            breakable: None,
+        }
+    }
+}
+
+// Which IntGrid values get colliders, and how they behave
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct IntGridColliders {
+    // Identifier of the IntGrid layer the colliders are built from
+    pub layer: String,
+    pub values: HashMap<i32, TileCollider>,
+}
+
+impl Default for IntGridColliders {
+    fn default() -> Self {
+        // Fences were the only colliders before this was configurable
+        IntGridColliders {
+            layer: DEFAULT_COLLISION_LAYER.to_string(),
+            values: HashMap::from([(1, TileCollider::default())]),
+        }
+    }
+}
+
+impl IntGridColliders {
+    pub fn load() -> IntGridColliders {
+        load_ron_or_default(INT_GRID_COLLIDERS_PATH)
+    }
+}
+
+// The IntGrid values of a level, kept on the level entity so tiles can be
+// edited at runtime without going back to the LDtk asset
+#[derive(Clone, Debug, Default, Component)]
+pub struct LevelTileGrid {
+    pub level_iid: String,
+    pub layer: String,
+    pub width: i32,
+    pub height: i32,
+    pub grid_size: i32,
+    pub cells: HashMap<GridCoords, i32>,
+}
+
+impl LevelTileGrid {
+    pub fn from_layer(level_iid: &str, layer: &LayerInstance) -> LevelTileGrid {
+        LevelTileGrid {
+            level_iid: level_iid.to_string(),
+            layer: layer.identifier.clone(),
+            width: layer.c_wid,
+            height: layer.c_hei,
+            grid_size: layer.grid_size,
+            cells: int_grid_values(layer),
+        }
+    }
+
+    pub fn value(&self, coords: GridCoords) -> i32 {
+        self.cells.get(&coords).copied().unwrap_or(0)
+    }
+
+    pub fn cells_with(&self, value: i32) -> HashSet<GridCoords> {
+        self.cells
+            .iter()
+            .filter(|&(_, &cell_value)| cell_value == value)
+            .map(|(&coords, _)| coords)
+            .collect()
+    }
+}
+
+// Sent to place (or with value 0, remove) a tile at runtime
+// Only the colliders around the edited tile get rebuilt
+pub struct TileEditEvent {
+    pub level: Entity,
+    pub coords: GridCoords,
+    pub value: i32,
+}
+
+// Sent by the combat code when a breakable tile runs out of health
+pub struct TileBrokenEvent {
+    pub level: Entity,
+    pub coords: GridCoords,
+    pub value: i32,
+    pub position: Vec2,
+}
+
+// Sensor over a single breakable tile, so attacks can find which tile they hit
+// The tile itself still blocks movement through its MergedTileCollider
+#[derive(Clone, Debug, Component)]
+pub struct BreakableTile {
+    pub coords: GridCoords,
+    pub value: i32,
+    pub health: f32,
+}
+
+// One compound collider for every rectangle of an IntGrid value in a level
+#[derive(Clone, Debug, Component)]
+pub struct MergedTileCollider {
+    pub tile: TileCollider,
+    pub value: i32,
+    pub rects: Vec<GridRect>,
+    pub grid_size: i32,
+}
+
+impl MergedTileCollider {
+    pub fn collider(&self) -> Collider {
+        Collider::compound(
+            self.rects
+                .iter()
+                .map(|rect| {
+                    let half_size = rect.size(self.grid_size) / 2.;
+                    (
+                        rect.center(self.grid_size),
+                        0.,
+                        Collider::cuboid(half_size.x, half_size.y),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+// Max speed multiplier from the tiles an entity is standing on, used by the movement code
+#[derive(Copy, Clone, Debug, Component)]
+pub struct SpeedModifier(pub f32);
+
+impl Default for SpeedModifier {
+    fn default() -> Self {
+        SpeedModifier(1.0)
+    }
+}
+
+// Returns the collider bundle, and whether it should be a sensor
+fn tile_collider_bundle(merged: MergedTileCollider) -> (impl Bundle, bool) {
+    let friction = Friction {
+        coefficient: 0.1,
+        combine_rule: CoefficientCombineRule::Min,
+    };
+
+    let (layer, sensor) = match merged.tile.behaviour {
+        TileBehaviour::Solid => (Layer::World, false),
+        TileBehaviour::Water => (Layer::Water, false),
+        TileBehaviour::OneWayLedge | TileBehaviour::Slow | TileBehaviour::Hazard => {
+            (Layer::Trigger, true)
+        }
+    };
+
+    (
+        (
+            merged.collider(),
+            RigidBody::Fixed,
+            friction,
+            layer.collision_groups(),
+            merged,
+        ),
+        sensor,
+    )
+}
+
+fn spawn_merged_collider(
+    commands: &mut Commands,
+    level_entity: Entity,
+    merged: MergedTileCollider,
+) {
+    // Making the collider a child of the level serves two purposes:
+    // 1. Adjusts the transforms to be relative to the level for free
+    // 2. the colliders will be despawned automatically when levels unload
+    let (collider_bundle, sensor) = tile_collider_bundle(merged);
+    commands.entity(level_entity).with_children(|level| {
+        let mut tile_collider = level.spawn((
+            collider_bundle,
+            Transform::default(),
+            GlobalTransform::default(),
+        ));
+        if sensor {
+            tile_collider.insert(Sensor);
+        }
+    });
+}
+
+fn spawn_breakable_tiles(
+    commands: &mut Commands,
+    level_entity: Entity,
+    grid: &LevelTileGrid,
+    cells: &HashSet<GridCoords>,
+    value: i32,
+    health: f32,
+) {
+    let half_size = grid.grid_size as f32 / 2.;
+
+    commands.entity(level_entity).with_children(|level| {
+        for &coords in cells.iter() {
+            let center = Vec2::new(coords.x as f32, coords.y as f32) * grid.grid_size as f32
+                + Vec2::splat(half_size);
+
+            level.spawn((
+                BreakableTile {
+                    coords,
+                    value,
+                    health,
+                },
+                Collider::cuboid(half_size, half_size),
+                Sensor,
+                Layer::Breakable.collision_groups(),
+                Transform::from_xyz(center.x, center.y, 0.),
+                GlobalTransform::default(),
+            ));
+        }
+    });
+}
+
+// Once a level has spawned, this finds its IntGrid layer and combines all the
+// tiles that have a collider mapping into as few rectangles as possible,
+// with one compound collider per IntGrid value
+pub fn spawn_int_grid_collision(
     mut commands: Commands,
-    wall_query: Query<(&GridCoords, &Parent), Added<Fence>>,
-    parent_query: Query<&Parent, Without<Fence>>,
+    mut level_events: EventReader<LevelEvent>,
     level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    tile_colliders: Query<
+        (Entity, &Parent),
+        Or<(With<MergedTileCollider>, With<BreakableTile>)>,
+    >,
     levels: Res<Assets<LdtkLevel>>,
+    int_grid_colliders: Res<IntGridColliders>,
+    save_data: Res<SaveData>,
 ) {
-    /// Represents a wide wall that is 1 tile tall
-    /// Used to spawn wall collisions
-    #[derive(
-    Copy, Clone, Eq, PartialEq, Debug, Default, Hash,
-    )]
-    struct Plate {
-        left: i32,
-        right: i32,
-    }
-
-    /// A simple rectangle type representing a
-    /// wall of any size
-    #[derive(
-    Copy, Clone, Eq, PartialEq, Debug, Default, Hash,
-    )]
-    struct Rect {
-        left: i32,
-        right: i32,
-        top: i32,
-        bottom: i32,
-    }
-
-    // Consider where the walls are
-    // storing them as GridCoords in a HashSet for
-    // quick, easy lookup
-    //
-    // The key of this map will be the entity of the
-    // level the wall belongs to. This has two
-    // consequences in the resulting collision
-    // entities: 1. it forces the walls to be
-    // split along level boundaries 2. it lets us
-    // easily add the collision entities as children
-    // of the appropriate level entity
-    let mut level_to_wall_locations: HashMap<
-        Entity,
-        HashSet<GridCoords>,
-    > = HashMap::new();
-
-    wall_query.for_each(|(&grid_coords, parent)| {
-        // An intgrid tile's direct parent will be a layer
-        // entity, not the level entity To get the
-        // level entity, you need the tile's grandparent.
-        // This is where parent_query comes in.
-        if let Ok(grandparent) =
-            parent_query.get(parent.get())
-        {
-            level_to_wall_locations
-                .entry(grandparent.get())
-                .or_insert(HashSet::new())
-                .insert(grid_coords);
-        }
-    });
+    for level_event in level_events.iter() {
+        let LevelEvent::Spawned(level_iid) = level_event else {
+            continue;
+        };
 
-    if !wall_query.is_empty() {
-        level_query.for_each(|(level_entity, level_handle)| {
-            if let Some(level_walls) = level_to_wall_locations.get(&level_entity) {
-                let level = levels
-                    .get(level_handle)
-                    .expect("Level should be loaded by this point");
-
-                let LayerInstance {
-                    c_wid: width,
-                    c_hei: height,
-                    grid_size,
-                    ..
-                } = level
-                    .level
-                    .layer_instances
-                    .clone()
-                    .expect("Level asset should have layers")[0];
-
-                // combine wall tiles into flat "plates" in each individual row
-                let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
-
-                for y in 0..height {
-                    let mut row_plates: Vec<Plate> = Vec::new();
-                    let mut plate_start = None;
-
-                    // + 1 to the width so the algorithm "terminates" plates that touch the right
-                    // edge
-                    for x in 0..width + 1 {
-                        match (plate_start, level_walls.contains(&GridCoords { x, y })) {
-                            (Some(s), false) => {
-                                row_plates.push(Plate {
-                                    left: s,
-                                    right: x - 1,
-                                });
-                                plate_start = None;
-                            }
-                            (None, true) => plate_start = Some(x),
-                            _ => (),
-                        }
-                    }
+        for (level_entity, level_handle) in level_query.iter() {
+            let Some(level) = levels.get(level_handle) else {
+                continue;
+            };
+            if &level.level.iid != level_iid {
+                continue;
+            }
+
+            let layer = level
+                .level
+                .layer_instances
+                .iter()
+                .flatten()
+                .find(|layer| layer.identifier == int_grid_colliders.layer);
+            let Some(layer) = layer else {
+                warn!(
+                    "Level {} has no IntGrid layer called {}",
+                    level_iid, int_grid_colliders.layer
+                );
+                continue;
+            };
 
-                    plate_stack.push(row_plates);
+            // Respawned levels get their colliders built again from scratch
+            for (collider_entity, parent) in tile_colliders.iter() {
+                if parent.get() == level_entity {
+                    commands.entity(collider_entity).despawn_recursive();
                 }
+            }
 
-                // combine "plates" into rectangles across multiple rows
-                let mut wall_rects: Vec<Rect> = Vec::new();
-                let mut previous_rects: HashMap<Plate, Rect> = HashMap::new();
-
-                // an extra empty row so the algorithm "terminates" the rects that touch the top
-                // edge
-                plate_stack.push(Vec::new());
-
-                for (y, row) in plate_stack.iter().enumerate() {
-                    let mut current_rects: HashMap<Plate, Rect> = HashMap::new();
-                    for plate in row {
-                        if let Some(previous_rect) = previous_rects.remove(plate) {
-                            current_rects.insert(
-                                *plate,
-                                Rect {
-                                    top: previous_rect.top + 1,
-                                    ..previous_rect
-                                },
-                            );
-                        } else {
-                            current_rects.insert(
-                                *plate,
-                                Rect {
-                                    bottom: y as i32,
-                                    top: y as i32,
-                                    left: plate.left,
-                                    right: plate.right,
-                                },
-                            );
-                        }
-                    }
+            let mut grid = LevelTileGrid::from_layer(level_iid, layer);
+            for coords in save_data.broken_tiles(level_iid) {
+                grid.cells.remove(&coords);
+            }
+
+            for (&value, &tile) in int_grid_colliders.values.iter() {
+                let cells = grid.cells_with(value);
+
+                if let Some(health) = tile.breakable {
+                    spawn_breakable_tiles(
+                        &mut commands,
+                        level_entity,
+                        &grid,
+                        &cells,
+                        value,
+                        health,
+                    );
+                }
+
+                let rects = merge_cells_into_rects(&cells, grid.width, grid.height);
+                if rects.is_empty() {
+                    continue;
+                }
+
+                spawn_merged_collider(
+                    &mut commands,
+                    level_entity,
+                    MergedTileCollider {
+                        tile,
+                        value,
+                        rects,
+                        grid_size: grid.grid_size,
+                    },
+                );
+            }
+
+            commands.entity(level_entity).insert(grid);
+        }
+    }
+}
+
+// Applies TileEditEvents to the level grids, and rebuilds only the rectangles
+// around the edited tiles. Tiles placed with a breakable value get their
+// sensor too, so they can be broken like the ones from LDtk
+pub fn edit_int_grid_system(
+    mut commands: Commands,
+    mut edit_events: EventReader<TileEditEvent>,
+    mut grids: Query<&mut LevelTileGrid>,
+    mut merged_colliders: Query<(Entity, &Parent, &mut MergedTileCollider, &mut Collider)>,
+    breakables: Query<(Entity, &Parent, &BreakableTile)>,
+    int_grid_colliders: Res<IntGridColliders>,
+) {
+    // Every edited tile, for each (level, IntGrid value) that needs a rebuild
+    let mut edited: HashMap<(Entity, i32), Vec<GridCoords>> = HashMap::new();
+    let mut changed_cells: Vec<(Entity, GridCoords, i32)> = Vec::new();
+
+    for edit in edit_events.iter() {
+        let Ok(mut grid) = grids.get_mut(edit.level) else {
+            continue;
+        };
+
+        let old_value = grid.value(edit.coords);
+        if old_value == edit.value {
+            continue;
+        }
+        if edit.value == 0 {
+            grid.cells.remove(&edit.coords);
+        } else {
+            grid.cells.insert(edit.coords, edit.value);
+        }
+        changed_cells.push((edit.level, edit.coords, edit.value));
+
+        for value in [old_value, edit.value] {
+            if int_grid_colliders.values.contains_key(&value) {
+                edited
+                    .entry((edit.level, value))
+                    .or_insert(Vec::new())
+                    .push(edit.coords);
+            }
+        }
+    }
+
+    for ((level_entity, value), edited_coords) in edited {
+        let Ok(grid) = grids.get(level_entity) else {
+            continue;
+        };
+        let cells = grid.cells_with(value);
+
+        let existing = merged_colliders.iter_mut().find(|(_, parent, merged, _)| {
+            parent.get() == level_entity && merged.value == value
+        });
+
+        match existing {
+            Some((collider_entity, _, mut merged, mut collider)) => {
+                for coords in edited_coords {
+                    update_rects_around(
+                        &mut merged.rects,
+                        &cells,
+                        coords,
+                        grid.width,
+                        grid.height,
+                    );
+                }
 
-                    // Any plates that weren't removed above have terminated
-                    wall_rects.append(&mut previous_rects.values().copied().collect());
-                    previous_rects = current_rects;
+                if merged.rects.is_empty() {
+                    commands.entity(collider_entity).despawn_recursive();
+                } else {
+                    *collider = merged.collider();
+                }
+            }
+            None => {
+                let rects = merge_cells_into_rects(&cells, grid.width, grid.height);
+                if rects.is_empty() {
+                    continue;
                 }
 
-                commands.entity(level_entity).with_children(|level| {
-                    // Spawn colliders for every rectangle..
-                    // Making the collider a child of the level serves two purposes:
-                    // 1. Adjusts the transforms to be relative to the level for free
-                    // 2. the colliders will be despawned automatically when levels unload
-                    for wall_rect in wall_rects {
-                        level
-                            .spawn((Collider::cuboid(
-                                (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                            ),
-                                    RigidBody::Fixed,
-                                    Friction{
-                                        coefficient: 0.1,
-                                        combine_rule:
-                                        CoefficientCombineRule::Min,
-                                    },
-                                    Transform::from_xyz(
-                                        (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
-                                            / 2.,
-                                        (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
-                                            / 2.,
-                                        0.,
-                                    ),
-                                    GlobalTransform::default()));
+                spawn_merged_collider(
+                    &mut commands,
+                    level_entity,
+                    MergedTileCollider {
+                        tile: int_grid_colliders.values[&value],
+                        value,
+                        rects,
+                        grid_size: grid.grid_size,
+                    },
+                );
+            }
+        }
+    }
+
+    for (level_entity, coords, value) in changed_cells {
+        for (sensor, parent, tile) in breakables.iter() {
+            if parent.get() == level_entity && tile.coords == coords {
+                commands.entity(sensor).despawn_recursive();
+            }
+        }
+
+        let health = int_grid_colliders
+            .values
+            .get(&value)
+            .and_then(|tile| tile.breakable);
+        let (Some(health), Ok(grid)) = (health, grids.get(level_entity)) else {
+            continue;
+        };
+        let cells = HashSet::from([coords]);
+        spawn_breakable_tiles(&mut commands, level_entity, grid, &cells, value, health);
+    }
+}
+
+// Removes broken tiles from the level grid, which rebuilds their colliders,
+// and remembers them in the save data
+pub fn break_tiles_system(
+    mut broken_events: EventReader<TileBrokenEvent>,
+    mut edit_events: EventWriter<TileEditEvent>,
+    grids: Query<&LevelTileGrid>,
+    mut save_data: ResMut<SaveData>,
+) {
+    for broken in broken_events.iter() {
+        edit_events.send(TileEditEvent {
+            level: broken.level,
+            coords: broken.coords,
+            value: 0,
+        });
+
+        if let Ok(grid) = grids.get(broken.level) {
+            save_data.break_tile(&grid.level_iid, broken.coords);
+        }
+    }
+}
+
+// Despawns the IntGrid cells (and with them their tiles) that are no longer
+// in their level's grid, either broken this session or in the save data
+pub fn remove_edited_cells_system(
+    mut commands: Commands,
+    changed_grids: Query<(Entity, &LevelTileGrid), Changed<LevelTileGrid>>,
+    cells: Query<(Entity, &GridCoords, &TilePos, &IntGridCell, &Parent)>,
+    mut layers: Query<(&Parent, &LayerMetadata, Option<&mut TileStorage>), Without<IntGridCell>>,
+) {
+    if changed_grids.is_empty() {
+        return;
+    }
+
+    for (cell_entity, &coords, tile_pos, cell, parent) in cells.iter() {
+        // An intgrid tile's direct parent is the layer, the level is its grandparent
+        let Ok((layer_parent, layer, tile_storage)) = layers.get_mut(parent.get()) else {
+            continue;
+        };
+        let Ok((_, grid)) = changed_grids.get(layer_parent.get()) else {
+            continue;
+        };
+
+        if layer.identifier == grid.layer && grid.value(coords) != cell.value {
+            // The layer's tilemap would keep pointing at the despawned tile
+            if let Some(mut tile_storage) = tile_storage {
+                tile_storage.remove(tile_pos);
+            }
+            commands.entity(cell_entity).despawn_recursive();
+        }
+    }
+}
+
+// Applies the Slow, OneWayLedge and Hazard tiles to everything that moves
+pub fn tile_zone_system(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut movers: Query<(Entity, &mut Velocity, Option<&mut SpeedModifier>)>,
+    tiles: Query<&MergedTileCollider, With<Sensor>>,
+    mut hazard_timers: Local<HashMap<Entity, f32>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, mut velocity, speed_modifier) in movers.iter_mut() {
+        let mut slow_factor: f32 = 1.0;
+        let mut hazard_damage: f32 = 0.0;
+
+        for (collider1, collider2, intersecting) in rapier_context.intersections_with(entity) {
+            if !intersecting {
+                continue;
+            }
+            let other = if collider1 == entity { collider2 } else { collider1 };
+            let Ok(MergedTileCollider { tile, .. }) = tiles.get(other) else {
+                continue;
+            };
+
+            match tile.behaviour {
+                TileBehaviour::Slow => slow_factor = slow_factor.min(tile.slow_factor),
+                TileBehaviour::Hazard => hazard_damage = hazard_damage.max(tile.damage),
+                TileBehaviour::OneWayLedge => {
+                    // Remove any movement against the direction of the ledge
+                    let direction = Vec2::from(tile.ledge_direction).normalize_or_zero();
+                    let against = velocity.linvel.dot(direction);
+                    if against < 0.0 {
+                        velocity.linvel -= direction * against;
                     }
+                }
+                TileBehaviour::Solid | TileBehaviour::Water => {}
+            }
+        }
+
+        if let Some(mut speed_modifier) = speed_modifier {
+            if speed_modifier.0 != slow_factor {
+                speed_modifier.0 = slow_factor;
+            }
+        }
+
+        if hazard_damage > 0.0 {
+            // First hit is immediate, then one every HAZARD_TICK_SECS
+            let timer = hazard_timers.entry(entity).or_insert(HAZARD_TICK_SECS);
+            *timer += time.delta_seconds();
+            if *timer >= HAZARD_TICK_SECS {
+                *timer = 0.0;
+                damage_events.send(DamageEvent {
+                    target: entity,
+                    amount: hazard_damage,
                 });
             }
+        } else {
+            hazard_timers.remove(&entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::attack::resisted_damage;
+    use crate::game_core::entities::entity_components::{DamageResistance, Health};
+    use crate::game_core::test_harness::{TestApp, TEST_TIMESTEP_SECS};
+
+    // A two by two tile zone centered on the player
+    fn zone_on_player(test_app: &mut TestApp, tile: TileCollider) {
+        let player = test_app.player();
+        let position = test_app.get::<Transform>(player).unwrap().translation;
+        let (collider_bundle, sensor) = tile_collider_bundle(MergedTileCollider {
+            tile,
+            value: 9,
+            rects: vec![GridRect {
+                left: -1,
+                right: 0,
+                top: 0,
+                bottom: -1,
+            }],
+            grid_size: 16,
         });
+        assert!(sensor);
+        test_app.app.world.spawn((
+            collider_bundle,
+            Sensor,
+            TransformBundle::from(Transform::from_translation(position)),
+        ));
+        test_app.step(1);
+    }
+
+    #[test]
+    fn slow_tiles_lower_the_speed_modifier() {
+        let mut test_app = TestApp::sandbox();
+        let tile = TileCollider {
+            behaviour: TileBehaviour::Slow,
+            slow_factor: 0.4,
+            ..default()
+        };
+        zone_on_player(&mut test_app, tile);
+        test_app.step(1);
+
+        let player = test_app.player();
+        assert_eq!(test_app.get::<SpeedModifier>(player).unwrap().0, 0.4);
+    }
+
+    #[test]
+    fn hazard_tiles_hurt_right_away_then_every_tick() {
+        let mut test_app = TestApp::sandbox();
+        let player = test_app.player();
+        let health = test_app.get::<Health>(player).unwrap().0;
+        let resistance = test_app.get::<DamageResistance>(player).unwrap().0;
+        let tile = TileCollider {
+            behaviour: TileBehaviour::Hazard,
+            damage: 5.0,
+            ..default()
+        };
+        zone_on_player(&mut test_app, tile);
+        test_app.step(1);
+
+        let hit = resisted_damage(5.0, resistance);
+        assert_eq!(test_app.get::<Health>(player).unwrap().0, health - hit);
+
+        test_app.step((HAZARD_TICK_SECS / TEST_TIMESTEP_SECS) as u32 + 1);
+        assert_eq!(
+            test_app.get::<Health>(player).unwrap().0,
+            health - hit - hit
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ledges_can_only_be_crossed_one_way() {
+        let mut test_app = TestApp::sandbox();
+        let tile = TileCollider {
+            behaviour: TileBehaviour::OneWayLedge,
+            ledge_direction: (0.0, -1.0),
+            ..default()
+        };
+        zone_on_player(&mut test_app, tile);
+        let player = test_app.player();
+
+        test_app.press_key(KeyCode::W);
+        test_app.step(5);
+        assert!(test_app.get::<Velocity>(player).unwrap().linvel.y.abs() < 1e-3);
+
+        test_app.release_key(KeyCode::W);
+        test_app.press_key(KeyCode::S);
+        test_app.step(5);
+        assert!(test_app.get::<Velocity>(player).unwrap().linvel.y < 0.0);
+    }
+
+    #[test]
+    fn placed_breakable_tiles_can_be_broken() {
+        let mut test_app = TestApp::new();
+        let mut colliders = IntGridColliders::default();
+        colliders.values.insert(
+            2,
+            TileCollider {
+                breakable: Some(20.0),
+                ..default()
+            },
+        );
+        test_app.app.insert_resource(colliders);
+        let level = test_app
+            .app
+            .world
+            .spawn((
+                LevelTileGrid {
+                    level_iid: "test-level".to_string(),
+                    layer: "Fences".to_string(),
+                    width: 8,
+                    height: 8,
+                    grid_size: 16,
+                    cells: HashMap::new(),
+                },
+                TransformBundle::default(),
+            ))
+            .id();
+        let coords = GridCoords { x: 3, y: 4 };
+
+        let edit = |test_app: &mut TestApp, value| {
+            test_app.app.world.send_event(TileEditEvent {
+                level,
+                coords,
+                value,
+            });
+            test_app.step(1);
+            let world = &mut test_app.app.world;
+            world
+                .query::<(&BreakableTile, &Parent)>()
+                .iter(world)
+                .map(|(tile, parent)| (tile.coords, tile.health, parent.get()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(edit(&mut test_app, 2), vec![(coords, 20.0, level)]);
+        assert!(edit(&mut test_app, 0).is_empty());
+    }
+}
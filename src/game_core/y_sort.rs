@@ -0,0 +1,193 @@
+// Draws whatever stands lower on the screen in front, by setting the z of
+// entities with YSort from where their feet are
+// bevy_ecs_ldtk puts each layer 1 above the one under it, so sorted entities
+// only move within a band above the z of their own layer and never end up
+// over (or under) another layer
+// Only entities are sorted. Tiles stay at the z of their tile or auto layer,
+// so anything drawn as tiles (a tree on a tile layer, say) is always under or
+// over the sorted entities, it has to be an entity to be walked behind
+
+use crate::game_core::data::load_ron_or_default;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub static Y_SORT_PATH: &str = "assets/data/y_sort.ron";
+
+// How much of the gap up to the next layer sorting uses
+pub static Y_SORT_BAND: f32 = 0.9;
+// Height in pixels, centered on y = 0, over which the order stays exact
+pub static Y_SORT_SPAN: f32 = 8192.0;
+
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct YSort {
+    // From the entity's position to its feet, in pixels
+    pub anchor: f32,
+    // The z of its LDtk layer, taken from the z it had when sorting started
+    pub layer_z: Option<f32>,
+}
+
+impl YSort {
+    pub fn new(anchor: f32) -> YSort {
+        YSort {
+            anchor,
+            layer_z: None,
+        }
+    }
+}
+
+// Enemies take theirs from their archetype instead
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct YSortConfig {
+    // By LDtk entity identifier
+    pub anchors: HashMap<String, f32>,
+}
+
+impl Default for YSortConfig {
+    fn default() -> Self {
+        YSortConfig {
+            anchors: HashMap::from([
+                ("Player".to_string(), -11.0),
+                ("Npc".to_string(), -11.0),
+                ("Chest".to_string(), -6.0),
+                ("Door".to_string(), -8.0),
+                ("Key".to_string(), -3.0),
+            ]),
+        }
+    }
+}
+
+impl YSortConfig {
+    pub fn load() -> YSortConfig {
+        load_ron_or_default(Y_SORT_PATH)
+    }
+}
+
+pub fn sort_z(layer_z: f32, feet_y: f32) -> f32 {
+    let fraction = (0.5 - feet_y / Y_SORT_SPAN).clamp(0.0, 1.0);
+    layer_z + fraction * Y_SORT_BAND
+}
+
+// Any LDtk entity with an anchor in the config is sorted, new kinds of
+// entities only need a line in y_sort.ron
+pub fn attach_y_sort_system(
+    mut commands: Commands,
+    config: Res<YSortConfig>,
+    instances: Query<(Entity, &EntityInstance), Added<EntityInstance>>,
+) {
+    for (entity, instance) in instances.iter() {
+        if let Some(&anchor) = config.anchors.get(&instance.identifier) {
+            commands.entity(entity).insert(YSort::new(anchor));
+        }
+    }
+}
+
+// Runs before the transforms are propagated, so it goes by last frame's
+// GlobalTransform. Entities can be children of a level, and it is the
+// position in the world that has to be compared
+pub fn y_sort_system(mut sorted: Query<(&mut YSort, &mut Transform, &GlobalTransform)>) {
+    for (mut y_sort, mut transform, global_transform) in sorted.iter_mut() {
+        let layer_z = match y_sort.layer_z {
+            Some(layer_z) => layer_z,
+            None => {
+                y_sort.layer_z = Some(transform.translation.z);
+                transform.translation.z
+            }
+        };
+
+        let z = sort_z(layer_z, global_transform.translation().y + y_sort.anchor);
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_core::test_harness::TestApp;
+
+    fn spawn_instance(test_app: &mut TestApp, identifier: &str, position: Vec3) -> Entity {
+        test_app
+            .app
+            .world
+            .spawn((
+                EntityInstance {
+                    identifier: identifier.to_string(),
+                    ..default()
+                },
+                TransformBundle::from(Transform::from_translation(position)),
+            ))
+            .id()
+    }
+
+    fn in_band(test_app: &TestApp, entity: Entity, layer_z: f32) -> bool {
+        let z = test_app.get::<Transform>(entity).unwrap().translation.z;
+        test_app.get::<YSort>(entity).unwrap().layer_z == Some(layer_z)
+            && z >= layer_z
+            && z < layer_z + 1.0
+    }
+
+    #[test]
+    fn lower_feet_are_drawn_in_front() {
+        assert!(sort_z(3.0, 100.0) > sort_z(3.0, 120.0));
+        assert!(sort_z(3.0, -40.0) > sort_z(3.0, 0.0));
+    }
+
+    #[test]
+    fn stays_under_the_next_layer() {
+        assert!(sort_z(3.0, 1.0e6) >= 3.0);
+        assert!(sort_z(3.0, -1.0e6) < 4.0);
+    }
+
+    #[test]
+    fn ldtk_entities_with_an_anchor_are_sorted() {
+        // Doors, keys and chests are built from their LDtk bundles
+        let mut test_app = TestApp::sandbox();
+        let trigger = spawn_instance(&mut test_app, "Trigger", Vec3::new(64.0, 40.0, 3.0));
+        test_app.step(2);
+
+        let world = &mut test_app.app.world;
+        let anchors: HashMap<String, f32> = world
+            .query::<(&EntityInstance, &YSort)>()
+            .iter(world)
+            .map(|(instance, y_sort)| (instance.identifier.clone(), y_sort.anchor))
+            .collect();
+        assert_eq!(anchors["Player"], -11.0);
+        assert_eq!(anchors["Door"], -8.0);
+        assert_eq!(anchors["Key"], -3.0);
+        assert_eq!(anchors["Chest"], -6.0);
+
+        assert!(test_app.get::<YSort>(trigger).is_none());
+        assert_eq!(
+            test_app.get::<Transform>(trigger).unwrap().translation.z,
+            3.0
+        );
+    }
+
+    #[test]
+    fn sorted_entities_stay_in_their_layer_band() {
+        let mut test_app = TestApp::new();
+        let high = spawn_instance(&mut test_app, "Chest", Vec3::new(64.0, 100.0, 3.0));
+        let low = spawn_instance(&mut test_app, "Npc", Vec3::new(64.0, 40.0, 3.0));
+        let far_down = spawn_instance(&mut test_app, "Key", Vec3::new(64.0, -1.0e6, 3.0));
+        test_app.step(3);
+
+        for entity in [high, low, far_down] {
+            assert!(in_band(&test_app, entity, 3.0));
+        }
+        let z = |entity| test_app.get::<Transform>(entity).unwrap().translation.z;
+        assert!(z(low) > z(high));
+        assert!(z(far_down) > z(low));
+
+        // Walking up past the chest puts the NPC behind it
+        test_app.get_mut::<Transform>(low).unwrap().translation.y = 160.0;
+        test_app.step(2);
+
+        assert!(in_band(&test_app, low, 3.0));
+        let z = |entity| test_app.get::<Transform>(entity).unwrap().translation.z;
+        assert!(z(low) < z(high));
+    }
+}
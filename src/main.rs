@@ -6,41 +6,73 @@ extern crate core;
 pub mod game_core;
 
 use bevy::prelude::*;
-use bevy::sprite::{Anchor, MaterialMesh2dBundle};
+use bevy::sprite::{Anchor, Material2dPlugin, MaterialMesh2dBundle};
+use bevy::transform::TransformSystem;
 use bevy::window::{CompositeAlphaMode, CursorGrabMode, PresentMode, WindowResizeConstraints};
 use bevy_ecs_ldtk::prelude::*;
-use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier2d::prelude::*;
 use std::thread::spawn;
 
+use crate::game_core::entities::attacks;
+use crate::game_core::entities::chest;
+use crate::game_core::entities::door;
 use crate::game_core::entities::enemy;
+use crate::game_core::entities::enemy_archetypes::{self, EnemyArchetypes};
 use crate::game_core::entities::entity_components::{EnemyBundle, PlayerBundle};
 use game_core::attack;
+use game_core::audio;
 use game_core::camera;
+#[cfg(feature = "debug")]
+use game_core::debug;
+use game_core::dialogue;
 use game_core::entities::entity_components;
+use game_core::entities::interaction;
+use game_core::entities::levelling;
+use game_core::entities::npc;
 use game_core::entities::player;
+use game_core::entities::spawner;
+use game_core::entities::stamina;
+use game_core::entities::trigger;
+use game_core::lighting;
+use game_core::particles;
+use game_core::projectile;
+use game_core::quest;
+use game_core::save;
+use game_core::settings::{self, Settings};
+use game_core::tile_animation;
+use game_core::ui;
+use game_core::ui::dialogue_box;
+use game_core::ui::hud;
+use game_core::ui::minimap;
+use game_core::ui::quest_log;
+use game_core::ui::settings_menu;
+use game_core::visibility;
 use game_core::world;
+use game_core::y_sort;
 
 fn main() {
     let mut app = App::new();
 
+    // Loaded before anything else so the window is created with the user's settings
+    let settings = Settings::load();
+
     app.add_plugins(
         DefaultPlugins
             .set(WindowPlugin {
                 window: WindowDescriptor {
-                    width: 1024.0,
-                    height: 576.0,
+                    width: settings.window.width,
+                    height: settings.window.height,
                     position: WindowPosition::Centered,
                     monitor: MonitorSelection::Primary,
                     resize_constraints: WindowResizeConstraints::default(),
                     scale_factor_override: None,
                     title: "Bevy Good Game".to_string(),
-                    present_mode: PresentMode::AutoVsync,
+                    present_mode: settings.window.present_mode(),
                     resizable: false,
                     decorations: true,
                     cursor_visible: true,
                     cursor_grab_mode: CursorGrabMode::None,
-                    mode: WindowMode::Windowed,
+                    mode: settings.window.mode.window_mode(),
                     transparent: false,
                     canvas: None,
                     fit_canvas_to_parent: false,
@@ -52,22 +84,195 @@ fn main() {
             })
             .set(ImagePlugin::default_nearest()),
     )
-    .add_plugin(RapierDebugRenderPlugin::default())
     .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
     .add_plugin(LdtkPlugin)
+    .add_plugin(Material2dPlugin::<lighting::LightingMaterial>::default())
     .insert_resource(LevelSelection::Index(0))
     .register_ldtk_int_cell::<world::FenceBundle>(1)
     .register_ldtk_entity::<PlayerBundle>("Player")
     .register_ldtk_entity::<EnemyBundle>("Enemy")
-    // .add_plugin(WorldInspectorPlugin);  // -> Debug information panel
+    .register_ldtk_entity::<door::DoorBundle>("Door")
+    .register_ldtk_entity::<door::KeyBundle>("Key")
+    .register_ldtk_entity::<spawner::SpawnerBundle>("Spawner")
+    .register_ldtk_entity::<spawner::BarrierBundle>("Barrier")
+    .register_ldtk_entity::<npc::NpcBundle>("Npc")
+    .register_ldtk_entity::<chest::ChestBundle>("Chest")
+    .register_ldtk_entity::<trigger::TriggerBundle>("Trigger")
+    .register_ldtk_entity::<lighting::LightBundle>("Light")
     .add_startup_system(setup_system)
+    .add_startup_system(particles::setup_particle_assets)
+    .add_startup_system(audio::setup_audio_system)
+    .add_startup_system_to_stage(StartupStage::PreStartup, ui::setup_ui_font)
+    .add_startup_system(settings_menu::setup_settings_menu)
+    .add_startup_system(hud::setup_hud)
+    .add_startup_system(dialogue_box::setup_dialogue_box)
+    .add_startup_system(quest_log::setup_quest_log)
+    .add_startup_system(minimap::setup_minimap)
+    .add_startup_system(lighting::setup_lighting)
+    .insert_resource(UiScale {
+        scale: settings.window.ui_scale,
+    })
+    .insert_resource(settings)
+    .init_resource::<player::InputLocks>()
     .insert_resource(LevelSelection::Index(0))
+    .add_event::<attack::DamageEvent>()
+    .add_event::<attack::EnemyHitEvent>()
+    .add_event::<attack::EnemyDeathEvent>()
+    .add_event::<levelling::LevelUpEvent>()
+    .add_event::<levelling::XpRewardEvent>()
+    .add_event::<chest::ChestOpenedEvent>()
+    .add_event::<quest::QuestEvent>()
+    .add_event::<quest::QuestCompletedEvent>()
+    .add_event::<audio::SoundEvent>()
+    .add_event::<world::TileEditEvent>()
+    .add_event::<world::TileBrokenEvent>()
     .add_system(player::player_movement_system)
+    .init_resource::<camera::CameraShake>()
     .add_system(camera::follow_player_system)
+    .insert_resource(attacks::AttackDefinitions::load())
     .add_system(player::attack_handler_system)
+    .add_system(player::ranged_attack_system)
+    .insert_resource(stamina::StaminaConfig::load())
+    .add_system(stamina::apply_equipment_load_system)
+    .add_system(stamina::stamina_regen_system)
+    .add_system(stamina::dash_system)
+    .insert_resource(levelling::LevellingConfig::load())
+    .add_system(levelling::restore_level_system)
+    .add_system(levelling::experience_system)
+    .add_system(projectile::projectile_lifetime_system)
+    .insert_resource(EnemyArchetypes::load())
+    .add_startup_system(enemy_archetypes::setup_enemy_archetypes)
     .add_system(enemy::on_enemy_spawned)
+    .add_system(enemy::enemy_ai_system)
+    .add_system(spawner::spawner_activation_system.before(spawner::spawner_system))
+    .add_system(spawner::spawner_system)
+    .add_system(spawner::barrier_system.after(spawner::spawner_system))
+    .add_system(door::on_door_spawned)
+    .add_system(door::on_key_spawned)
+    .add_system(door::restore_inventory_system)
+    .add_system(door::key_pickup_system)
+    .add_system(door::door_interaction_system.after(interaction::interaction_target_system))
+    .init_resource::<interaction::InteractionTarget>()
+    .add_system(interaction::interaction_target_system)
+    .init_resource::<dialogue::Conversation>()
+    .insert_resource(dialogue::Dialogues::load())
+    .add_system(dialogue::dialogue_input_system)
+    .add_system(
+        npc::npc_interaction_system
+            .after(dialogue::dialogue_input_system)
+            .after(interaction::interaction_target_system),
+    )
+    .add_system(chest::on_chest_spawned)
+    .add_system(chest::chest_interaction_system.after(interaction::interaction_target_system))
+    .add_system(trigger::on_trigger_spawned)
+    .add_system(trigger::trigger_system)
+    .insert_resource(quest::QuestDefinitions::load())
+    .add_system(quest::start_quests_system)
+    .add_system(quest::gameplay_quest_events_system)
+    .add_system(quest::level_quest_events_system)
+    .add_system(
+        quest::quest_progress_system
+            .after(quest::start_quests_system)
+            .after(quest::gameplay_quest_events_system),
+    )
     .add_system_to_stage(CoreStage::PostUpdate, player::Attack_Collider_Handler)
-    .add_system(world::spawn_fence_collision);
+    .add_system_to_stage(CoreStage::PostUpdate, projectile::projectile_hit_system)
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        attack::apply_damage_system
+            .after(player::Attack_Collider_Handler)
+            .after(projectile::projectile_hit_system),
+    )
+    .insert_resource(world::IntGridColliders::load())
+    .add_system(world::spawn_int_grid_collision)
+    .add_system(world::edit_int_grid_system.after(world::spawn_int_grid_collision))
+    .add_system(world::break_tiles_system.before(world::edit_int_grid_system))
+    .add_system(world::remove_edited_cells_system)
+    .insert_resource(tile_animation::TileAnimationManifest::load())
+    .init_resource::<tile_animation::TileAnimationClock>()
+    .add_startup_system(tile_animation::setup_tile_animations)
+    .add_system(tile_animation::sync_animated_tiles_system.after(world::edit_int_grid_system))
+    .add_system(tile_animation::animate_tiles_system)
+    .add_system(world::tile_zone_system.after(player::player_movement_system))
+    .insert_resource(y_sort::YSortConfig::load())
+    .add_system(y_sort::attach_y_sort_system)
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        y_sort::y_sort_system.before(TransformSystem::TransformPropagate),
+    )
+    .add_system(particles::attach_footstep_dust_system)
+    .add_system(particles::footstep_dust_system)
+    .add_system(particles::emit_particles_system)
+    .add_system(particles::update_particles_system)
+    .add_system(particles::combat_particles_system)
+    .add_system(particles::tile_debris_particles_system)
+    .add_system(audio::play_sound_system)
+    .add_system(audio::level_music_system)
+    .add_system(audio::music_volume_system)
+    .add_system(audio::combat_sounds_system)
+    .add_system(audio::footstep_sound_system)
+    .add_system(audio::level_up_sound_system)
+    .add_system(audio::quest_complete_sound_system)
+    .add_system(settings::save_settings_system)
+    .insert_resource(save::SaveData::load())
+    .add_system(save::save_game_system)
+    .add_system(settings::apply_window_settings_system)
+    .add_system(ui::button_color_system)
+    .add_system(hud::hud_system)
+    .add_system(hud::level_up_text_system)
+    .add_system(dialogue_box::dialogue_box_system)
+    .add_system(dialogue_box::talk_prompt_system)
+    .add_system(quest_log::toggle_quest_log_system)
+    .add_system(quest_log::quest_log_system)
+    .add_system(quest_log::quest_complete_text_system)
+    .insert_resource(lighting::LightingConfig::load())
+    .init_resource::<lighting::DayNightClock>()
+    .add_system(lighting::lighting_fallback_system)
+    .add_system(lighting::day_night_system)
+    .add_system(lighting::attach_player_light_system)
+    .add_system(lighting::lighting_overlay_system)
+    .add_system(
+        lighting::lighting_system
+            .after(lighting::day_night_system)
+            .after(lighting::lighting_overlay_system),
+    )
+    .init_resource::<visibility::FieldOfView>()
+    .add_system(visibility::field_of_view_system)
+    .add_system(visibility::hide_unseen_enemies_system.after(visibility::field_of_view_system))
+    .add_system(visibility::fog_of_war_system.after(minimap::explore_map_system))
+    .init_resource::<minimap::MapExploration>()
+    .init_resource::<minimap::MapView>()
+    .add_system(minimap::explore_map_system.after(visibility::field_of_view_system))
+    .add_system(minimap::minimap_image_system.after(minimap::explore_map_system))
+    .add_system(minimap::toggle_map_system)
+    .add_system(
+        minimap::minimap_layout_system
+            .after(minimap::explore_map_system)
+            .after(minimap::toggle_map_system),
+    )
+    .add_system(minimap::minimap_icons_system.after(minimap::explore_map_system))
+    .add_system(
+        settings_menu::toggle_settings_menu_system.before(settings_menu::rebind_key_system),
+    )
+    .add_system(settings_menu::settings_button_system)
+    .add_system(settings_menu::rebind_key_system)
+    .add_system(settings_menu::update_settings_text_system);
+
+    // Debug overlays, toggled at runtime with F1-F4 (see game_core/debug.rs)
+    #[cfg(feature = "debug")]
+    {
+        app.add_plugin(RapierDebugRenderPlugin::default())
+            .add_plugin(bevy_inspector_egui::bevy_egui::EguiPlugin)
+            .add_plugin(bevy_inspector_egui::DefaultInspectorConfigPlugin)
+            .add_plugin(bevy::diagnostic::FrameTimeDiagnosticsPlugin)
+            .add_plugin(bevy::diagnostic::EntityCountDiagnosticsPlugin)
+            .init_resource::<debug::DebugOverlays>()
+            .add_startup_system(debug::setup_debug_stats)
+            .add_system(debug::toggle_debug_overlays_system)
+            .add_system(debug::world_inspector_system)
+            .add_system(debug::debug_stats_system)
+            .add_system(debug::grid_overlay_system);
+    }
 
     app.run();
 }
@@ -77,7 +282,7 @@ fn setup_system(
     asset_server: Res<AssetServer>,
     meshes: Res<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    audio: Res<Audio>,
+    mut sound_events: EventWriter<audio::SoundEvent>,
 ) {
     // Spawn the camera
     commands.spawn(Camera2dBundle {
@@ -94,6 +299,5 @@ fn setup_system(
         ..Default::default()
     });
 
-    let startup_sound = asset_server.load("audio/sound_effects/pickup-coin.ogg");
-    audio.play(startup_sound);
+    sound_events.send(audio::SoundEvent(audio::Sound::Pickup));
 }